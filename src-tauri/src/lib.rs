@@ -2,15 +2,172 @@
 
 mod services;
 
-use services::db::{DatabaseService, Photo, Collection};
-use services::fs::{FileSystemService, ImageFile};
-use services::exif::{EXIFService, EXIFData};
-use services::image::{ImageService, ImageDimensions, ThumbnailResult};
-use std::sync::Mutex;
-use tauri::{State, Manager};
+use services::db::{DatabasePool, DatabaseService, Photo, Collection, BatchFailure, BatchReport, MetadataImport, EditAction, MetadataOp, DuplicateGroup, DuplicateResolution, SimilarPhoto, ColorMatch, Stack, SmartRule, PhotoLocation, PhotoRelocation, FolderNode, LibraryRoot, RootStatus, MaintenanceOptions, MaintenanceReport, LibraryStats, StatsScope};
+use services::db::{
+    BackupInfo, BackupPolicy, DATABASE_FILE, RecoveryAction, StartupState, backup_due, check_integrity, create_backup,
+    is_corruption, latest_backup, library_files, prune_backups, set_aside, verify_catalog,
+};
+use services::fs::{
+    FileSystemService, FileJournal, FileOperationResult, FolderTemplate, ImageFile, IngestFailure, IngestOptions,
+    IngestReport, IngestSkip, IngestedPhoto, LocateReport, AmbiguousMatch, RelocationReport, RenameOptions,
+    RenamePreview, RenameTemplate, ScanOptions, ScanProgress, ScanResult, TemplateContext, companion_destination, copy_verified,
+    find_by_name_and_size, name_and_size_key, parse_capture_date, rebase_path, spread_sample, unique_destination,
+    verify_file, VolumeInfo, mounted_volumes, volume_containing,
+};
+use services::error::{AppError, ResultExt};
+use services::settings::{AppSettings, RecentLibrary};
+use services::exif::{EXIFService, EXIFData, XmpLabels};
+use services::image::{
+    ImageService, ImageDimensions, ThumbnailResult, PerceptualHash, SimilarityIndex, ColorSignature, PaletteColor,
+    histogram_similarity, palette_similarity, parse_hex_color,
+};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::Duration;
+use tauri::{Emitter, State, Manager};
 
+// An open catalog and what belongs to it. Commands take their own `Arc` of
+// it, so one still running when the user switches libraries finishes against
+// the library it started with.
+struct Library {
+    dir: PathBuf,
+    pool: Arc<DatabasePool>,
+    thumbnail_dir: PathBuf,
+    // Built lazily from the stored perceptual hashes, dropped when photos are removed
+    similarity_index: Mutex<Option<SimilarityIndex>>,
+}
+
+impl Library {
+    // Runs `f` on a blocking thread with a reader connection; other reads and
+    // a write can run at the same time
+    async fn read<T, F>(self: Arc<Self>, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Library, &DatabaseService) -> Result<T, AppError> + Send + 'static,
+    {
+        run_blocking(move || {
+            let db = self.pool.reader()?;
+            f(&self, &db)
+        }).await
+    }
+    
+    // Runs `f` on a blocking thread with the writer connection, after any
+    // write already in progress
+    async fn write<T, F>(self: Arc<Self>, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Library, &DatabaseService) -> Result<T, AppError> + Send + 'static,
+    {
+        run_blocking(move || {
+            let db = self.pool.writer()?;
+            f(&self, &db)
+        }).await
+    }
+    
+    // Runs `f` on a blocking thread without a connection, for file-heavy work
+    // that takes a reader or the writer only around its own queries and changes
+    async fn run<T, F>(self: Arc<Self>, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Library) -> Result<T, AppError> + Send + 'static,
+    {
+        run_blocking(move || {
+            // Connections are refused from then on; fail before touching files
+            if self.pool.is_closed() {
+                return Err(AppError::DatabaseNotInitialized);
+            }
+            f(&self)
+        }).await
+    }
+}
+
+// Shared with the worker threads commands run on, hence the `Arc`s
+#[derive(Clone, Default)]
 struct AppState {
-    db: Mutex<Option<DatabaseService>>,
+    library: Arc<RwLock<Option<Arc<Library>>>>,
+    startup: Arc<Mutex<StartupState>>,
+    // Held while a library is opened or closed so switches cannot interleave
+    switching: Arc<Mutex<()>>,
+}
+
+impl AppState {
+    fn library(&self) -> Result<Arc<Library>, AppError> {
+        self.library.read().unwrap().clone().ok_or(AppError::DatabaseNotInitialized)
+    }
+    
+    async fn read<T, F>(&self, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&DatabaseService) -> Result<T, AppError> + Send + 'static,
+    {
+        self.library()?.read(move |_, db| f(db)).await
+    }
+    
+    async fn write<T, F>(&self, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&DatabaseService) -> Result<T, AppError> + Send + 'static,
+    {
+        self.library()?.write(move |_, db| f(db)).await
+    }
+}
+
+// Keeps slow file and database work off the thread that serves the UI
+async fn run_blocking<T, F>(f: F) -> Result<T, AppError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, AppError> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .context("Background task failed")?
+}
+
+// Hashes photos that were imported without a perceptual hash, then loads all
+// hashes into the in-memory index if it has not been built yet. The hashing
+// runs without a connection and the writer is only taken to store them; the
+// index is locked while it is built so `catalog_photo` cannot add a photo twice.
+fn ensure_similarity_index(library: &Library) -> Result<MutexGuard<'_, Option<SimilarityIndex>>, AppError> {
+    if library.similarity_index.lock().unwrap().is_none() {
+        let missing = library.pool.reader()?.get_photos_without_perceptual_hash()
+            .context("Failed to get photos")?;
+        let hashes: Vec<(i64, Result<PerceptualHash, AppError>)> = missing
+            .par_iter()
+            .map(|(id, thumbnail_path)| (*id, ImageService::compute_perceptual_hash(thumbnail_path)))
+            .collect();
+        
+        let mut computed = Vec::new();
+        for (id, hash) in hashes {
+            match hash {
+                Ok(hash) => computed.push((id, hash)),
+                Err(e) => log::warn!("Skipping photo {} in similarity index: {}", id, e),
+            }
+        }
+        
+        if !computed.is_empty() {
+            let db = library.pool.writer()?;
+            for (id, hash) in computed {
+                db.set_perceptual_hash(id, hash.dhash, hash.phash)
+                    .context("Failed to store perceptual hash")?;
+            }
+        }
+    }
+    
+    let mut index = library.similarity_index.lock().unwrap();
+    if index.is_none() {
+        let mut built = SimilarityIndex::new();
+        for (id, dhash, phash) in library.pool.reader()?.get_perceptual_hashes()
+            .context("Failed to get perceptual hashes")?
+        {
+            built.insert(id, PerceptualHash { dhash, phash });
+        }
+        
+        log::info!("Built similarity index with {} photos", built.len());
+        *index = Some(built);
+    }
+    Ok(index)
 }
 
 #[tauri::command]
@@ -18,187 +175,2398 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
-// Database commands
-#[tauri::command]
-fn init_database(app_handle: tauri::AppHandle, state: State<AppState>) -> Result<String, String> {
-    let app_data_dir = app_handle
+fn settings_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, AppError> {
+    Ok(app_handle
+        .path()
+        .app_config_dir()
+        .context("Failed to get config dir")?
+        .join("photo-manager")
+        .join("settings.json"))
+}
+
+fn update_settings(app_handle: &tauri::AppHandle, f: impl FnOnce(&mut AppSettings)) -> Result<(), AppError> {
+    let path = settings_path(app_handle)?;
+    let mut settings = AppSettings::load(&path)?;
+    f(&mut settings);
+    settings.save(&path)
+}
+
+fn default_library_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, AppError> {
+    Ok(app_handle
         .path()
         .app_data_dir()
-        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+        .context("Failed to get app data dir")?
+        .join("photo-manager"))
+}
+
+// The library to open at startup
+fn configured_library_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, AppError> {
+    match AppSettings::load(&settings_path(app_handle)?)?.library_path {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => default_library_dir(app_handle),
+    }
+}
+
+// The open library, or the one that last failed to open
+fn current_library_dir(app_handle: &tauri::AppHandle, state: &AppState) -> Result<PathBuf, AppError> {
+    if let Ok(library) = state.library() {
+        return Ok(library.dir.clone());
+    }
+    match &*state.startup.lock().unwrap() {
+        StartupState::Failed { library_path, .. } | StartupState::Corrupt { library_path, .. }
+            if !library_path.is_empty() => Ok(PathBuf::from(library_path)),
+        _ => configured_library_dir(app_handle),
+    }
+}
+
+// Each library caches thumbnails in a folder of its own, named after a hash of
+// its path, so no two catalogs share one. The default library keeps the folder
+// it always used.
+fn thumbnail_dir(app_handle: &tauri::AppHandle, library_dir: &Path) -> Result<PathBuf, AppError> {
+    let cache_dir = app_handle
+        .path()
+        .app_cache_dir()
+        .context("Failed to get cache dir")?
+        .join("photo-manager");
+    if library_dir == default_library_dir(app_handle)? {
+        return Ok(cache_dir.join("thumbnails"));
+    }
     
-    let db_path = app_data_dir.join("photo-manager").join("photos.db");
+    let key = ImageService::hash_file_path(&library_dir.to_string_lossy());
+    Ok(cache_dir.join("libraries").join(&key[..16]).join("thumbnails"))
+}
+
+// Absolute, with links resolved, so a library gets one entry in the recent
+// list and one thumbnail folder however it was reached
+fn canonical_library_dir(path: &str) -> Result<PathBuf, AppError> {
+    std::fs::canonicalize(path)
+        .map_err(|e| AppError::io(path, e).context("Failed to open library folder"))
+}
+
+fn corrupt_state(library_dir: &Path, problems: Vec<String>) -> StartupState {
+    StartupState::Corrupt {
+        library_path: library_dir.to_string_lossy().to_string(),
+        problems,
+        backup: latest_backup(library_dir),
+    }
+}
+
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+// Backs up the library and rotates its backups per the configured policy.
+// With `only_if_due` it does nothing until the next scheduled backup is due.
+// Call with `switching` held, so a restore cannot replace the catalog or
+// prune the backup it restores meanwhile.
+fn back_up_library(app_handle: &tauri::AppHandle, library: &Library, only_if_due: bool) -> Result<Option<BackupInfo>, AppError> {
+    let policy = AppSettings::load(&settings_path(app_handle)?)?.backup_policy;
+    if only_if_due && !backup_due(&library.dir, &policy) {
+        return Ok(None);
+    }
     
-    let db = DatabaseService::new(db_path)
-        .map_err(|e| format!("Failed to initialize database: {}", e))?;
+    let backup = create_backup(&*library.pool.reader()?, &library.dir)?;
+    prune_backups(&library.dir, &policy)?;
+    Ok(Some(backup))
+}
+
+// Takes the open library out of use and closes its catalog once the queries
+// and writes running on it are done. Commands still holding the library get
+// an error when they next ask for a connection, so nothing is written to the
+// file after this returns. Call with `switching` held.
+fn close_catalog(state: &AppState) -> Option<Arc<Library>> {
+    let library = state.library.write().unwrap().take()?;
+    library.pool.close();
+    log::info!("Closed library {:?}", library.dir);
+    Some(library)
+}
+
+// Closing a library backs it up, whether it is closed, switched from, or
+// open when the app exits. The backup is taken just before the catalog closes.
+fn close_and_back_up(app_handle: &tauri::AppHandle, state: &AppState) {
+    if let Ok(library) = state.library() {
+        if let Err(e) = back_up_library(app_handle, &library, false) {
+            log::warn!("Failed to back up library on close: {}", e);
+        }
+    }
+    close_catalog(state);
+}
+
+// Checks and opens (or creates) the catalog in `library_dir` and makes it the
+// one commands use. Failures are reported in the returned state. Call with
+// `switching` held and no library open.
+fn open_catalog(state: &AppState, library_dir: &Path, thumbnail_dir: PathBuf, xmp_sync: bool) -> StartupState {
+    let library_path = library_dir.to_string_lossy().to_string();
+    let db_path = library_dir.join(DATABASE_FILE);
     
-    let mut state_db = state.db.lock().unwrap();
-    *state_db = Some(db);
+    if db_path.exists() {
+        match check_integrity(&db_path, false) {
+            Ok(problems) if problems.is_empty() => {}
+            Ok(problems) => return corrupt_state(library_dir, problems),
+            Err(e) if is_corruption(&e) => return corrupt_state(library_dir, vec![e.to_string()]),
+            Err(e) => {
+                let error = AppError::from(e).context("Failed to check database");
+                return StartupState::Failed { library_path, error };
+            }
+        }
+    }
     
-    Ok("Database initialized".to_string())
+    let pool = match DatabasePool::open(db_path) {
+        Ok(pool) => pool,
+        Err(e) if is_corruption(&e) => return corrupt_state(library_dir, vec![e.to_string()]),
+        Err(e) => {
+            let error = AppError::from(e).context("Failed to open database");
+            return StartupState::Failed { library_path, error };
+        }
+    };
+    
+    if let Err(e) = check_library_roots(&pool, xmp_sync) {
+        log::warn!("Failed to check library roots: {}", e);
+    }
+    
+    *state.library.write().unwrap() = Some(Arc::new(Library {
+        dir: library_dir.to_path_buf(),
+        pool,
+        thumbnail_dir,
+        similarity_index: Mutex::new(None),
+    }));
+    StartupState::Ready { library_path }
+}
+
+// Makes `startup` what `get_startup_state` returns and tells the frontend
+fn publish_startup(app_handle: &tauri::AppHandle, state: &AppState, startup: StartupState) {
+    *state.startup.lock().unwrap() = startup.clone();
+    if let Err(e) = app_handle.emit("startup-state", startup) {
+        log::warn!("Failed to emit startup state: {}", e);
+    }
 }
 
+// Switches to the library in `library_dir` off the main thread and publishes
+// the outcome. Once it is open it becomes the library to open at startup.
+async fn load_library(
+    app_handle: &tauri::AppHandle,
+    state: &AppState,
+    library_dir: PathBuf,
+    name: Option<String>,
+) -> StartupState {
+    *state.startup.lock().unwrap() = StartupState::Opening;
+    
+    let library_path = library_dir.to_string_lossy().to_string();
+    let worker_state = state.clone();
+    let worker_handle = app_handle.clone();
+    let startup = run_blocking(move || {
+        let _switching = worker_state.switching.lock().unwrap();
+        let thumbnail_dir = thumbnail_dir(&worker_handle, &library_dir)?;
+        let xmp_sync = AppSettings::load(&settings_path(&worker_handle)?)?.xmp_sync;
+        close_and_back_up(&worker_handle, &worker_state);
+        let startup = open_catalog(&worker_state, &library_dir, thumbnail_dir, xmp_sync);
+        if let StartupState::Ready { library_path } = &startup {
+            let remembered = update_settings(&worker_handle, |settings| settings.remember_library(library_path, name));
+            if let Err(e) = remembered {
+                log::warn!("Failed to remember library: {}", e);
+            }
+        }
+        Ok(startup)
+    })
+    .await
+    .unwrap_or_else(|error| StartupState::Failed { library_path, error });
+    
+    match &startup {
+        StartupState::Ready { library_path } => log::info!("Opened library {}", library_path),
+        other => log::error!("Library not opened: {:?}", other),
+    }
+    publish_startup(app_handle, state, startup.clone());
+    startup
+}
+
+// Library commands
+#[tauri::command]
+fn get_startup_state(state: State<AppState>) -> StartupState {
+    state.startup.lock().unwrap().clone()
+}
+
+#[tauri::command]
+fn get_recent_libraries(app_handle: tauri::AppHandle) -> Result<Vec<RecentLibrary>, AppError> {
+    Ok(AppSettings::load(&settings_path(&app_handle)?)?.recent_libraries)
+}
+
+// Starts an empty catalog in `path` and switches to it
+#[tauri::command]
+async fn create_library(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    name: Option<String>,
+) -> Result<StartupState, AppError> {
+    let db_path = Path::new(&path).join(DATABASE_FILE);
+    if db_path.exists() {
+        return Err(AppError::AlreadyExists { path: db_path.to_string_lossy().to_string() });
+    }
+    std::fs::create_dir_all(&path)
+        .map_err(|e| AppError::io(&path, e).context("Failed to create library folder"))?;
+    let library_dir = canonical_library_dir(&path)?;
+    
+    Ok(load_library(&app_handle, &state, library_dir, name).await)
+}
+
+// Switches to the existing catalog in `path`, closing the open one
+#[tauri::command]
+async fn open_library(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<StartupState, AppError> {
+    let db_path = Path::new(&path).join(DATABASE_FILE);
+    if !db_path.is_file() {
+        return Err(AppError::FileNotFound { path: db_path.to_string_lossy().to_string() });
+    }
+    let library_dir = canonical_library_dir(&path)?;
+    
+    Ok(load_library(&app_handle, &state, library_dir, None).await)
+}
+
+// Closes the open library for this session; the next start opens it again
+#[tauri::command]
+async fn close_library(app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<StartupState, AppError> {
+    let worker_state = state.inner().clone();
+    let worker_handle = app_handle.clone();
+    run_blocking(move || {
+        let _switching = worker_state.switching.lock().unwrap();
+        close_and_back_up(&worker_handle, &worker_state);
+        Ok(())
+    }).await?;
+    
+    publish_startup(&app_handle, &state, StartupState::Closed);
+    Ok(StartupState::Closed)
+}
+
+// Drops a library from the recent list. With `delete_files` what the app
+// keeps in its folder (catalog, set-aside catalogs, backups) goes to the trash
+// and its thumbnails are deleted; photos and other files stay, and the folder
+// is only removed once empty. The open library cannot be removed.
+#[tauri::command]
+async fn remove_library(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    delete_files: bool,
+) -> Result<(), AppError> {
+    let worker_state = state.inner().clone();
+    run_blocking(move || {
+        // Held throughout so the library cannot be opened while it is removed
+        let _switching = worker_state.switching.lock().unwrap();
+        // A folder that is already gone can still be dropped from the list
+        let library_dir = canonical_library_dir(&path).unwrap_or_else(|_| PathBuf::from(&path));
+        if worker_state.library().is_ok_and(|library| library.dir == library_dir) {
+            return Err(AppError::Conflict { message: "Close the library before removing it".to_string() });
+        }
+        
+        let library_path = library_dir.to_string_lossy().to_string();
+        update_settings(&app_handle, |settings| {
+            settings.forget_library(&library_path);
+            if settings.library_path.as_deref() == Some(library_path.as_str()) {
+                settings.library_path = None;
+            }
+        })?;
+        if !delete_files {
+            return Ok(());
+        }
+        
+        if library_dir.is_dir() {
+            for file in library_files(&library_dir)? {
+                FileSystemService::move_to_trash(&file.to_string_lossy())?;
+            }
+            // Fails, and is meant to, while anything else is left in it
+            if std::fs::remove_dir(&library_dir).is_ok() {
+                log::info!("Removed empty library folder {:?}", library_dir);
+            }
+        }
+        
+        let thumbnail_dir = thumbnail_dir(&app_handle, &library_dir)?;
+        match std::fs::remove_dir_all(&thumbnail_dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(AppError::io(&thumbnail_dir, e).context("Failed to delete thumbnails"))
+            }
+            _ => Ok(()),
+        }
+    }).await
+}
+
+// Full integrity and foreign key check of the library's catalog; works when
+// it failed to open
 #[tauri::command]
-fn insert_photo(state: State<AppState>, photo: Photo) -> Result<i64, String> {
-    let state_db = state.db.lock().unwrap();
-    let db = state_db.as_ref().ok_or("Database not initialized")?;
+async fn check_database_integrity(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, AppError> {
+    let db_path = current_library_dir(&app_handle, &state)?.join(DATABASE_FILE);
+    
+    run_blocking(move || match verify_catalog(&db_path) {
+        Err(e) if is_corruption(&e) => Ok(vec![e.to_string()]),
+        result => result.context("Failed to check database"),
+    }).await
+}
+
+// Replaces a damaged catalog with its newest backup or an empty one and opens
+// it. The damaged file is kept, renamed, next to the new one.
+#[tauri::command]
+async fn recover_database(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    action: RecoveryAction,
+) -> Result<StartupState, AppError> {
+    let library_dir = current_library_dir(&app_handle, &state)?;
+    let flagged = matches!(*state.startup.lock().unwrap(), StartupState::Corrupt { .. });
+    let backup = match action {
+        RecoveryAction::RestoreBackup => {
+            Some(latest_backup(&library_dir).ok_or_else(|| AppError::not_found("No backup to restore"))?)
+        }
+        RecoveryAction::StartFresh => None,
+    };
     
-    db.insert_photo(&photo)
-        .map_err(|e| format!("Failed to insert photo: {}", e))
+    let worker_state = state.inner().clone();
+    let dir = library_dir.clone();
+    let replaced = run_blocking(move || {
+        let _switching = worker_state.switching.lock().unwrap();
+        // Also stops an import still running on the library from writing to
+        // the catalog once it is set aside
+        close_catalog(&worker_state);
+        
+        let db_path = dir.join(DATABASE_FILE);
+        let damaged = flagged || match check_integrity(&db_path, true) {
+            Ok(problems) => !problems.is_empty(),
+            Err(e) => is_corruption(&e),
+        };
+        if !damaged {
+            return Ok(false);
+        }
+        
+        match backup {
+            Some(backup) => services::db::restore_backup(&dir, Path::new(&backup.path), "corrupt")?,
+            None => {
+                set_aside(&dir, "corrupt")?;
+            }
+        }
+        Ok(true)
+    }).await;
+    
+    let startup = load_library(&app_handle, &state, library_dir, None).await;
+    if !replaced? {
+        return Err(AppError::Conflict { message: "The catalog passed the integrity check; nothing was replaced".to_string() });
+    }
+    Ok(startup)
+}
+
+// Backups of the open library, or of the one that failed to open, newest first
+#[tauri::command]
+async fn list_backups(app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<Vec<BackupInfo>, AppError> {
+    let library_dir = current_library_dir(&app_handle, &state)?;
+    
+    run_blocking(move || services::db::list_backups(&library_dir)).await
+}
+
+#[tauri::command]
+async fn back_up_now(app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<BackupInfo, AppError> {
+    let worker_state = state.inner().clone();
+    
+    run_blocking(move || {
+        let _switching = worker_state.switching.lock().unwrap();
+        let library = worker_state.library()?;
+        back_up_library(&app_handle, &library, false)?
+            .ok_or_else(|| AppError::not_found("No backup was made"))
+    }).await
+}
+
+// Replaces the library's catalog with one of its backups and reopens it. The
+// catalog it replaces is kept, renamed, next to it.
+#[tauri::command]
+async fn restore_backup(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<StartupState, AppError> {
+    let library_dir = current_library_dir(&app_handle, &state)?;
+    
+    let worker_state = state.inner().clone();
+    let dir = library_dir.clone();
+    let restored = run_blocking(move || {
+        let _switching = worker_state.switching.lock().unwrap();
+        close_catalog(&worker_state);
+        services::db::restore_backup(&dir, Path::new(&path), "replaced")
+    }).await;
+    
+    let startup = load_library(&app_handle, &state, library_dir, None).await;
+    restored?;
+    Ok(startup)
+}
+
+#[tauri::command]
+fn get_backup_policy(app_handle: tauri::AppHandle) -> Result<BackupPolicy, AppError> {
+    Ok(AppSettings::load(&settings_path(&app_handle)?)?.backup_policy)
 }
 
 #[tauri::command]
-fn get_all_photos(state: State<AppState>) -> Result<Vec<Photo>, String> {
-    let state_db = state.db.lock().unwrap();
-    let db = state_db.as_ref().ok_or("Database not initialized")?;
+fn set_backup_policy(app_handle: tauri::AppHandle, policy: BackupPolicy) -> Result<(), AppError> {
+    update_settings(&app_handle, |settings| settings.backup_policy = policy)
+}
+
+#[tauri::command]
+fn get_xmp_sync(app_handle: tauri::AppHandle) -> Result<bool, AppError> {
+    Ok(AppSettings::load(&settings_path(&app_handle)?)?.xmp_sync)
+}
+
+#[tauri::command]
+fn set_xmp_sync(app_handle: tauri::AppHandle, enabled: bool) -> Result<(), AppError> {
+    update_settings(&app_handle, |settings| settings.xmp_sync = enabled)
+}
+
+// Database commands
+#[tauri::command]
+async fn insert_photo(state: State<'_, AppState>, photo: Photo) -> Result<i64, AppError> {
+    state.library()?.run(move |library| {
+        let entry = prepare_photo(photo)?;
+        catalog_photo(library, &*library.pool.writer()?, entry)
+    }).await
+}
+
+// A photo about to be cataloged, with everything derived from its files.
+// Gathered by `prepare_photo` before the writer is taken.
+struct CatalogEntry {
+    photo: Photo,
+    exif: Option<EXIFData>,
+    perceptual_hash: Option<PerceptualHash>,
+    color_signature: Option<ColorSignature>,
+}
+
+// Reads what the catalog keeps about a photo from its files: motion pairing,
+// content hash, XMP labels, EXIF and thumbnail search data. Only the content
+// hash is required; other failures are logged.
+fn prepare_photo(mut photo: Photo) -> Result<CatalogEntry, AppError> {
+    // Record Live Photo / Motion Photo pairing on import
+    if photo.motion_type.is_none() {
+        match FileSystemService::detect_motion(&photo.path) {
+            Ok(motion) => {
+                photo.motion_type = motion.motion_type;
+                photo.motion_video_path = motion.video_path;
+                photo.motion_video_offset = motion.video_offset.map(|o| o as i64);
+                photo.content_identifier = motion.content_identifier;
+            }
+            Err(e) => log::warn!("Failed to detect motion data for {}: {}", photo.path, e),
+        }
+    }
+    
+    if photo.content_hash.is_none() {
+        photo.content_hash = Some(FileSystemService::hash_file(&photo.path)?);
+    }
+    
+    // Pick up culling done in other tools; values set by the caller win
+    match EXIFService::read_xmp_labels(&photo.path) {
+        Ok(labels) => {
+            if photo.rating == 0 {
+                photo.rating = labels.rating.unwrap_or(0);
+            }
+            if labels.rejected && photo.flag == "none" {
+                photo.flag = "rejected".to_string();
+            }
+            if photo.color_label.is_none() {
+                photo.color_label = labels.color_label;
+            }
+        }
+        Err(e) => log::warn!("Failed to read XMP labels for {}: {}", photo.path, e),
+    }
+    
+    let exif = EXIFService::extract_exif(&photo.path)
+        .inspect_err(|e| log::warn!("Failed to extract EXIF for {}: {}", photo.path, e))
+        .ok();
     
-    db.get_all_photos()
-        .map_err(|e| format!("Failed to get photos: {}", e))
+    let (perceptual_hash, color_signature) = match &photo.thumbnail_path {
+        Some(thumbnail_path) => (
+            ImageService::compute_perceptual_hash(thumbnail_path)
+                .inspect_err(|e| log::warn!("Failed to compute perceptual hash for {}: {}", thumbnail_path, e))
+                .ok(),
+            ImageService::compute_color_signature(thumbnail_path)
+                .inspect_err(|e| log::warn!("Failed to compute color signature for {}: {}", thumbnail_path, e))
+                .ok(),
+        ),
+        None => (None, None),
+    };
+    
+    Ok(CatalogEntry { photo, exif, perceptual_hash, color_signature })
+}
+
+// Adds a prepared photo to the catalog; `db` is the writer
+fn catalog_photo(library: &Library, db: &DatabaseService, entry: CatalogEntry) -> Result<i64, AppError> {
+    let photo_id = db.insert_photo(&entry.photo)
+        .context("Failed to insert photo")?;
+    
+    if let Some(exif) = &entry.exif {
+        db.upsert_exif(photo_id, exif)
+            .context("Failed to store EXIF data")?;
+    }
+    
+    if let Some(hash) = entry.perceptual_hash {
+        // Locked around the store so an index being built cannot also load it
+        let mut index = library.similarity_index.lock().unwrap();
+        db.set_perceptual_hash(photo_id, hash.dhash, hash.phash)
+            .context("Failed to store perceptual hash")?;
+        if let Some(index) = index.as_mut() {
+            index.insert(photo_id, hash);
+        }
+    }
+    
+    if let Some(signature) = &entry.color_signature {
+        store_color_signature(db, photo_id, signature)?;
+    }
+    
+    Ok(photo_id)
+}
+
+fn store_color_signature(
+    db: &DatabaseService,
+    photo_id: i64,
+    signature: &ColorSignature,
+) -> Result<(), AppError> {
+    let palette = serde_json::to_string(&signature.palette)
+        .context("Failed to serialize palette")?;
+    
+    db.set_color_signature(photo_id, &signature.histogram, &palette)
+        .context("Failed to store color signature")
+}
+
+// Analyzes photos that were imported before color search existed, taking the
+// writer only to store the results
+fn backfill_color_signatures(library: &Library) -> Result<(), AppError> {
+    let missing = library.pool.reader()?.get_photos_without_color_signature()
+        .context("Failed to get photos")?;
+    let signatures: Vec<(i64, Result<ColorSignature, AppError>)> = missing
+        .par_iter()
+        .map(|(id, thumbnail_path)| (*id, ImageService::compute_color_signature(thumbnail_path)))
+        .collect();
+    
+    let mut computed = Vec::new();
+    for (id, signature) in signatures {
+        match signature {
+            Ok(signature) => computed.push((id, signature)),
+            Err(e) => log::warn!("Skipping photo {} in color search: {}", id, e),
+        }
+    }
+    
+    if !computed.is_empty() {
+        let db = library.pool.writer()?;
+        for (id, signature) in &computed {
+            store_color_signature(&db, *id, signature)?;
+        }
+    }
+    
+    Ok(())
+}
+
+// Reads and stores EXIF for photos imported before it was persisted, taking
+// the writer only to store it
+fn backfill_exif(library: &Library) -> Result<(), AppError> {
+    let missing = library.pool.reader()?.get_photos_without_exif()
+        .context("Failed to get photos")?;
+    let extracted: Vec<(i64, Result<EXIFData, AppError>)> = missing
+        .par_iter()
+        .map(|(id, path)| (*id, EXIFService::extract_exif(path)))
+        .collect();
+    
+    let mut computed = Vec::new();
+    for (id, exif) in extracted {
+        match exif {
+            Ok(exif) => computed.push((id, exif)),
+            Err(e) => log::warn!("Failed to extract EXIF for photo {}: {}", id, e),
+        }
+    }
+    
+    if !computed.is_empty() {
+        let db = library.pool.writer()?;
+        for (id, exif) in &computed {
+            db.upsert_exif(*id, exif)
+                .context("Failed to store EXIF data")?;
+        }
+    }
+    
+    Ok(())
+}
+
+fn top_color_matches(db: &DatabaseService, mut scored: Vec<(i64, f32)>, limit: usize) -> Vec<ColorMatch> {
+    scored.retain(|(_, score)| *score > 0.0);
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    
+    scored
+        .into_iter()
+        .take(limit)
+        .filter_map(|(id, score)| db.get_photo(id).ok().map(|photo| ColorMatch { photo, score }))
+        .collect()
+}
+
+#[tauri::command]
+async fn get_all_photos(state: State<'_, AppState>, stack_tops_only: Option<bool>) -> Result<Vec<Photo>, AppError> {
+    state.read(move |db| {
+        db.get_all_photos(stack_tops_only.unwrap_or(false))
+            .context("Failed to get photos")
+    }).await
 }
 
 #[tauri::command]
-fn update_metadata(
-    state: State<AppState>,
+#[allow(clippy::too_many_arguments)]
+async fn update_metadata(
+    state: State<'_, AppState>,
     photo_id: i64,
     rating: Option<i32>,
     is_favorite: Option<bool>,
     tags: Option<String>,
     description: Option<String>,
-) -> Result<(), String> {
-    let state_db = state.db.lock().unwrap();
-    let db = state_db.as_ref().ok_or("Database not initialized")?;
-    
-    db.update_metadata(photo_id, rating, is_favorite, tags, description)
-        .map_err(|e| format!("Failed to update metadata: {}", e))
+    flag: Option<String>,
+    color_label: Option<String>,
+) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.update_metadata(photo_id, rating, is_favorite, tags, description, flag, color_label)
+            .context("Failed to update metadata")
+    }).await
 }
 
 #[tauri::command]
-fn create_collection(state: State<AppState>, name: String) -> Result<i64, String> {
-    let state_db = state.db.lock().unwrap();
-    let db = state_db.as_ref().ok_or("Database not initialized")?;
-    
-    db.create_collection(&name)
-        .map_err(|e| format!("Failed to create collection: {}", e))
+async fn batch_update_metadata(
+    state: State<'_, AppState>,
+    photo_ids: Vec<i64>,
+    operations: Vec<MetadataOp>,
+) -> Result<BatchReport, AppError> {
+    state.write(move |db| {
+        db.batch_update_metadata(&photo_ids, &operations)
+            .context("Failed to update metadata")
+    }).await
 }
 
 #[tauri::command]
-fn get_all_collections(state: State<AppState>) -> Result<Vec<Collection>, String> {
-    let state_db = state.db.lock().unwrap();
-    let db = state_db.as_ref().ok_or("Database not initialized")?;
-    
-    db.get_all_collections()
-        .map_err(|e| format!("Failed to get collections: {}", e))
+async fn undo(state: State<'_, AppState>) -> Result<Option<EditAction>, AppError> {
+    state.library()?.write(move |library, db| {
+        let action = db.undo()
+            .context("Failed to undo")?;
+        // Undoing a merge brings photos back
+        *library.similarity_index.lock().unwrap() = None;
+        Ok(action)
+    }).await
+}
+
+#[tauri::command]
+async fn redo(state: State<'_, AppState>) -> Result<Option<EditAction>, AppError> {
+    state.library()?.write(move |library, db| {
+        let action = db.redo()
+            .context("Failed to redo")?;
+        *library.similarity_index.lock().unwrap() = None;
+        Ok(action)
+    }).await
 }
 
 #[tauri::command]
-fn add_photo_to_collection(
-    state: State<AppState>,
+async fn get_edit_history(state: State<'_, AppState>, limit: Option<i64>) -> Result<Vec<EditAction>, AppError> {
+    state.read(move |db| {
+        db.get_edit_history(limit.unwrap_or(50))
+            .context("Failed to get edit history")
+    }).await
+}
+
+#[tauri::command]
+async fn get_edit_history_retention(state: State<'_, AppState>) -> Result<i64, AppError> {
+    state.read(move |db| {
+        db.get_edit_history_retention()
+            .context("Failed to get edit history retention")
+    }).await
+}
+
+#[tauri::command]
+async fn set_edit_history_retention(state: State<'_, AppState>, days: i64) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.set_edit_history_retention(days)
+            .context("Failed to set edit history retention")
+    }).await
+}
+
+#[tauri::command]
+async fn create_collection(
+    state: State<'_, AppState>,
+    name: String,
+    parent_id: Option<i64>,
+) -> Result<i64, AppError> {
+    state.write(move |db| {
+        db.create_collection(&name, parent_id)
+    }).await
+}
+
+#[tauri::command]
+async fn get_collection(state: State<'_, AppState>, collection_id: i64) -> Result<Collection, AppError> {
+    state.read(move |db| {
+        db.get_collection(collection_id)
+            .context("Failed to get collection")
+    }).await
+}
+
+#[tauri::command]
+async fn rename_collection(state: State<'_, AppState>, collection_id: i64, name: String) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.rename_collection(collection_id, &name)
+    }).await
+}
+
+#[tauri::command]
+async fn set_collection_description(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    description: Option<String>,
+) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.set_collection_description(collection_id, description.as_deref())
+            .context("Failed to update collection description")
+    }).await
+}
+
+#[tauri::command]
+async fn set_collection_cover(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    photo_id: Option<i64>,
+) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.set_collection_cover(collection_id, photo_id)
+            .context("Failed to update collection cover")
+    }).await
+}
+
+#[tauri::command]
+async fn create_smart_collection(
+    state: State<'_, AppState>,
+    name: String,
+    rules: SmartRule,
+) -> Result<i64, AppError> {
+    state.write(move |db| {
+        db.create_smart_collection(&name, &rules)
+    }).await
+}
+
+#[tauri::command]
+async fn set_collection_rules(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    rules: Option<SmartRule>,
+) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.set_collection_rules(collection_id, rules.as_ref())
+            .context("Failed to update collection rules")
+    }).await
+}
+
+#[tauri::command]
+async fn get_all_collections(state: State<'_, AppState>) -> Result<Vec<Collection>, AppError> {
+    state.read(move |db| {
+        db.get_all_collections()
+            .context("Failed to get collections")
+    }).await
+}
+
+#[tauri::command]
+async fn move_collection(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    parent_id: Option<i64>,
+    position: Option<usize>,
+) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.move_collection(collection_id, parent_id, position)
+            .context("Failed to move collection")
+    }).await
+}
+
+#[tauri::command]
+async fn reorder_collections(
+    state: State<'_, AppState>,
+    parent_id: Option<i64>,
+    collection_ids: Vec<i64>,
+) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.reorder_collections(parent_id, &collection_ids)
+            .context("Failed to reorder collections")
+    }).await
+}
+
+#[tauri::command]
+async fn delete_collection(state: State<'_, AppState>, collection_id: i64, cascade: bool) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.delete_collection(collection_id, cascade)
+            .context("Failed to delete collection")
+    }).await
+}
+
+#[tauri::command]
+async fn add_photo_to_collection(
+    state: State<'_, AppState>,
     photo_id: i64,
     collection_id: i64,
-) -> Result<(), String> {
-    let state_db = state.db.lock().unwrap();
-    let db = state_db.as_ref().ok_or("Database not initialized")?;
-    
-    db.add_photo_to_collection(photo_id, collection_id)
-        .map_err(|e| format!("Failed to add photo to collection: {}", e))
+) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.add_photo_to_collection(photo_id, collection_id)
+            .context("Failed to add photo to collection")
+    }).await
 }
 
 #[tauri::command]
-fn remove_photo_from_collection(
-    state: State<AppState>,
+async fn remove_photo_from_collection(
+    state: State<'_, AppState>,
     photo_id: i64,
     collection_id: i64,
-) -> Result<(), String> {
-    let state_db = state.db.lock().unwrap();
-    let db = state_db.as_ref().ok_or("Database not initialized")?;
+) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.remove_photo_from_collection(photo_id, collection_id)
+            .context("Failed to remove photo from collection")
+    }).await
+}
+
+#[tauri::command]
+async fn batch_add_to_collection(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    photo_ids: Vec<i64>,
+) -> Result<BatchReport, AppError> {
+    state.write(move |db| {
+        db.batch_add_to_collection(collection_id, &photo_ids)
+            .context("Failed to add photos to collection")
+    }).await
+}
+
+#[tauri::command]
+async fn batch_remove_from_collection(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    photo_ids: Vec<i64>,
+) -> Result<BatchReport, AppError> {
+    state.write(move |db| {
+        db.batch_remove_from_collection(collection_id, &photo_ids)
+            .context("Failed to remove photos from collection")
+    }).await
+}
+
+#[tauri::command]
+async fn get_photos_in_collection(state: State<'_, AppState>, collection_id: i64) -> Result<Vec<Photo>, AppError> {
+    state.read(move |db| {
+        db.get_photos_in_collection(collection_id)
+            .context("Failed to get photos in collection")
+    }).await
+}
+
+#[tauri::command]
+async fn reorder_collection_photos(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    photo_ids: Vec<i64>,
+) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.reorder_collection_photos(collection_id, &photo_ids)
+            .context("Failed to reorder collection photos")
+    }).await
+}
+
+#[tauri::command]
+async fn export_database_to_json(state: State<'_, AppState>) -> Result<String, AppError> {
+    state.read(move |db| {
+        db.export_to_json()
+            .context("Failed to export database")
+    }).await
+}
+
+#[tauri::command]
+async fn import_metadata_from_json(state: State<'_, AppState>, json: String) -> Result<MetadataImport, AppError> {
+    state.write(move |db| {
+        db.import_metadata_from_json(&json)
+            .context("Failed to import metadata")
+    }).await
+}
+
+// Writes rating, reject flag and color label of each photo to its XMP sidecar
+#[tauri::command]
+async fn write_xmp_sidecars(state: State<'_, AppState>, photo_ids: Vec<i64>) -> Result<BatchReport, AppError> {
+    state.read(move |db| {
+        let mut report = BatchReport::default();
+        for photo_id in photo_ids {
+            let result = db.get_photo(photo_id)
+                .context("Failed to get photo")
+                .for_photo(photo_id)
+                .and_then(write_photo_xmp);
+            
+            match result {
+                Ok(_) => report.succeeded.push(photo_id),
+                Err(e) => report.failed.push(BatchFailure { photo_id, error: e.to_string() }),
+            }
+        }
+        
+        Ok(report)
+    }).await
+}
+
+#[tauri::command]
+async fn find_duplicates(state: State<'_, AppState>) -> Result<Vec<DuplicateGroup>, AppError> {
+    state.library()?.run(move |library| {
+        // Photos imported before content hashing was added need a hash first;
+        // the files are hashed without a connection
+        let missing = library.pool.reader()?.get_photos_without_hash()
+            .context("Failed to get photos")?;
+        let hashes: Vec<(i64, Result<String, AppError>)> = missing
+            .par_iter()
+            .map(|(id, path)| (*id, FileSystemService::hash_file(path)))
+            .collect();
+        
+        let mut computed = Vec::new();
+        for (id, hash) in hashes {
+            match hash {
+                Ok(hash) => computed.push((id, hash)),
+                Err(e) => log::warn!("Skipping photo {} in duplicate scan: {}", id, e),
+            }
+        }
+        
+        if !computed.is_empty() {
+            let db = library.pool.writer()?;
+            for (id, hash) in &computed {
+                db.set_content_hash(*id, hash)
+                    .context("Failed to store content hash")?;
+            }
+        }
+        
+        library.pool.reader()?.find_duplicates()
+            .context("Failed to find duplicates")
+    }).await
+}
+
+#[tauri::command]
+async fn resolve_duplicates(
+    state: State<'_, AppState>,
+    keep_photo_id: i64,
+    duplicate_photo_ids: Vec<i64>,
+    trash_files: bool,
+) -> Result<DuplicateResolution, AppError> {
+    state.library()?.run(move |library| {
+        // The files are trashed once the writer is released
+        let (kept, removed) = {
+            let db = library.pool.writer()?;
+            let kept = db.get_photo(keep_photo_id)
+                .context("Failed to get photo")?;
+            // A stale or wrong list must not merge photos with other contents
+            for &photo_id in duplicate_photo_ids.iter().filter(|&&id| id != keep_photo_id) {
+                let photo = db.get_photo(photo_id)
+                    .context("Failed to get photo")
+                    .for_photo(photo_id)?;
+                if kept.content_hash.is_none() || photo.content_hash != kept.content_hash {
+                    return Err(AppError::invalid(format!(
+                        "Photo {} is not a duplicate of photo {}",
+                        photo_id, keep_photo_id
+                    ))
+                    .for_photo(photo_id));
+                }
+            }
+            
+            let removed = db.merge_duplicates(keep_photo_id, &duplicate_photo_ids)
+                .context("Failed to merge duplicates")?;
+            *library.similarity_index.lock().unwrap() = None;
+            (kept, removed)
+        };
+        
+        let mut resolution = DuplicateResolution {
+            kept_photo_id: keep_photo_id,
+            removed_photo_ids: removed.iter().map(|p| p.id).collect(),
+            ..Default::default()
+        };
+        
+        if trash_files {
+            for photo in &removed {
+                let mut paths = vec![photo.path.clone()];
+                // Take the Live Photo clip along unless the kept photo shares it
+                if photo.motion_type.as_deref() == Some("live_photo") && photo.motion_video_path != kept.motion_video_path {
+                    paths.extend(photo.motion_video_path.clone());
+                }
+                
+                for path in paths {
+                    if path == kept.path {
+                        continue;
+                    }
+                    match FileSystemService::move_to_trash(&path) {
+                        Ok(()) => resolution.trashed_paths.push(path),
+                        Err(e) => {
+                            log::warn!("{}", e);
+                            resolution.failed_paths.push(path);
+                        }
+                    }
+                }
+            }
+        }
+        
+        Ok(resolution)
+    }).await
+}
+
+// Moves (or copies) a photo's file, its sidecars and Live Photo clip, and its
+// cached thumbnail to `new_path`, recording each step in `journal`. Needs no
+// connection; `record_transfer` points the catalog at the result.
+fn transfer_files(
+    journal: &mut FileJournal,
+    cache_dir: &Path,
+    photo: &Photo,
+    new_path: &Path,
+    copy: bool,
+) -> Result<PhotoLocation, AppError> {
+    let old_path = Path::new(&photo.path);
+    let new_path_str = new_path.to_string_lossy().to_string();
+    let filename = new_path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| AppError::invalid(format!("Invalid file name: {}", new_path.display())))?;
+    let mut transfer = |from: &Path, to: &Path| {
+        if copy { journal.copy_file(from, to) } else { journal.move_file(from, to) }
+    };
+    
+    transfer(old_path, new_path)?;
     
-    db.remove_photo_from_collection(photo_id, collection_id)
-        .map_err(|e| format!("Failed to remove photo from collection: {}", e))
+    let live_photo_video = photo.motion_video_path.as_deref()
+        .filter(|_| photo.motion_type.as_deref() == Some("live_photo"));
+    let mut motion_video_path = photo.motion_video_path.clone();
+    for companion in FileSystemService::companion_files(&photo.path, live_photo_video) {
+        let dest = companion_destination(&companion, old_path, new_path)
+            .ok_or_else(|| AppError::invalid(format!("Invalid file name: {}", companion.display())))?;
+        transfer(&companion, &dest)?;
+        if live_photo_video == Some(companion.to_string_lossy().as_ref()) {
+            motion_video_path = Some(dest.to_string_lossy().to_string());
+        }
+    }
+    // A Motion Photo's clip is embedded in the file itself
+    if photo.motion_type.as_deref() == Some("motion_photo") {
+        motion_video_path = Some(new_path_str.clone());
+    }
+    
+    let thumbnail_path = match photo.thumbnail_path.as_deref().map(Path::new) {
+        Some(thumbnail) if thumbnail.is_file() => {
+            let dest = ImageService::thumbnail_path(&new_path_str, cache_dir);
+            // Whatever is cached for the new path belongs to a file that is gone
+            journal.remove_file(&dest)?;
+            if copy { journal.copy_file(thumbnail, &dest)? } else { journal.move_file(thumbnail, &dest)? }
+            Some(dest.to_string_lossy().to_string())
+        }
+        _ => None,
+    };
+    
+    Ok(PhotoLocation { photo_id: photo.id, path: new_path_str, filename, motion_video_path, thumbnail_path })
 }
 
+// Points the catalog at a photo moved by `transfer_files`, or catalogs the
+// copy. Returns the id of the photo at the new path.
+fn record_transfer(db: &DatabaseService, moved: &PhotoLocation, copy: bool) -> Result<i64, AppError> {
+    let (motion_video_path, thumbnail_path) = (moved.motion_video_path.as_deref(), moved.thumbnail_path.as_deref());
+    if copy {
+        db.copy_photo_record(moved.photo_id, &moved.path, &moved.filename, motion_video_path, thumbnail_path)
+            .context("Failed to catalog copy")
+    } else {
+        db.update_photo_location(moved.photo_id, &moved.path, &moved.filename, motion_video_path, thumbnail_path)
+            .context("Failed to update photo location")?;
+        Ok(moved.photo_id)
+    }
+}
+
+// Transfers a photo's files, then takes the writer to record it, undoing the
+// file changes if any step fails
+fn transfer_photo_or_rollback(
+    library: &Library,
+    photo_id: i64,
+    destination: impl FnOnce(&Photo) -> Result<PathBuf, AppError>,
+    copy: bool,
+) -> FileOperationResult {
+    let mut journal = FileJournal::new();
+    let result = library.pool.reader()
+        .and_then(|db| db.get_photo(photo_id).context("Failed to get photo"))
+        .for_photo(photo_id)
+        .and_then(|photo| {
+            let new_path = destination(&photo)?;
+            let moved = transfer_files(&mut journal, &library.thumbnail_dir, &photo, &new_path, copy)?;
+            let id = record_transfer(&*library.pool.writer()?, &moved, copy)?;
+            Ok((id, new_path))
+        });
+    
+    match result {
+        Ok((id, new_path)) => {
+            journal.commit();
+            FileOperationResult {
+                photo_id,
+                path: Some(new_path.to_string_lossy().to_string()),
+                new_photo_id: if copy { Some(id) } else { None },
+                error: None,
+            }
+        }
+        Err(e) => {
+            log::warn!("File operation on photo {} failed: {}", photo_id, e);
+            journal.rollback();
+            FileOperationResult { photo_id, error: Some(e.to_string()), ..Default::default() }
+        }
+    }
+}
+
+fn path_in_folder(photo: &Photo, folder: &str) -> Result<PathBuf, AppError> {
+    Path::new(&photo.path)
+        .file_name()
+        .map(|name| Path::new(folder).join(name))
+        .ok_or_else(|| AppError::invalid(format!("Invalid file name: {}", photo.path)))
+}
+
+#[tauri::command]
+async fn move_photos(
+    state: State<'_, AppState>,
+    photo_ids: Vec<i64>,
+    dest_folder: String,
+) -> Result<Vec<FileOperationResult>, AppError> {
+    state.library()?.run(move |library| {
+        Ok(photo_ids
+            .into_iter()
+            .map(|id| transfer_photo_or_rollback(library, id, |photo| path_in_folder(photo, &dest_folder), false))
+            .collect())
+    }).await
+}
+
+#[tauri::command]
+async fn copy_photos(
+    state: State<'_, AppState>,
+    photo_ids: Vec<i64>,
+    dest_folder: String,
+) -> Result<Vec<FileOperationResult>, AppError> {
+    state.library()?.run(move |library| {
+        let results: Vec<FileOperationResult> = photo_ids
+            .into_iter()
+            .map(|id| transfer_photo_or_rollback(library, id, |photo| path_in_folder(photo, &dest_folder), true))
+            .collect();
+        *library.similarity_index.lock().unwrap() = None;
+        
+        Ok(results)
+    }).await
+}
+
+// Renames a photo within its folder. Without an extension in `new_name` the
+// current one is kept.
 #[tauri::command]
-fn get_photos_in_collection(state: State<AppState>, collection_id: i64) -> Result<Vec<Photo>, String> {
-    let state_db = state.db.lock().unwrap();
-    let db = state_db.as_ref().ok_or("Database not initialized")?;
+async fn rename_photo(
+    state: State<'_, AppState>,
+    photo_id: i64,
+    new_name: String,
+) -> Result<FileOperationResult, AppError> {
+    state.library()?.run(move |library| {
+        if new_name.is_empty() || new_name.contains(['/', '\\']) {
+            return Err(AppError::invalid(format!("Invalid file name: {}", new_name)));
+        }
+        
+        Ok(transfer_photo_or_rollback(library, photo_id, |photo| {
+            let mut new_path = Path::new(&photo.path).with_file_name(&new_name);
+            if new_path.extension().is_none() {
+                if let Some(ext) = Path::new(&photo.path).extension() {
+                    new_path.set_extension(ext);
+                }
+            }
+            Ok(new_path)
+        }, false))
+    }).await
+}
+
+// Template values of one photo. User variables override the built-in ones.
+fn template_context(
+    db: &DatabaseService,
+    photo: &Photo,
+    options: &RenameOptions,
+    collection: Option<&str>,
+    sequence: u64,
+) -> Result<TemplateContext, AppError> {
+    let exif = db.get_exif(photo.id)
+        .context("Failed to get EXIF data")?
+        .unwrap_or_default();
+    
+    let mut values = file_template_values(&photo.path, &exif);
+    values.extend(
+        [
+            ("id", Some(photo.id.to_string())),
+            ("filename", Some(photo.filename.clone())),
+            ("rating", Some(photo.rating.to_string())),
+            ("flag", Some(photo.flag.clone())),
+            ("label", photo.color_label.clone()),
+            ("collection", collection.map(str::to_string)),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.to_string(), value?))),
+    );
+    values.extend(options.variables.clone());
+    
+    let date = exif.capture_date.as_deref()
+        .or(photo.capture_date.as_deref())
+        .and_then(parse_capture_date);
     
-    db.get_photos_in_collection(collection_id)
-        .map_err(|e| format!("Failed to get photos in collection: {}", e))
+    Ok(TemplateContext { date, sequence, values })
 }
 
+// Template values that only need the file and its EXIF data
+fn file_template_values(path: &str, exif: &EXIFData) -> HashMap<String, String> {
+    let path = Path::new(path);
+    
+    // Models usually repeat the make ("Canon EOS R5"), so only prefix it when they don't
+    let camera = match (&exif.camera_make, &exif.camera_model) {
+        (Some(make), Some(model)) if !model.starts_with(make.as_str()) => Some(format!("{} {}", make, model)),
+        (make, model) => model.clone().or_else(|| make.clone()),
+    };
+    
+    [
+        ("name", path.file_stem().map(|s| s.to_string_lossy().to_string())),
+        ("ext", path.extension().map(|s| s.to_string_lossy().to_string())),
+        ("make", exif.camera_make.clone()),
+        ("model", exif.camera_model.clone()),
+        ("camera", camera),
+        ("lens", exif.lens_model.clone()),
+        ("iso", exif.iso.map(|iso| iso.to_string())),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((key.to_string(), value?)))
+    .collect()
+}
+
+// Renders the new name of every photo, numbering them in the given order, and
+// reports what would stop the rename: render errors, names already taken on
+// disk or in the catalog, and names produced twice
+fn plan_rename(
+    db: &DatabaseService,
+    photo_ids: &[i64],
+    options: &RenameOptions,
+) -> Result<Vec<RenamePreview>, AppError> {
+    let template = RenameTemplate::parse(&options.template)?;
+    let collection = options.collection_id
+        .map(|id| db.get_collection(id))
+        .transpose()
+        .context("Failed to get collection")?
+        .map(|c| c.name);
+    
+    let mut plan = Vec::new();
+    let mut targets: HashMap<PathBuf, i64> = HashMap::new();
+    
+    for (index, &photo_id) in photo_ids.iter().enumerate() {
+        let photo = db.get_photo(photo_id)
+            .context("Failed to get photo")
+            .for_photo(photo_id)?;
+        let sequence = options.start.unwrap_or(1) + index as u64;
+        
+        let new_path = template_context(db, &photo, options, collection.as_deref(), sequence)
+            .and_then(|context| template.render(&context))
+            .map(|name| Path::new(&photo.path).with_file_name(name));
+        
+        let (new_path, conflict) = match new_path {
+            Err(e) => (None, Some(e.to_string())),
+            Ok(new_path) => {
+                let new_path_str = new_path.to_string_lossy().to_string();
+                let conflict = if new_path_str == photo.path {
+                    None
+                } else if let Some(other) = targets.get(&new_path) {
+                    Some(format!("Same name as photo {}", other))
+                } else if new_path.exists() {
+                    Some(format!("{} already exists", new_path_str))
+                } else if db.get_photo_id_by_path(&new_path_str)
+                    .context("Failed to check catalog")?
+                    .is_some()
+                {
+                    Some(format!("{} is already in the catalog", new_path_str))
+                } else {
+                    None
+                };
+                targets.entry(new_path).or_insert(photo_id);
+                (Some(new_path_str), conflict)
+            }
+        };
+        
+        plan.push(RenamePreview { photo_id, old_path: photo.path, new_path, conflict });
+    }
+    
+    Ok(plan)
+}
+
+#[tauri::command]
+async fn preview_rename(
+    state: State<'_, AppState>,
+    photo_ids: Vec<i64>,
+    options: RenameOptions,
+) -> Result<Vec<RenamePreview>, AppError> {
+    state.read(move |db| {
+        plan_rename(db, &photo_ids, &options)
+    }).await
+}
+
+// Renames all photos or none. Refuses to start while the plan has conflicts;
+// if a file operation fails midway, the files already renamed are moved back.
+// The catalog is updated in one transaction once all files are in place.
+#[tauri::command]
+async fn apply_rename(
+    state: State<'_, AppState>,
+    photo_ids: Vec<i64>,
+    options: RenameOptions,
+) -> Result<Vec<FileOperationResult>, AppError> {
+    state.library()?.run(move |library| {
+        let cache_dir = library.thumbnail_dir.as_path();
+        
+        let plan = plan_rename(&*library.pool.reader()?, &photo_ids, &options)?;
+        let conflicts = plan.iter().filter(|entry| entry.conflict.is_some()).count();
+        if conflicts > 0 {
+            return Err(AppError::Conflict {
+                message: format!("{} of {} names conflict; nothing was renamed", conflicts, plan.len()),
+            });
+        }
+        
+        // Every photo of the batch reports the error, or that it was rolled
+        // back because of the photo that failed
+        let rolled_back = |failed_photo_id: Option<i64>, e: AppError| -> Vec<FileOperationResult> {
+            log::warn!("Renaming failed, rolling back the batch: {}", e);
+            plan.iter()
+                .map(|other| FileOperationResult {
+                    photo_id: other.photo_id,
+                    error: Some(match failed_photo_id {
+                        Some(failed) if failed != other.photo_id => {
+                            format!("Rolled back because photo {} could not be renamed", failed)
+                        }
+                        _ => e.to_string(),
+                    }),
+                    ..Default::default()
+                })
+                .collect()
+        };
+        
+        // All files are renamed before the writer is taken to record them
+        let mut journal = FileJournal::new();
+        let mut renamed: Vec<PhotoLocation> = Vec::new();
+        for entry in &plan {
+            let Some(new_path) = entry.new_path.as_deref().filter(|p| *p != entry.old_path) else {
+                continue;
+            };
+            
+            let result = library.pool.reader()
+                .and_then(|db| db.get_photo(entry.photo_id).context("Failed to get photo"))
+                .and_then(|photo| transfer_files(&mut journal, cache_dir, &photo, Path::new(new_path), false));
+            match result {
+                Ok(location) => renamed.push(location),
+                Err(e) => {
+                    journal.rollback();
+                    return Ok(rolled_back(Some(entry.photo_id), e));
+                }
+            }
+        }
+        
+        // One transaction, so a failure leaves the catalog as it was to match
+        // the files moved back
+        let recorded = library.pool.writer()
+            .and_then(|db| db.update_photo_locations(&renamed).context("Failed to update photo locations"));
+        if let Err(e) = recorded {
+            journal.rollback();
+            return Ok(rolled_back(None, e));
+        }
+        journal.commit();
+        
+        Ok(plan
+            .into_iter()
+            .map(|entry| FileOperationResult {
+                photo_id: entry.photo_id,
+                path: entry.new_path,
+                ..Default::default()
+            })
+            .collect())
+    }).await
+}
+
+// Sends a photo and its companion files to the OS trash, then removes it and
+// its cached thumbnail from the catalog. The catalog entry is kept if the
+// photo itself cannot be trashed. The writer is only taken for the catalog
+// change.
+fn trash_photo(library: &Library, photo_id: i64) -> Result<(), AppError> {
+    let photo = library.pool.reader()?.get_photo(photo_id)
+        .context("Failed to get photo")?;
+    
+    let live_photo_video = photo.motion_video_path.as_deref()
+        .filter(|_| photo.motion_type.as_deref() == Some("live_photo"));
+    let companions = FileSystemService::companion_files(&photo.path, live_photo_video);
+    
+    FileSystemService::move_to_trash(&photo.path)?;
+    for companion in companions {
+        if let Err(e) = FileSystemService::move_to_trash(&companion.to_string_lossy()) {
+            log::warn!("{}", e);
+        }
+    }
+    
+    library.pool.writer()?.delete_photo(photo_id)
+        .context("Trashed but failed to remove from the catalog")?;
+    let _ = std::fs::remove_file(ImageService::thumbnail_path(&photo.path, &library.thumbnail_dir));
+    Ok(())
+}
+
+// Removes photos from the catalog. With `delete_files` their files go to the
+// OS trash; otherwise the files stay and the removal is one undoable action,
+// so thumbnails are kept for undo to bring back (maintenance clears them
+// otherwise).
+#[tauri::command]
+async fn remove_photos(
+    state: State<'_, AppState>,
+    photo_ids: Vec<i64>,
+    delete_files: bool,
+) -> Result<Vec<FileOperationResult>, AppError> {
+    state.library()?.run(move |library| {
+        let results = if delete_files {
+            photo_ids
+                .into_iter()
+                .map(|photo_id| match trash_photo(library, photo_id).for_photo(photo_id) {
+                    Ok(()) => FileOperationResult { photo_id, ..Default::default() },
+                    Err(e) => FileOperationResult { photo_id, error: Some(e.to_string()), ..Default::default() },
+                })
+                .collect()
+        } else {
+            let removed = library.pool.writer()?.remove_photos(&photo_ids)
+                .context("Failed to remove from the catalog")?;
+            photo_ids
+                .into_iter()
+                .map(|photo_id| match removed.contains(&photo_id) {
+                    true => FileOperationResult { photo_id, ..Default::default() },
+                    false => {
+                        let error = AppError::not_found("Not in the catalog").for_photo(photo_id);
+                        FileOperationResult { photo_id, error: Some(error.to_string()), ..Default::default() }
+                    }
+                })
+                .collect()
+        };
+        *library.similarity_index.lock().unwrap() = None;
+        
+        Ok(results)
+    }).await
+}
+
+// Sends photos and their companion files to the OS trash and removes them
+// from the catalog
+#[tauri::command]
+async fn trash_photos(
+    state: State<'_, AppState>,
+    photo_ids: Vec<i64>,
+) -> Result<Vec<FileOperationResult>, AppError> {
+    remove_photos(state, photo_ids, true).await
+}
+
+// Finds rows pointing at deleted rows, cached thumbnails no photo uses and
+// photos whose file is gone (offline drives aside). Only what `options` asks
+// for is fixed; the report lists everything found.
 #[tauri::command]
-fn export_database_to_json(state: State<AppState>) -> Result<String, String> {
-    let state_db = state.db.lock().unwrap();
-    let db = state_db.as_ref().ok_or("Database not initialized")?;
+async fn run_library_maintenance(
+    state: State<'_, AppState>,
+    options: Option<MaintenanceOptions>,
+) -> Result<MaintenanceReport, AppError> {
+    let options = options.unwrap_or_default();
     
-    db.export_to_json()
-        .map_err(|e| format!("Failed to export database: {}", e))
+    // Files are checked without a connection; the writer is only taken for fixes
+    state.library()?.run(move |library| {
+        let cache_dir = library.thumbnail_dir.as_path();
+        let db = if options.fix_orphaned_rows { library.pool.writer()? } else { library.pool.reader()? };
+        let orphaned_rows = db.find_orphaned_rows(options.fix_orphaned_rows)
+            .context("Failed to check for orphaned rows")?;
+        drop(db);
+        
+        let photos = library.pool.reader()?.get_all_photos(false)
+            .context("Failed to get photos")?;
+        let missing_photos: Vec<Photo> = photos
+            .par_iter()
+            .filter(|photo| !photo.is_offline && !Path::new(&photo.path).exists())
+            .cloned()
+            .collect();
+        if options.remove_missing_photos {
+            // Undoable, in case the files were only on a drive that was not marked offline
+            let missing_ids: Vec<i64> = missing_photos.iter().map(|photo| photo.id).collect();
+            // Their thumbnails still count as in use below, for undo to restore
+            library.pool.writer()?.remove_photos(&missing_ids)
+                .context("Failed to remove missing photos")?;
+            *library.similarity_index.lock().unwrap() = None;
+        }
+        
+        // A photo's thumbnail is cached under a hash of its path, but imports
+        // may have recorded another location
+        let in_use: HashSet<PathBuf> = photos
+            .iter()
+            .flat_map(|photo| {
+                let stored = photo.thumbnail_path.as_ref().map(PathBuf::from);
+                std::iter::once(ImageService::thumbnail_path(&photo.path, cache_dir)).chain(stored)
+            })
+            .collect();
+        let mut dangling_thumbnails = Vec::new();
+        if let Ok(entries) = std::fs::read_dir(cache_dir) {
+            for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+                if !path.is_file() || in_use.contains(&path) {
+                    continue;
+                }
+                if options.delete_dangling_thumbnails {
+                    if let Err(e) = std::fs::remove_file(&path) {
+                        log::warn!("Failed to delete thumbnail {:?}: {}", path, e);
+                    }
+                }
+                dangling_thumbnails.push(path.to_string_lossy().to_string());
+            }
+        }
+        
+        Ok(MaintenanceReport { orphaned_rows, dangling_thumbnails, missing_photos })
+    }).await
+}
+
+// Shooting statistics over the whole library, or the photos in `scope`
+#[tauri::command]
+async fn get_library_stats(state: State<'_, AppState>, scope: Option<StatsScope>) -> Result<LibraryStats, AppError> {
+    let scope = scope.unwrap_or_default();
+    for date in [&scope.date_from, &scope.date_to].into_iter().flatten() {
+        if chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+            return Err(AppError::invalid(format!("Invalid date: {}", date)));
+        }
+    }
+    
+    state.read(move |db| {
+        db.get_library_stats(&scope)
+            .context("Failed to get library statistics")
+    }).await
+}
+
+// When the photo was taken, falling back to the file's modification time for
+// files without EXIF (screenshots, some scans)
+fn ingest_date(path: &str, exif: &EXIFData) -> Option<chrono::NaiveDateTime> {
+    exif.capture_date.as_deref()
+        .and_then(parse_capture_date)
+        .or_else(|| {
+            let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+            Some(chrono::DateTime::<chrono::Local>::from(modified).naive_local())
+        })
+}
+
+// Copies a file and its companions to `dest`, verifying each copy against
+// its source checksum
+fn copy_with_companions(
+    journal: &mut FileJournal,
+    image: &ImageFile,
+    content_hash: &str,
+    dest: &Path,
+) -> Result<(), AppError> {
+    let source = Path::new(&image.path);
+    copy_verified(journal, source, dest, content_hash)?;
+    
+    for companion in FileSystemService::companion_files(&image.path, image.live_photo_video.as_deref()) {
+        let companion_dest = companion_destination(&companion, source, dest)
+            .ok_or_else(|| AppError::invalid(format!("Invalid file name: {}", companion.display())))?;
+        let companion_hash = FileSystemService::hash_file(&companion.to_string_lossy())?;
+        copy_verified(journal, &companion, &companion_dest, &companion_hash)?;
+    }
+    Ok(())
+}
+
+// Copies one file into the library (and the backup location) and catalogs
+// the copy, taking the writer only for the insert. The caller rolls `journal`
+// back if this fails.
+fn ingest_file(
+    library: &Library,
+    journal: &mut FileJournal,
+    cache_dir: &Path,
+    options: &IngestOptions,
+    folder_template: &FolderTemplate,
+    image: &ImageFile,
+    content_hash: &str,
+) -> Result<IngestedPhoto, AppError> {
+    let exif = EXIFService::extract_exif(&image.path).unwrap_or_else(|e| {
+        log::warn!("Failed to extract EXIF for {}: {}", image.path, e);
+        EXIFData::default()
+    });
+    let context = TemplateContext {
+        date: ingest_date(&image.path, &exif),
+        values: file_template_values(&image.path, &exif),
+        ..Default::default()
+    };
+    let folder = folder_template.render(&context)?;
+    
+    let dest = unique_destination(&Path::new(&options.destination).join(&folder).join(&image.filename));
+    copy_with_companions(journal, image, content_hash, &dest)?;
+    
+    let backup_path = match &options.backup_destination {
+        Some(backup) => {
+            let name = dest.file_name().ok_or_else(|| AppError::invalid(format!("Invalid file name: {}", dest.display())))?;
+            let backup_path = unique_destination(&Path::new(backup).join(&folder).join(name));
+            copy_with_companions(journal, image, content_hash, &backup_path)?;
+            Some(backup_path.to_string_lossy().to_string())
+        }
+        None => None,
+    };
+    
+    let dest_str = dest.to_string_lossy().to_string();
+    let dimensions = ImageService::get_dimensions(&dest_str)?;
+    let thumbnail_path = match ImageService::generate_thumbnail(&dest_str, cache_dir) {
+        Ok(thumbnail) => Some(thumbnail.thumbnail_path),
+        Err(e) => {
+            log::warn!("Failed to generate thumbnail for {}: {}", dest_str, e);
+            None
+        }
+    };
+    
+    let photo = Photo {
+        id: 0,
+        path: dest_str.clone(),
+        filename: dest.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        file_size: image.file_size as i64,
+        width: dimensions.width as i32,
+        height: dimensions.height as i32,
+        capture_date: exif.capture_date.clone(),
+        added_at: String::new(),
+        rating: 0,
+        is_favorite: false,
+        tags: serde_json::to_string(&options.tags)
+            .context("Failed to serialize tags")?,
+        description: None,
+        thumbnail_path: thumbnail_path.clone(),
+        motion_type: None,
+        motion_video_path: None,
+        motion_video_offset: None,
+        content_identifier: None,
+        content_hash: Some(content_hash.to_string()),
+        stack_id: None,
+        flag: "none".to_string(),
+        color_label: None,
+        is_offline: false,
+    };
+    
+    let photo_id = prepare_photo(photo).and_then(|entry| {
+        let db = library.pool.writer()?;
+        // Another import may have added the same file since it was checked
+        if db.get_photo_id_by_content_hash(content_hash)
+            .context("Failed to check catalog")?
+            .is_some()
+        {
+            return Err(AppError::Conflict { message: format!("{} was imported while this import ran", image.path) });
+        }
+        catalog_photo(library, &db, entry)
+    })
+    .inspect_err(|_| {
+        if let Some(thumbnail_path) = &thumbnail_path {
+            let _ = std::fs::remove_file(thumbnail_path);
+        }
+    })?;
+    
+    Ok(IngestedPhoto { source: image.path.clone(), photo_id, path: dest_str, backup_path })
+}
+
+// Imports from a memory card or other folder by copying into the library
+// instead of cataloging files in place. Files already in the catalog (by
+// content hash) are skipped, and a file whose copy fails verification is
+// rolled back and reported without stopping the rest.
+#[tauri::command]
+async fn ingest_from_card(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    options: IngestOptions,
+) -> Result<IngestReport, AppError> {
+    // Files are hashed and copied without a connection; the writer is only
+    // taken for each insert so other edits are not held up by the import
+    state.library()?.run(move |library| {
+        let cache_dir = library.thumbnail_dir.as_path();
+        
+        let folder_template = FolderTemplate::parse(&options.folder_template)?;
+        if let Some(collection_id) = options.collection_id {
+            library.pool.reader()?.get_collection(collection_id)
+                .context("Failed to get collection")?;
+        }
+        
+        let scan = FileSystemService::scan_images(&options.source, &ScanOptions::default())?;
+        std::fs::create_dir_all(&options.destination)
+            .map_err(|e| AppError::io(&options.destination, e).context("Failed to create directory"))?;
+        let volumes = mounted_volumes();
+        register_library_root(&*library.pool.writer()?, &options.destination, &volumes)?;
+        let mut report = IngestReport::default();
+        report.failed.extend(scan.errors.into_iter().map(|e| IngestFailure {
+            source: e.path.unwrap_or_else(|| options.source.clone()),
+            error: e.error,
+        }));
+        let mut seen = HashMap::new();
+        
+        for (index, image) in scan.images.iter().enumerate() {
+            let progress = ScanProgress { current: index + 1, total: scan.images.len(), current_file: image.filename.clone() };
+            if let Err(e) = app_handle.emit("ingest-progress", progress) {
+                log::warn!("Failed to emit ingest progress: {}", e);
+            }
+            
+            let content_hash = match FileSystemService::hash_file(&image.path) {
+                Ok(hash) => hash,
+                Err(e) => {
+                    report.failed.push(IngestFailure { source: image.path.clone(), error: e.to_string() });
+                    continue;
+                }
+            };
+            
+            let existing_photo_id = library.pool.reader()?.get_photo_id_by_content_hash(&content_hash)
+                .context("Failed to check catalog")?;
+            if existing_photo_id.is_some() || seen.contains_key(&content_hash) {
+                report.skipped.push(IngestSkip { source: image.path.clone(), existing_photo_id });
+                continue;
+            }
+            seen.insert(content_hash.clone(), image.path.clone());
+            
+            let mut journal = FileJournal::new();
+            match ingest_file(library, &mut journal, cache_dir, &options, &folder_template, image, &content_hash) {
+                Ok(imported) => {
+                    journal.commit();
+                    report.imported.push(imported);
+                }
+                Err(e) => {
+                    log::warn!("Failed to ingest {}: {}", image.path, e);
+                    journal.rollback();
+                    report.failed.push(IngestFailure { source: image.path.clone(), error: e.to_string() });
+                }
+            }
+        }
+        
+        if let Some(collection_id) = options.collection_id {
+            let photo_ids: Vec<i64> = report.imported.iter().map(|p| p.photo_id).collect();
+            library.pool.writer()?.batch_add_to_collection(collection_id, &photo_ids)
+                .context("Failed to add imported photos to collection")?;
+        }
+        
+        Ok(report)
+    }).await
+}
+
+const DEFAULT_RELOCATION_SAMPLE: usize = 20;
+
+// Photos whose file is no longer where the catalog says
+#[tauri::command]
+async fn find_missing_photos(state: State<'_, AppState>) -> Result<Vec<Photo>, AppError> {
+    state.read(move |db| {
+        let photos = db.get_all_photos(false)
+            .context("Failed to get photos")?;
+        
+        // Photos on disconnected drives are offline, not missing
+        Ok(photos.into_par_iter().filter(|photo| !photo.is_offline && !Path::new(&photo.path).exists()).collect())
+    }).await
+}
+
+// Points every photo under `old_prefix` to the same relative path under
+// `new_prefix`, e.g. after a drive letter changed or a folder was moved.
+// A sample of files is checked first: any sampled file that exists with a
+// different size (or contents, with `verify_hash`) aborts the relocation, as
+// does a sample in which no file is found at all. Individual files that are
+// missing are tolerated since they may have been deleted before the move.
+#[tauri::command]
+async fn relocate_root(
+    state: State<'_, AppState>,
+    old_prefix: String,
+    new_prefix: String,
+    sample_size: Option<usize>,
+    verify_hash: Option<bool>,
+) -> Result<RelocationReport, AppError> {
+    // The sample is checked without a connection; the writer is only taken to relocate
+    state.library()?.run(move |library| {
+        let (old_root, new_root) = (Path::new(&old_prefix), Path::new(&new_prefix));
+        if !new_root.is_dir() {
+            return Err(AppError::FileNotFound { path: new_prefix });
+        }
+        
+        let photos = library.pool.reader()?.get_photos_under(old_root)
+            .context("Failed to get photos")?;
+        if photos.is_empty() {
+            return Err(AppError::not_found(format!("No photos under {}", old_prefix)));
+        }
+        
+        let sample = spread_sample(&photos, sample_size.unwrap_or(DEFAULT_RELOCATION_SAMPLE));
+        let mut missing_in_sample = 0;
+        for photo in &sample {
+            let new_path = rebase_path(&photo.path, old_root, new_root)
+                .ok_or_else(|| AppError::invalid(format!("{} is not under {}", photo.path, old_prefix)))?;
+            if !Path::new(&new_path).exists() {
+                missing_in_sample += 1;
+                continue;
+            }
+            let content_hash = photo.content_hash.as_deref().filter(|_| verify_hash.unwrap_or(false));
+            verify_file(Path::new(&new_path), photo.file_size as u64, content_hash)
+                .context(&format!("{} does not look like the moved library", new_prefix))
+                .for_photo(photo.id)?;
+        }
+        if missing_in_sample == sample.len() {
+            return Err(AppError::not_found(format!(
+                "None of the {} sampled photos were found under {}",
+                sample.len(),
+                new_prefix
+            )));
+        }
+        
+        let relocated = rebase_photos(&*library.pool.writer()?, old_root, new_root)?;
+        
+        Ok(RelocationReport { relocated, sampled: sample.len(), missing_in_sample })
+    }).await
+}
+
+// Points every photo and library root under `old_root` to the same relative
+// path under `new_root`
+fn rebase_photos(db: &DatabaseService, old_root: &Path, new_root: &Path) -> Result<usize, AppError> {
+    let photos = db.get_photos_under(old_root)
+        .context("Failed to get photos")?;
+    
+    let relocations: Vec<PhotoRelocation> = photos
+        .iter()
+        .filter_map(|photo| {
+            Some(PhotoRelocation {
+                photo_id: photo.id,
+                path: rebase_path(&photo.path, old_root, new_root)?,
+                motion_video_path: photo.motion_video_path.as_deref()
+                    .map(|p| rebase_path(p, old_root, new_root).unwrap_or_else(|| p.to_string())),
+            })
+        })
+        .collect();
+    db.relocate_photos(&relocations)
+        .context("Failed to relocate photos")?;
+    db.relocate_library_roots(old_root, new_root)
+        .context("Failed to relocate library roots")?;
+    
+    Ok(relocations.len())
+}
+
+// Searches `search_folder` for missing photos (all of them, or `photo_ids`)
+// by file name and size. Photos with exactly one match are relocated in one
+// transaction; the rest are reported as ambiguous or not found. The folder
+// is searched without a connection and the writer is only taken to relocate.
+#[tauri::command]
+async fn locate_missing_photos(
+    state: State<'_, AppState>,
+    search_folder: String,
+    photo_ids: Option<Vec<i64>>,
+) -> Result<LocateReport, AppError> {
+    state.library()?.run(move |library| {
+        let db = library.pool.reader()?;
+        let photos = match photo_ids {
+            Some(ids) => ids.into_iter()
+                .map(|id| db.get_photo(id))
+                .collect::<Result<Vec<_>, _>>(),
+            None => db.get_all_photos(false),
+        }
+        .context("Failed to get photos")?;
+        drop(db);
+        let missing: Vec<Photo> = photos
+            .into_iter()
+            .filter(|photo| !photo.is_offline && !Path::new(&photo.path).exists())
+            .collect();
+        
+        let wanted: HashSet<(String, u64)> = missing.iter()
+            .filter_map(|photo| name_and_size_key(&photo.path, photo.file_size as u64))
+            .collect();
+        let found = find_by_name_and_size(Path::new(&search_folder), &wanted);
+        
+        // A file claimed by two missing photos (same name and size) belongs to neither for sure
+        let mut claims: HashMap<&Path, usize> = HashMap::new();
+        for paths in found.values() {
+            for path in paths {
+                *claims.entry(path.as_path()).or_default() += 1;
+            }
+        }
+        
+        let mut report = LocateReport::default();
+        let mut relocations = Vec::new();
+        let db = library.pool.reader()?;
+        for photo in &missing {
+            let candidates: Vec<&PathBuf> = name_and_size_key(&photo.path, photo.file_size as u64)
+                .and_then(|key| found.get(&key))
+                .map(|paths| paths.iter().collect())
+                .unwrap_or_default();
+            
+            let mut available = Vec::new();
+            for candidate in &candidates {
+                let cataloged = db.get_photo_id_by_path(&candidate.to_string_lossy())
+                    .context("Failed to check catalog")?
+                    .is_some();
+                if !cataloged {
+                    available.push(*candidate);
+                }
+            }
+            
+            match available.as_slice() {
+                [] => report.not_found.push(photo.id),
+                [path] if claims.get(path.as_path()) == Some(&1) => {
+                    let new_path = path.to_string_lossy().to_string();
+                    let motion_video_path = match photo.motion_type.as_deref() {
+                        Some("live_photo") => FileSystemService::find_live_photo_video(path),
+                        Some("motion_photo") => Some(new_path.clone()),
+                        _ => photo.motion_video_path.clone(),
+                    };
+                    relocations.push(PhotoRelocation { photo_id: photo.id, path: new_path, motion_video_path });
+                }
+                _ => report.ambiguous.push(AmbiguousMatch {
+                    photo_id: photo.id,
+                    candidates: available.iter().map(|p| p.to_string_lossy().to_string()).collect(),
+                }),
+            }
+        }
+        
+        drop(db);
+        
+        // The files were found with an older view of the catalog; a path
+        // cataloged since then is not taken over
+        let db = library.pool.writer()?;
+        let mut still_free = Vec::new();
+        for relocation in relocations {
+            match db.get_photo_id_by_path(&relocation.path).context("Failed to check catalog")? {
+                Some(_) => report.not_found.push(relocation.photo_id),
+                None => still_free.push(relocation),
+            }
+        }
+        let relocations = still_free;
+        db.relocate_photos(&relocations)
+            .context("Failed to relocate photos")?;
+        report.found = relocations
+            .into_iter()
+            .map(|r| FileOperationResult { photo_id: r.photo_id, path: Some(r.path), ..Default::default() })
+            .collect();
+        
+        Ok(report)
+    }).await
+}
+
+#[tauri::command]
+async fn get_library_roots(state: State<'_, AppState>) -> Result<Vec<LibraryRoot>, AppError> {
+    state.read(move |db| {
+        db.get_library_roots()
+            .context("Failed to get library roots")
+    }).await
+}
+
+#[tauri::command]
+async fn add_library_root(state: State<'_, AppState>, path: String) -> Result<i64, AppError> {
+    state.library()?.run(move |library| {
+        if !Path::new(&path).is_dir() {
+            return Err(AppError::FileNotFound { path });
+        }
+        
+        let volumes = mounted_volumes();
+        register_library_root(&*library.pool.writer()?, &path, &volumes)
+    }).await
+}
+
+#[tauri::command]
+async fn remove_library_root(state: State<'_, AppState>, root_id: i64) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.remove_library_root(root_id)
+            .context("Failed to remove library root")
+    }).await
+}
+
+// Registers a library root along with the volume it is on, one of `volumes`
+// (listed beforehand, since that can take a while)
+fn register_library_root(db: &DatabaseService, path: &str, volumes: &[VolumeInfo]) -> Result<i64, AppError> {
+    let root_id = db.add_library_root(path)
+        .context("Failed to add library root")?;
+    record_root_volume(db, root_id, Path::new(path), volumes)?;
+    Ok(root_id)
+}
+
+fn record_root_volume(db: &DatabaseService, root_id: i64, path: &Path, volumes: &[VolumeInfo]) -> Result<(), AppError> {
+    let Some(volume) = volume_containing(volumes, path) else { return Ok(()) };
+    let volume_path = path.strip_prefix(&volume.mount_point)
+        .map(|p| p.to_string_lossy().to_string())
+        .ok();
+    
+    db.set_root_volume(root_id, volume.id.as_deref(), volume.label.as_deref(), volume_path.as_deref())
+        .context(&format!("Failed to record volume of {}", path.display()))
+}
+
+// Writes a photo's rating, reject flag and color label to its XMP sidecar
+fn write_photo_xmp(photo: Photo) -> Result<String, AppError> {
+    let labels = XmpLabels {
+        rating: Some(photo.rating),
+        rejected: photo.flag == "rejected",
+        color_label: photo.color_label,
+    };
+    EXIFService::write_xmp_labels(&photo.path, &labels)
+}
+
+// Brings each library root's online state up to date. A root whose folder is
+// gone (or now belongs to another drive) is looked for on its volume, by id or
+// label, among the mounted volumes, which finds a drive that came back under
+// another mount point or drive letter. Label edits queued while a root was
+// offline are written back to XMP sidecars once it is online, but only to a
+// photo's existing sidecar unless `xmp_sync` is on; the rest are just dropped
+// from the queue. Volumes are listed and sidecars written without a
+// connection; the writer is only taken to record what changed.
+fn check_library_roots(pool: &Arc<DatabasePool>, xmp_sync: bool) -> Result<Vec<RootStatus>, AppError> {
+    let roots = pool.reader()?.get_library_roots()
+        .context("Failed to get library roots")?;
+    let volumes = mounted_volumes();
+    let mut statuses = Vec::new();
+    
+    for root in roots {
+        let root_path = PathBuf::from(&root.path);
+        let (volume_id, volume_label) = (root.volume_id.as_deref(), root.volume_label.as_deref());
+        let identified = volume_id.is_some() || volume_label.is_some();
+        
+        let on_own_volume = root_path.is_dir()
+            && (!identified || volume_containing(&volumes, &root_path).is_some_and(|v| v.matches(volume_id, volume_label)));
+        let moved_to = if on_own_volume || !identified {
+            None
+        } else {
+            volumes
+                .iter()
+                .filter(|v| v.matches(volume_id, volume_label))
+                .map(|v| v.mount_point.join(root.volume_path.as_deref().unwrap_or_default()))
+                .find(|candidate| candidate.is_dir())
+        };
+        
+        let mut status = RootStatus {
+            root_id: root.id,
+            path: root.path.clone(),
+            is_online: on_own_volume || moved_to.is_some(),
+            relocated_from: None,
+            written_back: BatchReport::default(),
+        };
+        {
+            let db = pool.writer()?;
+            if let Some(new_path) = moved_to {
+                log::info!("Library root {} found at {}", root.path, new_path.display());
+                rebase_photos(&db, &root_path, &new_path)?;
+                status.path = new_path.to_string_lossy().to_string();
+                status.relocated_from = Some(root.path.clone());
+            }
+            if status.is_online && !identified {
+                record_root_volume(&db, root.id, Path::new(&status.path), &volumes)?;
+            }
+            
+            db.set_root_online(root.id, status.is_online)
+                .context("Failed to update library root")?;
+        }
+        
+        if status.is_online {
+            let db = pool.reader()?;
+            let pending = db.get_pending_writes(Path::new(&status.path))
+                .context("Failed to get queued edits")?;
+            let photos: Vec<(i64, Result<Photo, AppError>)> = pending
+                .into_iter()
+                .map(|photo_id| (photo_id, db.get_photo(photo_id).context("Failed to get photo").for_photo(photo_id)))
+                .collect();
+            drop(db);
+            
+            let mut done = Vec::new();
+            for (photo_id, photo) in photos {
+                let written = photo.and_then(|photo| {
+                    if !xmp_sync && EXIFService::find_xmp_sidecars(&photo.path).is_empty() {
+                        return Ok(false);
+                    }
+                    write_photo_xmp(photo).map(|_| true)
+                });
+                match written {
+                    Ok(written) => done.push((photo_id, written)),
+                    Err(e) => status.written_back.failed.push(BatchFailure { photo_id, error: e.to_string() }),
+                }
+            }
+            
+            if !done.is_empty() {
+                let db = pool.writer()?;
+                for (photo_id, written) in done {
+                    match db.clear_pending_write(photo_id).context("Failed to clear queued edit") {
+                        Ok(()) if written => status.written_back.succeeded.push(photo_id),
+                        Ok(()) => {}
+                        Err(e) => status.written_back.failed.push(BatchFailure { photo_id, error: e.to_string() }),
+                    }
+                }
+            }
+        }
+        
+        statuses.push(status);
+    }
+    
+    Ok(statuses)
+}
+
+// Re-checks which library roots are connected, e.g. after a drive was plugged in
+#[tauri::command]
+async fn refresh_library_roots(app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<Vec<RootStatus>, AppError> {
+    let xmp_sync = AppSettings::load(&settings_path(&app_handle)?)?.xmp_sync;
+    state.library()?.run(move |library| {
+        check_library_roots(&library.pool, xmp_sync)
+    }).await
+}
+
+#[tauri::command]
+async fn get_folder_tree(state: State<'_, AppState>) -> Result<Vec<FolderNode>, AppError> {
+    state.read(move |db| {
+        db.get_folder_tree()
+            .context("Failed to get folder tree")
+    }).await
+}
+
+#[tauri::command]
+async fn get_photos_in_folder(state: State<'_, AppState>, path: String, recursive: bool) -> Result<Vec<Photo>, AppError> {
+    state.read(move |db| {
+        db.get_photos_in_folder(Path::new(&path), recursive)
+            .context("Failed to get photos in folder")
+    }).await
+}
+
+#[tauri::command]
+async fn find_similar(
+    state: State<'_, AppState>,
+    photo_id: i64,
+    threshold: u32,
+) -> Result<Vec<SimilarPhoto>, AppError> {
+    state.library()?.run(move |library| {
+        let index = ensure_similarity_index(library)?;
+        let index = index.as_ref().ok_or_else(|| AppError::not_found("Similarity index not available"))?;
+        let db = library.pool.reader()?;
+        
+        let (dhash, phash) = db.get_perceptual_hash(photo_id)
+            .context("Failed to get perceptual hash")?
+            .ok_or_else(|| AppError::not_found("Photo has no perceptual hash"))?;
+        
+        let mut matches = index.query(&PerceptualHash { dhash, phash }, threshold);
+        matches.retain(|(id, _)| *id != photo_id);
+        matches.sort_by_key(|(_, distance)| *distance);
+        
+        Ok(matches
+            .into_iter()
+            .filter_map(|(id, distance)| {
+                db.get_photo(id).ok().map(|photo| SimilarPhoto { photo, distance })
+            })
+            .collect())
+    }).await
+}
+
+// Clusters near-duplicates and bursts so each group can be culled in one go
+#[tauri::command]
+async fn group_similar_photos(state: State<'_, AppState>, threshold: u32) -> Result<Vec<Vec<Photo>>, AppError> {
+    state.library()?.run(move |library| {
+        let index = ensure_similarity_index(library)?;
+        let index = index.as_ref().ok_or_else(|| AppError::not_found("Similarity index not available"))?;
+        let db = library.pool.reader()?;
+        
+        let mut groups: Vec<Vec<Photo>> = index
+            .clusters(threshold)
+            .into_iter()
+            .map(|ids| {
+                let mut photos: Vec<Photo> = ids.into_iter().filter_map(|id| db.get_photo(id).ok()).collect();
+                photos.sort_by(|a, b| a.capture_date.cmp(&b.capture_date).then(a.added_at.cmp(&b.added_at)));
+                photos
+            })
+            .filter(|photos| photos.len() > 1)
+            .collect();
+        groups.sort_by(|a, b| a[0].capture_date.cmp(&b[0].capture_date));
+        
+        Ok(groups)
+    }).await
+}
+
+// Query by color: photos whose dominant colors are closest to the given "#rrggbb" colors
+#[tauri::command]
+async fn find_by_color(state: State<'_, AppState>, colors: Vec<String>, limit: usize) -> Result<Vec<ColorMatch>, AppError> {
+    let targets = colors
+        .iter()
+        .map(|c| parse_hex_color(c).ok_or_else(|| AppError::invalid(format!("Invalid color: {}", c))))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    state.library()?.run(move |library| {
+        backfill_color_signatures(library)?;
+        let db = library.pool.reader()?;
+        let signatures = db.get_color_signatures()
+            .context("Failed to get color signatures")?;
+        
+        let scored = signatures
+            .par_iter()
+            .filter_map(|(id, _, palette)| {
+                let palette: Vec<PaletteColor> = serde_json::from_str(palette).ok()?;
+                Some((*id, palette_similarity(&palette, &targets)))
+            })
+            .collect();
+        
+        Ok(top_color_matches(&db, scored, limit))
+    }).await
+}
+
+// Query by example: photos with the closest color distribution to `photo_id`
+#[tauri::command]
+async fn find_visually_similar(state: State<'_, AppState>, photo_id: i64, limit: usize) -> Result<Vec<ColorMatch>, AppError> {
+    state.library()?.run(move |library| {
+        backfill_color_signatures(library)?;
+        let db = library.pool.reader()?;
+        let signatures = db.get_color_signatures()
+            .context("Failed to get color signatures")?;
+        
+        let reference = signatures
+            .iter()
+            .find(|(id, _, _)| *id == photo_id)
+            .map(|(_, histogram, _)| histogram.clone())
+            .ok_or_else(|| AppError::not_found("Photo has no color signature"))?;
+        
+        let scored = signatures
+            .par_iter()
+            .filter(|(id, _, _)| *id != photo_id)
+            .map(|(id, histogram, _)| (*id, histogram_similarity(&reference, histogram)))
+            .collect();
+        
+        Ok(top_color_matches(&db, scored, limit))
+    }).await
+}
+
+#[tauri::command]
+async fn extract_motion_video(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    photo_id: i64,
+) -> Result<String, AppError> {
+    let photo = state.read(move |db| {
+        db.get_photo(photo_id)
+            .context("Failed to get photo")
+            .for_photo(photo_id)
+    }).await?;
+    
+    let video_path = photo.motion_video_path.ok_or_else(|| AppError::not_found("Photo has no motion video"))?;
+    
+    match photo.motion_video_offset {
+        // Motion Photo: the clip is embedded in the JPEG and must be cut out
+        Some(offset) => {
+            let dest_path = app_handle
+                .path()
+                .temp_dir()
+                .context("Failed to get temp dir")?
+                .join("photo-manager")
+                .join("motion")
+                .join(format!("{}.mp4", photo_id));
+            
+            run_blocking(move || {
+                FileSystemService::extract_motion_video(&video_path, offset as u64, &dest_path)?;
+                Ok(dest_path.to_string_lossy().to_string())
+            }).await
+        }
+        // Live Photo: the MOV is already a standalone file
+        None => Ok(video_path),
+    }
+}
+
+// Stack commands
+#[tauri::command]
+async fn auto_stack_photos(state: State<'_, AppState>, max_gap_seconds: Option<f64>) -> Result<Vec<Stack>, AppError> {
+    state.library()?.run(move |library| {
+        backfill_exif(library)?;
+        library.pool.writer()?.auto_stack(max_gap_seconds.unwrap_or(2.0))
+            .context("Failed to stack photos")
+    }).await
+}
+
+#[tauri::command]
+async fn get_all_stacks(state: State<'_, AppState>) -> Result<Vec<Stack>, AppError> {
+    state.read(move |db| {
+        db.get_all_stacks()
+            .context("Failed to get stacks")
+    }).await
+}
+
+#[tauri::command]
+async fn get_stack_photos(state: State<'_, AppState>, stack_id: i64) -> Result<Vec<Photo>, AppError> {
+    state.read(move |db| {
+        db.get_stack_photos(stack_id)
+            .context("Failed to get stack photos")
+    }).await
+}
+
+#[tauri::command]
+async fn set_stack_collapsed(state: State<'_, AppState>, stack_id: i64, collapsed: bool) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.set_stack_collapsed(stack_id, collapsed)
+            .context("Failed to update stack")
+    }).await
+}
+
+#[tauri::command]
+async fn set_stack_top(state: State<'_, AppState>, stack_id: i64, photo_id: i64) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.set_stack_top(stack_id, photo_id)
+            .context("Failed to set stack top")
+    }).await
+}
+
+#[tauri::command]
+async fn unstack(state: State<'_, AppState>, stack_id: i64) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.unstack(stack_id)
+            .context("Failed to unstack")
+    }).await
 }
 
 // File system commands
+// Without `options`, uses the rules stored for the library root that contains
+// the folder, or the defaults for a new folder
 #[tauri::command]
-fn scan_images(folder_path: String) -> Result<Vec<ImageFile>, String> {
-    FileSystemService::scan_images(&folder_path)
+async fn scan_images(
+    state: State<'_, AppState>,
+    folder_path: String,
+    options: Option<ScanOptions>,
+) -> Result<ScanResult, AppError> {
+    let library = state.library().ok();
+    run_blocking(move || {
+        let options = match (options, library) {
+            (Some(options), _) => options,
+            (None, Some(library)) => library.pool.reader()?.scan_options_for(Path::new(&folder_path))
+                .context("Failed to get scan options")?,
+            (None, None) => ScanOptions::default(),
+        };
+        
+        FileSystemService::scan_images(&folder_path, &options)
+    }).await
+}
+
+#[tauri::command]
+async fn set_scan_options(state: State<'_, AppState>, root_id: i64, options: ScanOptions) -> Result<(), AppError> {
+    state.write(move |db| {
+        db.set_scan_options(root_id, &options)
+            .context("Failed to save scan options")
+    }).await
 }
 
 // EXIF commands
 #[tauri::command]
-fn get_exif(path: String) -> Result<EXIFData, String> {
-    EXIFService::extract_exif(&path)
+async fn get_exif(path: String) -> Result<EXIFData, AppError> {
+    run_blocking(move || EXIFService::extract_exif(&path)).await
+}
+
+// Stored EXIF, without re-reading the file
+#[tauri::command]
+async fn get_photo_exif(state: State<'_, AppState>, photo_id: i64) -> Result<Option<EXIFData>, AppError> {
+    state.read(move |db| {
+        db.get_exif(photo_id)
+            .context("Failed to get EXIF data")
+    }).await
 }
 
 // Image processing commands
 #[tauri::command]
-fn get_image_dimensions(path: String) -> Result<ImageDimensions, String> {
-    ImageService::get_dimensions(&path)
+async fn get_image_dimensions(path: String) -> Result<ImageDimensions, AppError> {
+    run_blocking(move || ImageService::get_dimensions(&path)).await
 }
 
 #[tauri::command]
-fn generate_thumbnail(
-    app_handle: tauri::AppHandle,
+async fn generate_thumbnail(
+    state: State<'_, AppState>,
     image_path: String,
-) -> Result<ThumbnailResult, String> {
-    let cache_dir = app_handle
-        .path()
-        .app_cache_dir()
-        .map_err(|e| format!("Failed to get cache dir: {}", e))?
-        .join("photo-manager")
-        .join("thumbnails");
+) -> Result<ThumbnailResult, AppError> {
+    let cache_dir = state.library()?.thumbnail_dir.clone();
     
-    ImageService::generate_thumbnail(&image_path, &cache_dir)
+    run_blocking(move || ImageService::generate_thumbnail(&image_path, &cache_dir)).await
 }
 
 #[tauri::command]
-fn resize_image(
+async fn resize_image(
     source_path: String,
     dest_path: String,
     width: Option<u32>,
     height: Option<u32>,
     preserve_exif: bool,
-) -> Result<(), String> {
-    ImageService::resize_image(&source_path, &dest_path, width, height, preserve_exif)
+) -> Result<(), AppError> {
+    run_blocking(move || ImageService::resize_image(&source_path, &dest_path, width, height, preserve_exif)).await
 }
 
 #[tauri::command]
-fn clear_thumbnail_cache(app_handle: tauri::AppHandle) -> Result<usize, String> {
-    let cache_dir = app_handle
-        .path()
-        .app_cache_dir()
-        .map_err(|e| format!("Failed to get cache dir: {}", e))?
-        .join("photo-manager")
-        .join("thumbnails");
+async fn clear_thumbnail_cache(state: State<'_, AppState>) -> Result<usize, AppError> {
+    let cache_dir = state.library()?.thumbnail_dir.clone();
     
-    ImageService::clear_cache(&cache_dir)
+    run_blocking(move || ImageService::clear_cache(&cache_dir)).await
 }
 
 #[tauri::command]
-fn get_cache_size(app_handle: tauri::AppHandle) -> Result<u64, String> {
-    let cache_dir = app_handle
-        .path()
-        .app_cache_dir()
-        .map_err(|e| format!("Failed to get cache dir: {}", e))?
-        .join("photo-manager")
-        .join("thumbnails");
+async fn get_cache_size(state: State<'_, AppState>) -> Result<u64, AppError> {
+    let cache_dir = state.library()?.thumbnail_dir.clone();
     
-    ImageService::get_cache_size(&cache_dir)
+    run_blocking(move || ImageService::get_cache_size(&cache_dir)).await
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -209,29 +2577,136 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
-        .manage(AppState {
-            db: Mutex::new(None),
+        .manage(AppState::default())
+        // The catalog opens in the background; the frontend waits for the
+        // "startup-state" event or polls `get_startup_state`
+        .setup(|app| {
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let state = app_handle.state::<AppState>().inner().clone();
+                match configured_library_dir(&app_handle) {
+                    Ok(library_dir) => {
+                        load_library(&app_handle, &state, library_dir, None).await;
+                    }
+                    Err(error) => {
+                        let startup = StartupState::Failed { library_path: String::new(), error };
+                        publish_startup(&app_handle, &state, startup);
+                    }
+                }
+            });
+            
+            // Scheduled backups; `back_up_library` skips libraries backed up
+            // recently enough
+            let app_handle = app.handle().clone();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(BACKUP_CHECK_INTERVAL);
+                // Not while a library is being switched or its catalog replaced
+                let state = app_handle.state::<AppState>();
+                let _switching = state.switching.lock().unwrap();
+                let Ok(library) = state.library() else {
+                    continue;
+                };
+                if let Err(e) = back_up_library(&app_handle, &library, true) {
+                    log::warn!("Scheduled backup failed: {}", e);
+                }
+            });
+            Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             greet,
-            init_database,
+            get_startup_state,
+            get_recent_libraries,
+            create_library,
+            open_library,
+            close_library,
+            remove_library,
+            check_database_integrity,
+            recover_database,
+            list_backups,
+            back_up_now,
+            restore_backup,
+            get_backup_policy,
+            set_backup_policy,
+            get_xmp_sync,
+            set_xmp_sync,
             insert_photo,
             get_all_photos,
             update_metadata,
+            batch_update_metadata,
+            undo,
+            redo,
+            get_edit_history,
+            get_edit_history_retention,
+            set_edit_history_retention,
             create_collection,
+            get_collection,
+            rename_collection,
+            set_collection_description,
+            set_collection_cover,
+            create_smart_collection,
+            set_collection_rules,
             get_all_collections,
+            move_collection,
+            reorder_collections,
+            delete_collection,
             add_photo_to_collection,
             remove_photo_from_collection,
+            batch_add_to_collection,
+            batch_remove_from_collection,
             get_photos_in_collection,
+            reorder_collection_photos,
             export_database_to_json,
+            import_metadata_from_json,
+            write_xmp_sidecars,
+            find_duplicates,
+            resolve_duplicates,
+            move_photos,
+            copy_photos,
+            rename_photo,
+            trash_photos,
+            remove_photos,
+            run_library_maintenance,
+            get_library_stats,
+            preview_rename,
+            apply_rename,
+            ingest_from_card,
+            find_missing_photos,
+            relocate_root,
+            locate_missing_photos,
+            get_library_roots,
+            add_library_root,
+            remove_library_root,
+            get_folder_tree,
+            refresh_library_roots,
+            get_photos_in_folder,
+            find_similar,
+            group_similar_photos,
+            find_by_color,
+            find_visually_similar,
+            extract_motion_video,
+            auto_stack_photos,
+            get_all_stacks,
+            get_stack_photos,
+            set_stack_collapsed,
+            set_stack_top,
+            unstack,
             scan_images,
+            set_scan_options,
             get_exif,
+            get_photo_exif,
             get_image_dimensions,
             generate_thumbnail,
             resize_image,
             clear_thumbnail_cache,
             get_cache_size,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app_handle, event| {
+            if let tauri::RunEvent::Exit = event {
+                let state = app_handle.state::<AppState>();
+                let _switching = state.switching.lock().unwrap();
+                close_and_back_up(app_handle, &state);
+            }
+        });
 }
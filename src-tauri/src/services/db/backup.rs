@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{Datelike, Local, NaiveDateTime, TimeZone, Utc};
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use log::info;
+use crate::services::error::{AppError, AppResult, ResultExt};
+use super::{DATABASE_FILE, DatabaseService, set_aside, verify_catalog};
+use super::recovery::is_catalog_file;
+
+const BACKUP_DIR: &str = "backups";
+const BACKUP_PREFIX: &str = "photos-";
+const STAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupInfo {
+    pub path: String,
+    pub created_at: String, // UTC, like the catalog's own timestamps
+    pub size: u64,
+}
+
+// Which backups survive rotation: the newest of each of the last `daily` days
+// and of each of the last `weekly` weeks that have one. While the app runs a
+// backup is taken every `interval_hours`; 0 leaves only the ones taken when
+// a library is closed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct BackupPolicy {
+    pub daily: u32,
+    pub weekly: u32,
+    pub interval_hours: u32,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        BackupPolicy { daily: 7, weekly: 4, interval_hours: 24 }
+    }
+}
+
+fn backup_dir(library_dir: &Path) -> PathBuf {
+    library_dir.join(BACKUP_DIR)
+}
+
+// Backups are named after when they were taken, photos-<UTC time>.db
+fn backup_time(path: &Path) -> Option<NaiveDateTime> {
+    let stamp = path.file_name()?.to_str()?.strip_prefix(BACKUP_PREFIX)?.strip_suffix(".db")?;
+    NaiveDateTime::parse_from_str(stamp, STAMP_FORMAT).ok()
+}
+
+fn dated_backups(library_dir: &Path) -> AppResult<Vec<(NaiveDateTime, BackupInfo)>> {
+    let dir = backup_dir(library_dir);
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(AppError::io(&dir, e).context("Failed to read backups")),
+    };
+
+    let mut backups: Vec<(NaiveDateTime, BackupInfo)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let taken_at = backup_time(&path)?;
+            let size = entry.metadata().ok()?.len();
+            Some((taken_at, BackupInfo {
+                path: path.to_string_lossy().to_string(),
+                created_at: taken_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                size,
+            }))
+        })
+        .collect();
+    backups.sort_by_key(|(taken_at, _)| std::cmp::Reverse(*taken_at));
+
+    Ok(backups)
+}
+
+// Newest first
+pub fn list_backups(library_dir: &Path) -> AppResult<Vec<BackupInfo>> {
+    Ok(dated_backups(library_dir)?.into_iter().map(|(_, backup)| backup).collect())
+}
+
+pub fn latest_backup(library_dir: &Path) -> Option<BackupInfo> {
+    list_backups(library_dir).ok()?.into_iter().next()
+}
+
+// Whether the scheduled backup is due: the newest one is older than the
+// policy's interval, or there is none
+pub fn backup_due(library_dir: &Path, policy: &BackupPolicy) -> bool {
+    if policy.interval_hours == 0 {
+        return false;
+    }
+    match dated_backups(library_dir).ok().and_then(|backups| backups.first().map(|(taken_at, _)| *taken_at)) {
+        Some(taken_at) => Utc::now().naive_utc() - taken_at >= chrono::Duration::hours(policy.interval_hours as i64),
+        None => true,
+    }
+}
+
+// Copies the catalog into the backup folder and keeps the copy only if it
+// passes the integrity and foreign key checks
+pub fn create_backup(db: &DatabaseService, library_dir: &Path) -> AppResult<BackupInfo> {
+    let dir = backup_dir(library_dir);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| AppError::io(&dir, e).context("Failed to create backup directory"))?;
+
+    let taken_at = Utc::now().naive_utc();
+    let path = dir.join(format!("{}{}.db", BACKUP_PREFIX, taken_at.format(STAMP_FORMAT)));
+    // Names have a resolution of one second
+    if path.exists() {
+        return Err(AppError::AlreadyExists { path: path.to_string_lossy().to_string() }
+            .context("A backup was taken less than a second ago"));
+    }
+    let temp_path = path.with_extension("db.tmp");
+    let _ = std::fs::remove_file(&temp_path);
+
+    let verified = db.backup_to(&temp_path)
+        .context("Failed to back up catalog")
+        .and_then(|_| verify_catalog(&temp_path).context("Failed to check backup"))
+        .and_then(|problems| match problems.is_empty() {
+            true => Ok(()),
+            false => Err(AppError::DatabaseCorrupt { message: problems.join("; ") }.context("Backup discarded")),
+        });
+    if let Err(e) = verified {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    std::fs::rename(&temp_path, &path)
+        .map_err(|e| AppError::io(&path, e).context("Failed to store backup"))?;
+
+    info!("Backed up catalog to {:?}", path);
+    Ok(BackupInfo {
+        path: path.to_string_lossy().to_string(),
+        created_at: taken_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        size: std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+    })
+}
+
+// Deletes the backups `policy` does not keep; the newest is always kept.
+// Days and weeks are local time. Returns how many were deleted.
+pub fn prune_backups(library_dir: &Path, policy: &BackupPolicy) -> AppResult<usize> {
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    let mut removed = 0;
+
+    for (index, (taken_at, backup)) in dated_backups(library_dir)?.into_iter().enumerate() {
+        let date = Local.from_utc_datetime(&taken_at).date_naive();
+        let week = (date.iso_week().year(), date.iso_week().week());
+        let keep_day = !days.contains(&date) && days.len() < policy.daily as usize;
+        let keep_week = !weeks.contains(&week) && weeks.len() < policy.weekly as usize;
+        days.insert(date);
+        weeks.insert(week);
+
+        if index == 0 || keep_day || keep_week {
+            continue;
+        }
+        std::fs::remove_file(&backup.path)
+            .map_err(|e| AppError::io(&backup.path, e).context("Failed to delete old backup"))?;
+        removed += 1;
+    }
+
+    if removed > 0 {
+        info!("Deleted {} old backups", removed);
+    }
+    Ok(removed)
+}
+
+// What the app keeps in a library folder: the catalog and its WAL files,
+// catalogs set aside by a restore or recovery, and the backup folder. Photos
+// and anything else the user put there are left out.
+pub fn library_files(library_dir: &Path) -> AppResult<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(library_dir)
+        .map_err(|e| AppError::io(library_dir, e).context("Failed to read library folder"))?;
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.file_name().and_then(|n| n.to_str()).is_some_and(is_catalog_file))
+        .collect();
+    let backups = backup_dir(library_dir);
+    if backups.is_dir() {
+        files.push(backups);
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+// Puts a backup of this library in place of its catalog, which must be
+// closed. The catalog it replaces is set aside under `replaced_label`.
+pub fn restore_backup(library_dir: &Path, backup_path: &Path, replaced_label: &str) -> AppResult<()> {
+    let known = list_backups(library_dir)?.iter().any(|backup| Path::new(&backup.path) == backup_path);
+    if !known {
+        return Err(AppError::not_found(format!("No backup at {}", backup_path.display())));
+    }
+    let problems = verify_catalog(backup_path).context("Failed to check backup")?;
+    if !problems.is_empty() {
+        return Err(AppError::DatabaseCorrupt { message: problems.join("; ") }.context("Backup is damaged"));
+    }
+
+    // Copied next to the catalog first so a failed copy leaves it untouched
+    let db_path = library_dir.join(DATABASE_FILE);
+    let temp_path = db_path.with_extension("db.restore");
+    std::fs::copy(backup_path, &temp_path)
+        .map_err(|e| AppError::io(backup_path, e).context("Failed to restore backup"))?;
+    set_aside(library_dir, replaced_label)?;
+    std::fs::rename(&temp_path, &db_path)
+        .map_err(|e| AppError::io(&db_path, e).context("Failed to restore backup"))?;
+
+    info!("Restored catalog from {:?}", backup_path);
+    Ok(())
+}
+
+impl DatabaseService {
+    // A consistent copy of the catalog at `dest`, made with SQLite's online
+    // backup API in a single step so concurrent writes cannot restart it. In
+    // WAL mode the step does not block writers.
+    pub fn backup_to(&self, dest: &Path) -> SqlResult<()> {
+        let mut dest = Connection::open(dest)?;
+        let backup = Backup::new(&self.conn, &mut dest)?;
+        loop {
+            match backup.step(-1)? {
+                StepResult::Done => return Ok(()),
+                _ => std::thread::sleep(Duration::from_millis(100)),
+            }
+        }
+    }
+}
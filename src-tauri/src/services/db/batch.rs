@@ -0,0 +1,154 @@
+use rusqlite::{Result as SqlResult, params};
+use serde::{Deserialize, Serialize};
+use crate::services::error::AppResult;
+use super::DatabaseService;
+
+// One metadata change applied to every photo of a batch, e.g.
+// {"op":"add_tags","tags":["client-x"]} or {"op":"toggle_favorite"}
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum MetadataOp {
+    SetRating { value: i32 },
+    SetFavorite { value: bool },
+    ToggleFavorite,
+    AddTags { tags: Vec<String> },
+    RemoveTags { tags: Vec<String> },
+    SetDescription { value: Option<String> },
+    AppendDescription { text: String },
+    SetFlag { value: String }, // "none", "picked" or "rejected"
+    SetColorLabel { value: Option<String> },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchFailure {
+    pub photo_id: i64,
+    pub error: String,
+}
+
+// Outcome of a batch per photo id. Failed ids are rolled back individually;
+// the rest of the batch is still committed.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct BatchReport {
+    pub succeeded: Vec<i64>,
+    pub failed: Vec<BatchFailure>,
+}
+
+impl BatchReport {
+    fn record(&mut self, photo_id: i64, result: SqlResult<()>) {
+        match result {
+            Ok(()) => self.succeeded.push(photo_id),
+            Err(rusqlite::Error::QueryReturnedNoRows) => self.failed.push(BatchFailure {
+                photo_id,
+                error: "Photo not found".to_string(),
+            }),
+            Err(e) => self.failed.push(BatchFailure { photo_id, error: e.to_string() }),
+        }
+    }
+}
+
+impl DatabaseService {
+    // Applies `ops` in order to each photo, all in a single transaction and a
+    // single undo step
+    pub fn batch_update_metadata(&self, photo_ids: &[i64], ops: &[MetadataOp]) -> SqlResult<BatchReport> {
+        let mut tx = self.conn.unchecked_transaction()?;
+        let action_id = self.begin_edit_action("Edit metadata of multiple photos")?;
+        let mut report = BatchReport::default();
+
+        for &photo_id in photo_ids {
+            let sp = tx.savepoint()?;
+            let result = self.journal_metadata_edit(action_id, photo_id, || self.apply_metadata_ops(photo_id, ops));
+            if result.is_ok() {
+                sp.commit()?;
+            }
+            report.record(photo_id, result);
+        }
+
+        self.finish_edit_action(action_id)?;
+        tx.commit()?;
+        Ok(report)
+    }
+
+    fn apply_metadata_ops(&self, photo_id: i64, ops: &[MetadataOp]) -> SqlResult<()> {
+        let mut photo = self.get_photo(photo_id)?;
+        let mut tags: Vec<String> = serde_json::from_str(&photo.tags).unwrap_or_default();
+
+        for op in ops {
+            match op {
+                MetadataOp::SetRating { value } => photo.rating = *value,
+                MetadataOp::SetFavorite { value } => photo.is_favorite = *value,
+                MetadataOp::ToggleFavorite => photo.is_favorite = !photo.is_favorite,
+                MetadataOp::AddTags { tags: added } => {
+                    for tag in added {
+                        if !tags.contains(tag) {
+                            tags.push(tag.clone());
+                        }
+                    }
+                }
+                MetadataOp::RemoveTags { tags: removed } => tags.retain(|t| !removed.contains(t)),
+                MetadataOp::SetDescription { value } => photo.description = value.clone(),
+                MetadataOp::AppendDescription { text } => {
+                    photo.description = Some(match photo.description.take() {
+                        Some(existing) if !existing.is_empty() => format!("{}\n{}", existing, text),
+                        _ => text.clone(),
+                    });
+                }
+                MetadataOp::SetFlag { value } => photo.flag = value.clone(),
+                MetadataOp::SetColorLabel { value } => photo.color_label = value.clone(),
+            }
+        }
+
+        let tags = serde_json::to_string(&tags)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.conn.execute(
+            "UPDATE photos SET rating = ?1, is_favorite = ?2, tags = ?3, description = ?4, flag = ?5, color_label = ?6
+             WHERE id = ?7",
+            params![photo.rating, photo.is_favorite as i32, tags, photo.description, photo.flag, photo.color_label, photo_id],
+        )?;
+        Ok(())
+    }
+
+    // Photos already in the collection count as succeeded
+    pub fn batch_add_to_collection(&self, collection_id: i64, photo_ids: &[i64]) -> AppResult<BatchReport> {
+        self.get_collection(collection_id)?;
+
+        let mut tx = self.conn.unchecked_transaction()?;
+        let action_id = self.begin_edit_action("Add to collection")?;
+        let mut report = BatchReport::default();
+
+        for &photo_id in photo_ids {
+            let sp = tx.savepoint()?;
+            let result = self.get_photo(photo_id)
+                .and_then(|_| self.set_membership(action_id, photo_id, collection_id, true));
+            if result.is_ok() {
+                sp.commit()?;
+            }
+            report.record(photo_id, result);
+        }
+
+        self.finish_edit_action(action_id)?;
+        tx.commit()?;
+        Ok(report)
+    }
+
+    // Photos that were not in the collection count as succeeded
+    pub fn batch_remove_from_collection(&self, collection_id: i64, photo_ids: &[i64]) -> AppResult<BatchReport> {
+        self.get_collection(collection_id)?;
+
+        let mut tx = self.conn.unchecked_transaction()?;
+        let action_id = self.begin_edit_action("Remove from collection")?;
+        let mut report = BatchReport::default();
+
+        for &photo_id in photo_ids {
+            let sp = tx.savepoint()?;
+            let result = self.set_membership(action_id, photo_id, collection_id, false);
+            if result.is_ok() {
+                sp.commit()?;
+            }
+            report.record(photo_id, result);
+        }
+
+        self.finish_edit_action(action_id)?;
+        tx.commit()?;
+        Ok(report)
+    }
+}
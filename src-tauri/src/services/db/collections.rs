@@ -0,0 +1,339 @@
+use rusqlite::{Result as SqlResult, Row, params};
+use serde::{Deserialize, Serialize};
+use crate::services::error::{AppError, AppResult};
+use super::{DatabaseService, Photo, SmartRule, PHOTO_COLUMNS, constraint_error};
+
+const COLLECTION_COLUMNS: &str = "c.id, c.name, c.created_at, c.rules, c.parent_id, c.sequence, \
+     c.description, c.cover_photo_id, c.updated_at, c.photo_count";
+
+// A UNIQUE violation on `collections.name` is reported as `DuplicateName`
+pub(super) fn name_write_error(e: rusqlite::Error, name: &str) -> AppError {
+    match &e {
+        rusqlite::Error::SqliteFailure(err, _)
+            if err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE =>
+        {
+            AppError::DuplicateName { name: name.to_string() }
+        }
+        _ => e.into(),
+    }
+}
+
+fn collection_not_found(collection_id: i64) -> AppError {
+    AppError::not_found(format!("Collection {} not found", collection_id))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Collection {
+    pub id: i64,
+    pub name: String,
+    pub created_at: String,
+    pub rules: Option<SmartRule>, // Set for smart collections
+    pub parent_id: Option<i64>,   // Containing collection set, None at the top level
+    pub sequence: i64,            // Manual order among siblings
+    pub description: Option<String>,
+    pub cover_photo_id: Option<i64>,
+    pub updated_at: String,
+    pub photo_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PhotoCollection {
+    pub photo_id: i64,
+    pub collection_id: i64,
+    pub sequence: i64, // Manual order within the collection
+}
+
+impl DatabaseService {
+    fn collection_from_row(row: &Row) -> SqlResult<Collection> {
+        let rules: Option<String> = row.get(3)?;
+        Ok(Collection {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_at: row.get(2)?,
+            rules: rules.map(|r| Self::rules_from_json(&r)).transpose()?,
+            parent_id: row.get(4)?,
+            sequence: row.get(5)?,
+            description: row.get(6)?,
+            cover_photo_id: row.get(7)?,
+            updated_at: row.get(8)?,
+            photo_count: row.get(9)?,
+        })
+    }
+
+    pub fn create_collection(&self, name: &str, parent_id: Option<i64>) -> AppResult<i64> {
+        self.conn.execute(
+            "INSERT INTO collections (name, parent_id, sequence, updated_at)
+             VALUES (?1, ?2, (SELECT COALESCE(MAX(sequence), -1) + 1 FROM collections WHERE parent_id IS ?2), datetime('now'))",
+            params![name, parent_id],
+        )
+        .map_err(|e| name_write_error(e, name))?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn get_collection(&self, collection_id: i64) -> AppResult<Collection> {
+        let mut collection = self.conn.query_row(
+            &format!("SELECT {} FROM collections c WHERE c.id = ?1", COLLECTION_COLUMNS),
+            params![collection_id],
+            Self::collection_from_row,
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => collection_not_found(collection_id),
+            e => e.into(),
+        })?;
+
+        if let Some(rules) = &collection.rules {
+            collection.photo_count = self.count_photos_matching_rule(rules)?;
+        }
+        Ok(collection)
+    }
+
+    pub fn rename_collection(&self, collection_id: i64, name: &str) -> AppResult<()> {
+        let updated = self.conn.execute(
+            "UPDATE collections SET name = ?1, updated_at = datetime('now') WHERE id = ?2",
+            params![name, collection_id],
+        )
+        .map_err(|e| name_write_error(e, name))?;
+
+        if updated == 0 {
+            return Err(collection_not_found(collection_id));
+        }
+        Ok(())
+    }
+
+    pub fn set_collection_description(&self, collection_id: i64, description: Option<&str>) -> AppResult<()> {
+        self.update_collection_field(
+            collection_id,
+            "UPDATE collections SET description = ?1, updated_at = datetime('now') WHERE id = ?2",
+            description,
+        )
+    }
+
+    // `None` clears the cover so the UI falls back to the first photo
+    pub fn set_collection_cover(&self, collection_id: i64, photo_id: Option<i64>) -> AppResult<()> {
+        self.update_collection_field(
+            collection_id,
+            "UPDATE collections SET cover_photo_id = ?1, updated_at = datetime('now') WHERE id = ?2",
+            photo_id,
+        )
+    }
+
+    fn update_collection_field(&self, collection_id: i64, sql: &str, value: impl rusqlite::ToSql) -> AppResult<()> {
+        let updated = self.conn.execute(sql, params![value, collection_id])?;
+        if updated == 0 {
+            return Err(collection_not_found(collection_id));
+        }
+        Ok(())
+    }
+
+    // Flat list in tree order; the hierarchy is given by `parent_id`
+    pub fn get_all_collections(&self) -> SqlResult<Vec<Collection>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM collections c ORDER BY c.parent_id, c.sequence, c.id",
+            COLLECTION_COLUMNS
+        ))?;
+
+        let mut collections = stmt.query_map([], Self::collection_from_row)?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        // Smart collection membership is evaluated, not stored
+        for collection in collections.iter_mut() {
+            if let Some(rules) = &collection.rules {
+                collection.photo_count = self.count_photos_matching_rule(rules)?;
+            }
+        }
+
+        Ok(collections)
+    }
+
+    pub fn add_photo_to_collection(&self, photo_id: i64, collection_id: i64) -> SqlResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let action_id = self.begin_edit_action("Add to collection")?;
+        self.set_membership(action_id, photo_id, collection_id, true)?;
+        self.finish_edit_action(action_id)?;
+        tx.commit()
+    }
+
+    pub fn remove_photo_from_collection(&self, photo_id: i64, collection_id: i64) -> SqlResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let action_id = self.begin_edit_action("Remove from collection")?;
+        self.set_membership(action_id, photo_id, collection_id, false)?;
+        self.finish_edit_action(action_id)?;
+        tx.commit()
+    }
+
+    pub fn get_photos_in_collection(&self, collection_id: i64) -> SqlResult<Vec<Photo>> {
+        if let Some(rules) = self.get_collection_rules(collection_id)? {
+            return self.get_photos_matching_rule(&rules);
+        }
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}
+             FROM photos p
+             INNER JOIN photo_collections pc ON p.id = pc.photo_id
+             WHERE pc.collection_id = ?1
+             ORDER BY pc.sequence, p.added_at DESC",
+            PHOTO_COLUMNS
+        ))?;
+
+        let photos = stmt.query_map(params![collection_id], Self::photo_from_row)?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(photos)
+    }
+
+    pub fn get_all_photo_collections(&self) -> SqlResult<Vec<PhotoCollection>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT photo_id, collection_id, sequence FROM photo_collections ORDER BY collection_id, sequence"
+        )?;
+
+        let photo_collections = stmt.query_map([], |row| {
+            Ok(PhotoCollection {
+                photo_id: row.get(0)?,
+                collection_id: row.get(1)?,
+                sequence: row.get(2)?,
+            })
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(photo_collections)
+    }
+
+    fn child_collection_ids(&self, parent_id: Option<i64>) -> SqlResult<Vec<i64>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM collections WHERE parent_id IS ?1 ORDER BY sequence, id"
+        )?;
+
+        let ids = stmt.query_map(params![parent_id], |row| row.get(0))?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(ids)
+    }
+
+    // The collection itself plus everything nested below it
+    fn collection_subtree_ids(&self, collection_id: i64) -> SqlResult<Vec<i64>> {
+        let mut stmt = self.conn.prepare(
+            "WITH RECURSIVE subtree(id) AS (
+                 SELECT ?1
+                 UNION
+                 SELECT c.id FROM collections c INNER JOIN subtree s ON c.parent_id = s.id
+             )
+             SELECT id FROM subtree"
+        )?;
+
+        let ids = stmt.query_map(params![collection_id], |row| row.get(0))?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(ids)
+    }
+
+    // Moves a collection under `new_parent_id` (None = top level) at `position`
+    // among its new siblings (None = last)
+    pub fn move_collection(
+        &self,
+        collection_id: i64,
+        new_parent_id: Option<i64>,
+        position: Option<usize>,
+    ) -> SqlResult<()> {
+        if let Some(parent_id) = new_parent_id {
+            if self.collection_subtree_ids(collection_id)?.contains(&parent_id) {
+                return Err(constraint_error("Cannot move a collection into itself or its descendants"));
+            }
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+
+        let mut siblings: Vec<i64> = self
+            .child_collection_ids(new_parent_id)?
+            .into_iter()
+            .filter(|&id| id != collection_id)
+            .collect();
+        let position = position.unwrap_or(siblings.len()).min(siblings.len());
+        siblings.insert(position, collection_id);
+
+        tx.execute(
+            "UPDATE collections SET parent_id = ?1 WHERE id = ?2",
+            params![new_parent_id, collection_id],
+        )?;
+        for (sequence, id) in siblings.iter().enumerate() {
+            tx.execute(
+                "UPDATE collections SET sequence = ?1 WHERE id = ?2",
+                params![sequence as i64, id],
+            )?;
+        }
+
+        tx.commit()
+    }
+
+    // Applies a drag-and-drop order to the children of `parent_id`. Children
+    // missing from `collection_ids` keep their relative order after the listed ones.
+    pub fn reorder_collections(&self, parent_id: Option<i64>, collection_ids: &[i64]) -> SqlResult<()> {
+        let current = self.child_collection_ids(parent_id)?;
+        let order: Vec<i64> = collection_ids
+            .iter()
+            .copied()
+            .filter(|id| current.contains(id))
+            .chain(current.iter().copied().filter(|id| !collection_ids.contains(id)))
+            .collect();
+
+        let tx = self.conn.unchecked_transaction()?;
+        for (sequence, id) in order.iter().enumerate() {
+            tx.execute(
+                "UPDATE collections SET sequence = ?1 WHERE id = ?2",
+                params![sequence as i64, id],
+            )?;
+        }
+        tx.commit()
+    }
+
+    // Applies a drag-and-drop order to the photos of a manual collection. Photos
+    // missing from `photo_ids` keep their relative order after the listed ones.
+    pub fn reorder_collection_photos(&self, collection_id: i64, photo_ids: &[i64]) -> SqlResult<()> {
+        let mut stmt = self.conn.prepare(
+            "SELECT photo_id FROM photo_collections WHERE collection_id = ?1 ORDER BY sequence, photo_id"
+        )?;
+        let current: Vec<i64> = stmt.query_map(params![collection_id], |row| row.get(0))?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        let order: Vec<i64> = photo_ids
+            .iter()
+            .copied()
+            .filter(|id| current.contains(id))
+            .chain(current.iter().copied().filter(|id| !photo_ids.contains(id)))
+            .collect();
+
+        let tx = self.conn.unchecked_transaction()?;
+        for (sequence, photo_id) in order.iter().enumerate() {
+            tx.execute(
+                "UPDATE photo_collections SET sequence = ?1 WHERE collection_id = ?2 AND photo_id = ?3",
+                params![sequence as i64, collection_id, photo_id],
+            )?;
+        }
+        tx.execute(
+            "UPDATE collections SET updated_at = datetime('now') WHERE id = ?1",
+            params![collection_id],
+        )?;
+        tx.commit()
+    }
+
+    // A collection set with children is only deleted when `cascade` is set, in
+    // which case everything nested below it goes too
+    pub fn delete_collection(&self, collection_id: i64, cascade: bool) -> AppResult<()> {
+        let ids = self.collection_subtree_ids(collection_id)?;
+        if ids.len() > 1 && !cascade {
+            return Err(AppError::Conflict { message: "Collection set is not empty".to_string() });
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        for id in &ids {
+            tx.execute("DELETE FROM photo_collections WHERE collection_id = ?1", params![id])?;
+        }
+        // Deepest first, so no row ever points at a deleted parent
+        for id in ids.iter().rev() {
+            let deleted = tx.execute("DELETE FROM collections WHERE id = ?1", params![id])?;
+            if deleted == 0 {
+                return Err(collection_not_found(collection_id));
+            }
+        }
+        Ok(tx.commit()?)
+    }
+}
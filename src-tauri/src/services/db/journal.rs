@@ -0,0 +1,505 @@
+use rusqlite::types::{Type, Value, ValueRef};
+use rusqlite::{OptionalExtension, Result as SqlResult, Row, params, params_from_iter};
+use serde::{Deserialize, Serialize};
+use super::DatabaseService;
+
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+// One user action (a command call) in the undo history
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EditAction {
+    pub id: i64,
+    pub label: String,
+    pub created_at: String,
+    pub undone: bool,
+    pub photo_ids: Vec<i64>, // Photos touched by the action, so the UI can refresh them
+}
+
+// Metadata fields covered by undo, stored as JSON in `edit_journal`
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct MetadataSnapshot {
+    rating: i32,
+    is_favorite: bool,
+    tags: String,
+    description: Option<String>,
+    #[serde(default = "default_flag")]
+    flag: String,
+    #[serde(default)]
+    color_label: Option<String>,
+}
+
+fn default_flag() -> String {
+    "none".to_string()
+}
+
+// A column value of a catalog row, kept to insert the row again
+#[derive(Debug, Serialize, Deserialize, Clone)]
+enum StoredValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl From<ValueRef<'_>> for StoredValue {
+    fn from(value: ValueRef) -> Self {
+        match value {
+            ValueRef::Null => StoredValue::Null,
+            ValueRef::Integer(i) => StoredValue::Integer(i),
+            ValueRef::Real(f) => StoredValue::Real(f),
+            ValueRef::Text(t) => StoredValue::Text(String::from_utf8_lossy(t).into_owned()),
+            ValueRef::Blob(b) => StoredValue::Blob(b.to_vec()),
+        }
+    }
+}
+
+impl From<&StoredValue> for Value {
+    fn from(value: &StoredValue) -> Self {
+        match value {
+            StoredValue::Null => Value::Null,
+            StoredValue::Integer(i) => Value::Integer(*i),
+            StoredValue::Real(f) => Value::Real(*f),
+            StoredValue::Text(t) => Value::Text(t.clone()),
+            StoredValue::Blob(b) => Value::Blob(b.clone()),
+        }
+    }
+}
+
+// (column, value) pairs of one row
+type StoredRow = Vec<(String, StoredValue)>;
+
+// Value of a `kind = 'photo'` journal entry: the photo's rows as they were
+// before it was removed, or the removal itself. Its memberships are
+// journaled as membership entries.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum PhotoRecord {
+    Present {
+        photo: StoredRow,
+        exif: Option<StoredRow>,
+        cover_of: Vec<i64>, // Collections it was the cover of
+    },
+    Removed {
+        replaced_by: Option<i64>, // Photo that takes over as cover
+    },
+}
+
+const PHOTO_ENTRY: &str = "photo";
+
+// A journal entry restores a metadata snapshot (`collection_id` NULL), a
+// collection membership, where the value is the photo's sequence in the
+// collection and NULL means "not a member", or with `kind` 'photo' a
+// `PhotoRecord`
+struct JournalEntry {
+    photo_id: i64,
+    collection_id: Option<i64>,
+    kind: Option<String>,
+    value: Option<String>,
+}
+
+fn json_error(e: serde_json::Error) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e))
+}
+
+impl DatabaseService {
+    fn metadata_snapshot(&self, photo_id: i64) -> SqlResult<MetadataSnapshot> {
+        self.conn.query_row(
+            "SELECT rating, is_favorite, tags, description, flag, color_label FROM photos WHERE id = ?1",
+            params![photo_id],
+            |row| {
+                Ok(MetadataSnapshot {
+                    rating: row.get(0)?,
+                    is_favorite: row.get::<_, i32>(1)? != 0,
+                    tags: row.get(2)?,
+                    description: row.get(3)?,
+                    flag: row.get(4)?,
+                    color_label: row.get(5)?,
+                })
+            },
+        )
+    }
+
+    // Starts an action; must run inside the transaction that makes the edits
+    pub(super) fn begin_edit_action(&self, label: &str) -> SqlResult<i64> {
+        self.conn.execute("INSERT INTO edit_actions (label) VALUES (?1)", params![label])?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    // Drops the action if it changed nothing, otherwise discards the redo history
+    // it supersedes
+    pub(super) fn finish_edit_action(&self, action_id: i64) -> SqlResult<()> {
+        let entries: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM edit_journal WHERE action_id = ?1",
+            params![action_id],
+            |row| row.get(0),
+        )?;
+
+        if entries == 0 {
+            self.conn.execute("DELETE FROM edit_actions WHERE id = ?1", params![action_id])?;
+        } else {
+            self.conn.execute(
+                "DELETE FROM edit_journal WHERE action_id IN (SELECT id FROM edit_actions WHERE undone = 1)",
+                [],
+            )?;
+            self.conn.execute("DELETE FROM edit_actions WHERE undone = 1", [])?;
+        }
+        Ok(())
+    }
+
+    // Runs `edit`, which changes the metadata of `photo_id`, and journals the change
+    pub(super) fn journal_metadata_edit(
+        &self,
+        action_id: i64,
+        photo_id: i64,
+        edit: impl FnOnce() -> SqlResult<()>,
+    ) -> SqlResult<()> {
+        let before = self.metadata_snapshot(photo_id)?;
+        edit()?;
+        let after = self.metadata_snapshot(photo_id)?;
+
+        if before != after {
+            self.conn.execute(
+                "INSERT INTO edit_journal (action_id, photo_id, before_value, after_value) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    action_id,
+                    photo_id,
+                    serde_json::to_string(&before).map_err(json_error)?,
+                    serde_json::to_string(&after).map_err(json_error)?,
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn membership_sequence(&self, photo_id: i64, collection_id: i64) -> SqlResult<Option<i64>> {
+        self.conn.query_row(
+            "SELECT sequence FROM photo_collections WHERE photo_id = ?1 AND collection_id = ?2",
+            params![photo_id, collection_id],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    // Adds or removes a photo from a collection and journals the change
+    pub(super) fn set_membership(
+        &self,
+        action_id: i64,
+        photo_id: i64,
+        collection_id: i64,
+        member: bool,
+    ) -> SqlResult<()> {
+        let before = self.membership_sequence(photo_id, collection_id)?;
+        if before.is_some() == member {
+            return Ok(());
+        }
+
+        if member {
+            self.conn.execute(
+                "INSERT INTO photo_collections (photo_id, collection_id, sequence)
+                 VALUES (?1, ?2, (SELECT COALESCE(MAX(sequence), -1) + 1 FROM photo_collections WHERE collection_id = ?2))",
+                params![photo_id, collection_id],
+            )?;
+        } else {
+            self.conn.execute(
+                "DELETE FROM photo_collections WHERE photo_id = ?1 AND collection_id = ?2",
+                params![photo_id, collection_id],
+            )?;
+        }
+        let after = self.membership_sequence(photo_id, collection_id)?;
+
+        self.conn.execute(
+            "INSERT INTO edit_journal (action_id, photo_id, collection_id, before_value, after_value)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                action_id,
+                photo_id,
+                collection_id,
+                before.map(|s| s.to_string()),
+                after.map(|s| s.to_string()),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn stored_row(&self, table: &str, key_column: &str, id: i64) -> SqlResult<Option<StoredRow>> {
+        let mut stmt = self.conn.prepare(&format!("SELECT * FROM {} WHERE {} = ?1", table, key_column))?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+
+        stmt.query_row(params![id], |row| {
+            columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| Ok((column.clone(), StoredValue::from(row.get_ref(i)?))))
+                .collect()
+        })
+        .optional()
+    }
+
+    fn insert_stored_row(&self, table: &str, row: &StoredRow) -> SqlResult<()> {
+        let columns: Vec<String> = row.iter().map(|(column, _)| format!("\"{}\"", column)).collect();
+        let placeholders = vec!["?"; row.len()].join(", ");
+        self.conn.execute(
+            &format!("INSERT INTO {} ({}) VALUES ({})", table, columns.join(", "), placeholders),
+            params_from_iter(row.iter().map(|(_, value)| Value::from(value))),
+        )?;
+        Ok(())
+    }
+
+    // Removes a photo from the catalog and journals it so undo can bring it
+    // back with its EXIF data, search data and covers. Collections it was the
+    // cover of show `replaced_by` instead, if given.
+    pub(super) fn remove_photo_journaled(
+        &self,
+        action_id: i64,
+        photo_id: i64,
+        replaced_by: Option<i64>,
+    ) -> SqlResult<()> {
+        let photo = self.stored_row("photos", "id", photo_id)?
+            .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        let exif = self.stored_row("photo_exif", "photo_id", photo_id)?;
+        let mut stmt = self.conn.prepare("SELECT id FROM collections WHERE cover_photo_id = ?1")?;
+        let cover_of = stmt.query_map(params![photo_id], |row| row.get(0))?
+            .collect::<SqlResult<Vec<i64>>>()?;
+
+        let before = PhotoRecord::Present { photo, exif, cover_of };
+        let after = PhotoRecord::Removed { replaced_by };
+        self.apply_photo_record(photo_id, &after)?;
+
+        self.conn.execute(
+            "INSERT INTO edit_journal (action_id, photo_id, kind, before_value, after_value) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                action_id,
+                photo_id,
+                PHOTO_ENTRY,
+                serde_json::to_string(&before).map_err(json_error)?,
+                serde_json::to_string(&after).map_err(json_error)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn apply_photo_record(&self, photo_id: i64, record: &PhotoRecord) -> SqlResult<()> {
+        match record {
+            PhotoRecord::Removed { replaced_by } => {
+                let Some(stack_id) = self.conn.query_row(
+                    "SELECT stack_id FROM photos WHERE id = ?1",
+                    params![photo_id],
+                    |row| row.get::<_, Option<i64>>(0),
+                )
+                .optional()? else {
+                    return Ok(()); // Already gone
+                };
+
+                if let Some(replaced_by) = replaced_by {
+                    self.conn.execute(
+                        "UPDATE collections SET cover_photo_id = ?1 WHERE cover_photo_id = ?2",
+                        params![replaced_by, photo_id],
+                    )?;
+                }
+                self.conn.execute("DELETE FROM photos WHERE id = ?1", params![photo_id])?;
+                if let Some(stack_id) = stack_id {
+                    self.repair_stack(stack_id)?;
+                }
+            }
+            PhotoRecord::Present { photo, exif, cover_of } => {
+                // Its stack may have been dissolved since
+                let stack_exists = |stack_id: i64| -> SqlResult<bool> {
+                    self.conn.query_row(
+                        "SELECT EXISTS (SELECT 1 FROM stacks WHERE id = ?1)",
+                        params![stack_id],
+                        |row| row.get(0),
+                    )
+                };
+                let mut photo = photo.clone();
+                for (column, value) in photo.iter_mut() {
+                    if let ("stack_id", StoredValue::Integer(stack_id)) = (column.as_str(), &*value) {
+                        if !stack_exists(*stack_id)? {
+                            *value = StoredValue::Null;
+                        }
+                    }
+                }
+
+                self.insert_stored_row("photos", &photo)?;
+                if let Some(exif) = exif {
+                    self.insert_stored_row("photo_exif", exif)?;
+                }
+                for collection_id in cover_of {
+                    self.conn.execute(
+                        "UPDATE collections SET cover_photo_id = ?1 WHERE id = ?2",
+                        params![photo_id, collection_id],
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_journal_entry(&self, entry: &JournalEntry) -> SqlResult<()> {
+        if entry.kind.as_deref() == Some(PHOTO_ENTRY) {
+            let Some(value) = &entry.value else { return Ok(()) };
+            let record: PhotoRecord = serde_json::from_str(value).map_err(json_error)?;
+            return self.apply_photo_record(entry.photo_id, &record);
+        }
+
+        match entry.collection_id {
+            None => {
+                let Some(value) = &entry.value else { return Ok(()) };
+                let snapshot: MetadataSnapshot = serde_json::from_str(value).map_err(json_error)?;
+                self.conn.execute(
+                    "UPDATE photos SET rating = ?1, is_favorite = ?2, tags = ?3, description = ?4, flag = ?5, color_label = ?6
+                     WHERE id = ?7",
+                    params![
+                        snapshot.rating,
+                        snapshot.is_favorite as i32,
+                        snapshot.tags,
+                        snapshot.description,
+                        snapshot.flag,
+                        snapshot.color_label,
+                        entry.photo_id,
+                    ],
+                )?;
+            }
+            Some(collection_id) => {
+                self.conn.execute(
+                    "DELETE FROM photo_collections WHERE photo_id = ?1 AND collection_id = ?2",
+                    params![entry.photo_id, collection_id],
+                )?;
+                if let Some(sequence) = entry.value.as_deref().and_then(|v| v.parse::<i64>().ok()) {
+                    // The photo or collection may have been deleted since
+                    self.conn.execute(
+                        "INSERT INTO photo_collections (photo_id, collection_id, sequence)
+                         SELECT ?1, ?2, ?3
+                         WHERE EXISTS (SELECT 1 FROM photos WHERE id = ?1)
+                           AND EXISTS (SELECT 1 FROM collections WHERE id = ?2)",
+                        params![entry.photo_id, collection_id, sequence],
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Restores the journaled values of one action, `before` values for undo and
+    // `after` values for redo
+    fn replay_edit_action(&self, action_id: i64, undo: bool) -> SqlResult<EditAction> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        let (column, order) = if undo { ("before_value", "DESC") } else { ("after_value", "ASC") };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT photo_id, collection_id, kind, {} FROM edit_journal WHERE action_id = ?1 ORDER BY id {}",
+            column, order
+        ))?;
+        let entries = stmt.query_map(params![action_id], |row| {
+            Ok(JournalEntry {
+                photo_id: row.get(0)?,
+                collection_id: row.get(1)?,
+                kind: row.get(2)?,
+                value: row.get(3)?,
+            })
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+        for entry in &entries {
+            self.apply_journal_entry(entry)?;
+        }
+        tx.execute(
+            "UPDATE edit_actions SET undone = ?1 WHERE id = ?2",
+            params![undo as i32, action_id],
+        )?;
+
+        tx.commit()?;
+        self.get_edit_action(action_id)
+    }
+
+    // Reverts the most recent action that has not been undone yet
+    pub fn undo(&self) -> SqlResult<Option<EditAction>> {
+        let action_id: Option<i64> = self.conn.query_row(
+            "SELECT MAX(id) FROM edit_actions WHERE undone = 0",
+            [],
+            |row| row.get(0),
+        )?;
+        action_id.map(|id| self.replay_edit_action(id, true)).transpose()
+    }
+
+    // Re-applies the earliest undone action
+    pub fn redo(&self) -> SqlResult<Option<EditAction>> {
+        let action_id: Option<i64> = self.conn.query_row(
+            "SELECT MIN(id) FROM edit_actions WHERE undone = 1",
+            [],
+            |row| row.get(0),
+        )?;
+        action_id.map(|id| self.replay_edit_action(id, false)).transpose()
+    }
+
+    fn edit_action_from_row(&self, row: &Row) -> SqlResult<EditAction> {
+        let id: i64 = row.get(0)?;
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT DISTINCT photo_id FROM edit_journal WHERE action_id = ?1 ORDER BY photo_id"
+        )?;
+        let photo_ids = stmt.query_map(params![id], |row| row.get(0))?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(EditAction {
+            id,
+            label: row.get(1)?,
+            created_at: row.get(2)?,
+            undone: row.get::<_, i32>(3)? != 0,
+            photo_ids,
+        })
+    }
+
+    fn get_edit_action(&self, action_id: i64) -> SqlResult<EditAction> {
+        self.conn.query_row(
+            "SELECT id, label, created_at, undone FROM edit_actions WHERE id = ?1",
+            params![action_id],
+            |row| self.edit_action_from_row(row),
+        )
+    }
+
+    // Most recent first
+    pub fn get_edit_history(&self, limit: i64) -> SqlResult<Vec<EditAction>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, label, created_at, undone FROM edit_actions ORDER BY id DESC LIMIT ?1"
+        )?;
+
+        let actions = stmt.query_map(params![limit], |row| self.edit_action_from_row(row))?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(actions)
+    }
+
+    pub fn get_edit_history_retention(&self) -> SqlResult<i64> {
+        let value: Option<String> = self.conn.query_row(
+            "SELECT value FROM app_metadata WHERE key = 'edit_history_retention_days'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+        Ok(value.and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_RETENTION_DAYS))
+    }
+
+    pub fn set_edit_history_retention(&self, days: i64) -> SqlResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO app_metadata (key, value) VALUES ('edit_history_retention_days', ?1)",
+            params![days.max(0).to_string()],
+        )?;
+        self.prune_edit_history()
+    }
+
+    // Forgets actions older than the retention period
+    pub(super) fn prune_edit_history(&self) -> SqlResult<()> {
+        let cutoff = format!("-{} days", self.get_edit_history_retention()?);
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM edit_journal WHERE action_id IN
+                 (SELECT id FROM edit_actions WHERE created_at < datetime('now', ?1))",
+            params![cutoff],
+        )?;
+        tx.execute(
+            "DELETE FROM edit_actions WHERE created_at < datetime('now', ?1)",
+            params![cutoff],
+        )?;
+        tx.commit()
+    }
+}
@@ -0,0 +1,85 @@
+use std::path::Path;
+use rusqlite::{Result as SqlResult, params};
+use serde::{Deserialize, Serialize};
+use super::{DatabaseService, Photo, PHOTO_COLUMNS};
+
+// New location of a photo whose file was moved outside the app
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PhotoRelocation {
+    pub photo_id: i64,
+    pub path: String,
+    pub motion_video_path: Option<String>,
+}
+
+// Where the app moved or copied a photo's files, for the catalog to record
+#[derive(Debug, Clone)]
+pub struct PhotoLocation {
+    pub photo_id: i64,
+    pub path: String,
+    pub filename: String,
+    pub motion_video_path: Option<String>,
+    pub thumbnail_path: Option<String>,
+}
+
+impl DatabaseService {
+    // Photos whose file lives under `folder`. Paths are compared by component,
+    // so /photos does not match /photos2.
+    pub fn get_photos_under(&self, folder: &Path) -> SqlResult<Vec<Photo>> {
+        let prefix = folder.to_string_lossy();
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM photos p WHERE substr(p.path, 1, length(?1)) = ?1 ORDER BY p.path",
+            PHOTO_COLUMNS
+        ))?;
+
+        let photos = stmt.query_map(params![prefix], Self::photo_from_row)?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(photos.into_iter().filter(|p| Path::new(&p.path).starts_with(folder)).collect())
+    }
+
+    // Photos directly in `folder`, or with `recursive` also those in its subfolders
+    pub fn get_photos_in_folder(&self, folder: &Path, recursive: bool) -> SqlResult<Vec<Photo>> {
+        let mut photos = self.get_photos_under(folder)?;
+        if !recursive {
+            photos.retain(|p| Path::new(&p.path).parent() == Some(folder));
+        }
+        Ok(photos)
+    }
+
+    // Rewrites the paths of all `relocations` in one transaction; if any new
+    // path is already taken nothing changes
+    pub fn relocate_photos(&self, relocations: &[PhotoRelocation]) -> SqlResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        for relocation in relocations {
+            let filename = Path::new(&relocation.path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| relocation.path.clone());
+            tx.execute(
+                "UPDATE photos SET path = ?1, filename = ?2, motion_video_path = ?3 WHERE id = ?4",
+                params![relocation.path, filename, relocation.motion_video_path, relocation.photo_id],
+            )?;
+        }
+
+        tx.commit()
+    }
+
+    // Points every photo in `locations` at its new files in one transaction;
+    // if any update fails nothing changes
+    pub fn update_photo_locations(&self, locations: &[PhotoLocation]) -> SqlResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        for location in locations {
+            self.update_photo_location(
+                location.photo_id,
+                &location.path,
+                &location.filename,
+                location.motion_video_path.as_deref(),
+                location.thumbnail_path.as_deref(),
+            )?;
+        }
+
+        tx.commit()
+    }
+}
@@ -0,0 +1,143 @@
+use rusqlite::Result as SqlResult;
+use serde::{Deserialize, Serialize};
+use log::info;
+use super::{DatabaseService, Photo};
+
+// What `run_library_maintenance` should fix; by default it only reports
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(default)]
+pub struct MaintenanceOptions {
+    pub fix_orphaned_rows: bool,
+    pub delete_dangling_thumbnails: bool,
+    pub remove_missing_photos: bool, // Offline photos are never removed
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct MaintenanceReport {
+    pub orphaned_rows: Vec<OrphanedRows>,
+    pub dangling_thumbnails: Vec<String>, // Cached thumbnails no photo uses
+    pub missing_photos: Vec<Photo>,       // Photos whose file is gone
+}
+
+// Rows of one kind that point at rows which no longer exist, left behind
+// by catalogs written while foreign keys were not enforced
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrphanedRows {
+    pub table: String,
+    pub problem: String,
+    pub count: i64,
+}
+
+enum OrphanFix {
+    Delete,
+    Clear(&'static str), // Column to set to NULL
+}
+
+struct OrphanCheck {
+    table: &'static str,
+    problem: &'static str,
+    condition: &'static str,
+    fix: OrphanFix,
+}
+
+const ORPHAN_CHECKS: &[OrphanCheck] = &[
+    OrphanCheck {
+        table: "photo_collections",
+        problem: "Membership of a deleted photo or collection",
+        condition: "photo_id NOT IN (SELECT id FROM photos) OR collection_id NOT IN (SELECT id FROM collections)",
+        fix: OrphanFix::Delete,
+    },
+    OrphanCheck {
+        table: "photo_exif",
+        problem: "EXIF data of a deleted photo",
+        condition: "photo_id NOT IN (SELECT id FROM photos)",
+        fix: OrphanFix::Delete,
+    },
+    OrphanCheck {
+        table: "pending_writes",
+        problem: "Queued XMP write for a deleted photo",
+        condition: "photo_id NOT IN (SELECT id FROM photos)",
+        fix: OrphanFix::Delete,
+    },
+    OrphanCheck {
+        table: "edit_journal",
+        problem: "Undo entry of a deleted edit",
+        condition: "action_id NOT IN (SELECT id FROM edit_actions)",
+        fix: OrphanFix::Delete,
+    },
+    OrphanCheck {
+        table: "photos",
+        problem: "Photo in a deleted stack",
+        condition: "stack_id IS NOT NULL AND stack_id NOT IN (SELECT id FROM stacks)",
+        fix: OrphanFix::Clear("stack_id"),
+    },
+    OrphanCheck {
+        table: "stacks",
+        problem: "Stack without photos",
+        condition: "id NOT IN (SELECT stack_id FROM photos WHERE stack_id IS NOT NULL)",
+        fix: OrphanFix::Delete,
+    },
+    OrphanCheck {
+        table: "stacks",
+        problem: "Stack of a single photo",
+        condition: "(SELECT COUNT(*) FROM photos WHERE stack_id = stacks.id) = 1",
+        fix: OrphanFix::Delete,
+    },
+    OrphanCheck {
+        table: "stacks",
+        problem: "Stack top that was deleted",
+        condition: "top_photo_id IS NOT NULL AND top_photo_id NOT IN (SELECT id FROM photos)",
+        fix: OrphanFix::Clear("top_photo_id"),
+    },
+    OrphanCheck {
+        table: "collections",
+        problem: "Cover photo that was deleted",
+        condition: "cover_photo_id IS NOT NULL AND cover_photo_id NOT IN (SELECT id FROM photos)",
+        fix: OrphanFix::Clear("cover_photo_id"),
+    },
+    OrphanCheck {
+        table: "collections",
+        problem: "Collection in a deleted set",
+        condition: "parent_id IS NOT NULL AND parent_id NOT IN (SELECT id FROM collections)",
+        fix: OrphanFix::Clear("parent_id"),
+    },
+];
+
+impl DatabaseService {
+    // Counts each kind of orphaned row. With `fix` they are deleted, or their
+    // dangling reference cleared, in the same transaction.
+    pub fn find_orphaned_rows(&self, fix: bool) -> SqlResult<Vec<OrphanedRows>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut found = Vec::new();
+
+        for check in ORPHAN_CHECKS {
+            let count: i64 = tx.query_row(
+                &format!("SELECT COUNT(*) FROM {} WHERE {}", check.table, check.condition),
+                [],
+                |row| row.get(0),
+            )?;
+            if count == 0 {
+                continue;
+            }
+
+            if fix {
+                let sql = match check.fix {
+                    OrphanFix::Delete => format!("DELETE FROM {} WHERE {}", check.table, check.condition),
+                    OrphanFix::Clear(column) => {
+                        format!("UPDATE {} SET {} = NULL WHERE {}", check.table, column, check.condition)
+                    }
+                };
+                tx.execute(&sql, [])?;
+                info!("Fixed {} orphaned rows in {}: {}", count, check.table, check.problem);
+            }
+            found.push(OrphanedRows {
+                table: check.table.to_string(),
+                problem: check.problem.to_string(),
+                count,
+            });
+        }
+
+        tx.commit()?;
+        Ok(found)
+    }
+}
@@ -1,9 +1,177 @@
-use rusqlite::{Connection, Result as SqlResult, params};
+use rusqlite::{Connection, OptionalExtension, Result as SqlResult, Row, params};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use log::{info, error};
 
-const SCHEMA_VERSION: i32 = 1;
+mod backup;
+mod batch;
+mod collections;
+mod journal;
+mod locations;
+mod maintenance;
+mod photo_exif;
+mod pool;
+mod recovery;
+mod roots;
+mod smart;
+mod stacks;
+mod stats;
+
+pub use backup::{
+    BackupInfo, BackupPolicy, backup_due, create_backup, latest_backup, library_files, list_backups, prune_backups,
+    restore_backup,
+};
+pub use batch::{BatchFailure, BatchReport, MetadataOp};
+pub use collections::{Collection, PhotoCollection};
+pub use journal::EditAction;
+pub use locations::{PhotoLocation, PhotoRelocation};
+pub use maintenance::{MaintenanceOptions, MaintenanceReport};
+pub use pool::DatabasePool;
+pub use recovery::{
+    DATABASE_FILE, RecoveryAction, StartupState, check_integrity, is_corruption, set_aside, verify_catalog,
+};
+pub use roots::{FolderNode, LibraryRoot, RootStatus};
+pub use smart::SmartRule;
+pub use stacks::Stack;
+pub use stats::{LibraryStats, StatsScope};
+
+// Each entry upgrades the schema by one version, starting from version 1
+const MIGRATIONS: &[&str] = &[
+    // 2: Live Photo / Motion Photo pairing
+    "ALTER TABLE photos ADD COLUMN motion_type TEXT;
+     ALTER TABLE photos ADD COLUMN motion_video_path TEXT;
+     ALTER TABLE photos ADD COLUMN motion_video_offset INTEGER;
+     ALTER TABLE photos ADD COLUMN content_identifier TEXT;
+     CREATE INDEX IF NOT EXISTS idx_photos_content_identifier ON photos(content_identifier);",
+    // 3: Content hash for duplicate detection
+    "ALTER TABLE photos ADD COLUMN content_hash TEXT;
+     CREATE INDEX IF NOT EXISTS idx_photos_content_hash ON photos(content_hash);",
+    // 4: Perceptual hashes for near-duplicate search (u64 bits stored as i64)
+    "ALTER TABLE photos ADD COLUMN dhash INTEGER;
+     ALTER TABLE photos ADD COLUMN phash INTEGER;",
+    // 5: Color histogram (64 bytes) and dominant palette (JSON) for color search
+    "ALTER TABLE photos ADD COLUMN color_histogram BLOB;
+     ALTER TABLE photos ADD COLUMN palette TEXT;",
+    // 6: Persisted EXIF data and auto-stacks of bursts/brackets
+    "CREATE TABLE IF NOT EXISTS photo_exif (
+         photo_id INTEGER PRIMARY KEY,
+         camera_make TEXT,
+         camera_model TEXT,
+         lens_model TEXT,
+         focal_length REAL,
+         aperture REAL,
+         shutter_speed TEXT,
+         iso INTEGER,
+         exposure_bias REAL,
+         exposure_mode INTEGER,
+         flash TEXT,
+         orientation INTEGER,
+         capture_date TEXT,
+         subsec_time TEXT,
+         burst_id TEXT,
+         gps_latitude REAL,
+         gps_longitude REAL,
+         gps_altitude REAL,
+         FOREIGN KEY (photo_id) REFERENCES photos(id) ON DELETE CASCADE
+     );
+     CREATE INDEX IF NOT EXISTS idx_photo_exif_capture_date ON photo_exif(capture_date);
+     CREATE TABLE IF NOT EXISTS stacks (
+         id INTEGER PRIMARY KEY AUTOINCREMENT,
+         top_photo_id INTEGER,
+         kind TEXT NOT NULL,
+         is_collapsed INTEGER NOT NULL DEFAULT 1,
+         created_at TEXT NOT NULL DEFAULT (datetime('now')),
+         FOREIGN KEY (top_photo_id) REFERENCES photos(id) ON DELETE SET NULL
+     );
+     ALTER TABLE photos ADD COLUMN stack_id INTEGER REFERENCES stacks(id) ON DELETE SET NULL;
+     CREATE INDEX IF NOT EXISTS idx_photos_stack_id ON photos(stack_id);",
+    // 7: Smart collections (serialized SmartRule tree, NULL for manual collections)
+    "ALTER TABLE collections ADD COLUMN rules TEXT;",
+    // 8: Collection sets (nesting) and manual ordering of collections and their photos
+    "ALTER TABLE collections ADD COLUMN parent_id INTEGER REFERENCES collections(id);
+     ALTER TABLE collections ADD COLUMN sequence INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE photo_collections ADD COLUMN sequence INTEGER NOT NULL DEFAULT 0;
+     UPDATE collections SET sequence = (SELECT COUNT(*) FROM collections c2 WHERE c2.id < collections.id);
+     UPDATE photo_collections SET sequence = (
+         SELECT COUNT(*) FROM photo_collections pc2
+         WHERE pc2.collection_id = photo_collections.collection_id AND pc2.rowid < photo_collections.rowid
+     );
+     CREATE INDEX IF NOT EXISTS idx_collections_parent_id ON collections(parent_id);",
+    // 9: Collection details plus a photo count and modification time kept current by triggers
+    "ALTER TABLE collections ADD COLUMN description TEXT;
+     ALTER TABLE collections ADD COLUMN cover_photo_id INTEGER REFERENCES photos(id) ON DELETE SET NULL;
+     ALTER TABLE collections ADD COLUMN photo_count INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE collections ADD COLUMN updated_at TEXT;
+     UPDATE collections SET
+         photo_count = (SELECT COUNT(*) FROM photo_collections pc WHERE pc.collection_id = collections.id),
+         updated_at = created_at;
+     CREATE TRIGGER IF NOT EXISTS trg_photo_collections_insert AFTER INSERT ON photo_collections BEGIN
+         UPDATE collections SET photo_count = photo_count + 1, updated_at = datetime('now')
+         WHERE id = NEW.collection_id;
+     END;
+     CREATE TRIGGER IF NOT EXISTS trg_photo_collections_delete AFTER DELETE ON photo_collections BEGIN
+         UPDATE collections SET photo_count = photo_count - 1, updated_at = datetime('now')
+         WHERE id = OLD.collection_id;
+     END;",
+    // 10: Undo/redo journal, one action per user edit with before/after values per photo
+    "CREATE TABLE IF NOT EXISTS edit_actions (
+         id INTEGER PRIMARY KEY AUTOINCREMENT,
+         label TEXT NOT NULL,
+         created_at TEXT NOT NULL DEFAULT (datetime('now')),
+         undone INTEGER NOT NULL DEFAULT 0
+     );
+     CREATE TABLE IF NOT EXISTS edit_journal (
+         id INTEGER PRIMARY KEY AUTOINCREMENT,
+         action_id INTEGER NOT NULL,
+         photo_id INTEGER NOT NULL,
+         collection_id INTEGER,
+         before_value TEXT,
+         after_value TEXT,
+         FOREIGN KEY (action_id) REFERENCES edit_actions(id) ON DELETE CASCADE
+     );
+     CREATE INDEX IF NOT EXISTS idx_edit_journal_action_id ON edit_journal(action_id);",
+    // 11: Pick/reject flags and color labels
+    "ALTER TABLE photos ADD COLUMN flag TEXT NOT NULL DEFAULT 'none'
+         CHECK (flag IN ('none', 'picked', 'rejected'));
+     ALTER TABLE photos ADD COLUMN color_label TEXT
+         CHECK (color_label IN ('red', 'yellow', 'green', 'blue', 'purple'));
+     CREATE INDEX IF NOT EXISTS idx_photos_flag ON photos(flag);
+     CREATE INDEX IF NOT EXISTS idx_photos_color_label ON photos(color_label);",
+    // 12: Folders the library was imported from, for browsing by folder
+    "CREATE TABLE IF NOT EXISTS library_roots (
+         id INTEGER PRIMARY KEY AUTOINCREMENT,
+         path TEXT NOT NULL UNIQUE,
+         added_at TEXT NOT NULL DEFAULT (datetime('now'))
+     );",
+    // 13: Per-root scan rules (serialized ScanOptions, NULL for the defaults)
+    "ALTER TABLE library_roots ADD COLUMN scan_options TEXT;",
+    // 14: Offline roots. Volume identity finds a drive again under another mount
+    // point; label edits made while offline are queued until it is back.
+    "ALTER TABLE library_roots ADD COLUMN volume_id TEXT;
+     ALTER TABLE library_roots ADD COLUMN volume_label TEXT;
+     ALTER TABLE library_roots ADD COLUMN volume_path TEXT;
+     ALTER TABLE library_roots ADD COLUMN is_online INTEGER NOT NULL DEFAULT 1;
+     ALTER TABLE library_roots ADD COLUMN last_seen_at TEXT;
+     ALTER TABLE photos ADD COLUMN is_offline INTEGER NOT NULL DEFAULT 0;
+     CREATE TABLE IF NOT EXISTS pending_writes (
+         photo_id INTEGER PRIMARY KEY REFERENCES photos(id) ON DELETE CASCADE,
+         queued_at TEXT NOT NULL DEFAULT (datetime('now'))
+     );
+     CREATE TRIGGER IF NOT EXISTS trg_photos_offline_edit
+     AFTER UPDATE OF rating, flag, color_label ON photos WHEN NEW.is_offline = 1 BEGIN
+         INSERT OR REPLACE INTO pending_writes (photo_id) VALUES (NEW.id);
+     END;",
+    // 15: Journal entries that remove or restore a whole photo ('photo');
+    // NULL for metadata and membership entries
+    "ALTER TABLE edit_journal ADD COLUMN kind TEXT;",
+];
+
+const SCHEMA_VERSION: i32 = MIGRATIONS.len() as i32 + 1;
+
+const PHOTO_COLUMNS: &str = "p.id, p.path, p.filename, p.file_size, p.width, p.height, p.capture_date, p.added_at, p.rating, p.is_favorite, p.tags, p.description, p.thumbnail_path, \
+     p.motion_type, p.motion_video_path, p.motion_video_offset, p.content_identifier, \
+     p.content_hash, p.stack_id, p.flag, p.color_label, p.is_offline";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Photo {
@@ -20,25 +188,82 @@ pub struct Photo {
     pub tags: String, // JSON array as string
     pub description: Option<String>,
     pub thumbnail_path: Option<String>,
+    pub motion_type: Option<String>, // "live_photo" or "motion_photo"
+    pub motion_video_path: Option<String>,
+    pub motion_video_offset: Option<i64>,
+    pub content_identifier: Option<String>,
+    pub content_hash: Option<String>, // SHA-256 of the file contents
+    pub stack_id: Option<i64>,
+    #[serde(default = "default_flag")]
+    pub flag: String, // "none", "picked" or "rejected"
+    pub color_label: Option<String>, // "red", "yellow", "green", "blue" or "purple"
+    #[serde(default)]
+    pub is_offline: bool, // On a library root whose drive is disconnected
+}
+
+fn default_flag() -> String {
+    "none".to_string()
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Collection {
-    pub id: i64,
-    pub name: String,
-    pub created_at: String,
+pub struct DuplicateGroup {
+    pub content_hash: String,
+    pub photos: Vec<Photo>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct PhotoCollection {
-    pub photo_id: i64,
-    pub collection_id: i64,
+pub struct SimilarPhoto {
+    pub photo: Photo,
+    pub distance: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ColorMatch {
+    pub photo: Photo,
+    pub score: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct MetadataImport {
+    pub updated: i64,
+    pub unmatched_paths: Vec<String>, // Backup entries with no photo at that path
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct DuplicateResolution {
+    pub kept_photo_id: i64,
+    pub removed_photo_ids: Vec<i64>,
+    pub trashed_paths: Vec<String>,
+    pub failed_paths: Vec<String>,
+}
+
+// Rule violations detected in Rust rather than by SQLite itself, reported the
+// same way SQLite reports its own constraint failures
+fn constraint_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT),
+        Some(message.to_string()),
+    )
 }
 
+// How long a statement waits for another connection's write to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub struct DatabaseService {
     conn: Connection,
 }
 
+// Settings every connection to the catalog needs
+fn open_connection(db_path: &Path) -> SqlResult<Connection> {
+    let conn = Connection::open(db_path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    // Not on by default in every SQLite build; without it the ON DELETE
+    // clauses never fire
+    conn.pragma_update(None, "foreign_keys", true)?;
+    Ok(conn)
+}
+
 impl DatabaseService {
     pub fn new(db_path: PathBuf) -> SqlResult<Self> {
         info!("Initializing database at: {:?}", db_path);
@@ -50,14 +275,27 @@ impl DatabaseService {
             })?;
         }
 
-        let conn = Connection::open(&db_path)?;
+        let conn = open_connection(&db_path)?;
+        // Readers see the last commit while a write is in progress; the
+        // setting is stored in the file
+        let journal_mode: String = conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))?;
+        if !journal_mode.eq_ignore_ascii_case("wal") {
+            error!("Database is in {} mode; reads will wait for writes", journal_mode);
+        }
         let service = DatabaseService { conn };
         service.init_schema()?;
+        service.prune_edit_history()?;
+        service.backfill_library_roots()?;
         
         info!("Database initialized successfully");
         Ok(service)
     }
 
+    // Another connection to a catalog already set up by `new`
+    pub(super) fn open_existing(db_path: &Path) -> SqlResult<Self> {
+        Ok(DatabaseService { conn: open_connection(db_path)? })
+    }
+
     fn init_schema(&self) -> SqlResult<()> {
         info!("Creating database schema");
 
@@ -70,21 +308,11 @@ impl DatabaseService {
             [],
         )?;
 
-        // Check and set schema version
-        let version: Option<i32> = self.conn
-            .query_row(
-                "SELECT value FROM app_metadata WHERE key = 'schema_version'",
-                [],
-                |row| row.get(0),
-            )
-            .ok();
-
-        if version.is_none() {
-            self.conn.execute(
-                "INSERT INTO app_metadata (key, value) VALUES ('schema_version', ?1)",
-                params![SCHEMA_VERSION.to_string()],
-            )?;
-        }
+        // Fresh databases start at version 1 and are migrated forward below
+        self.conn.execute(
+            "INSERT OR IGNORE INTO app_metadata (key, value) VALUES ('schema_version', '1')",
+            [],
+        )?;
 
         // Create photos table
         self.conn.execute(
@@ -146,14 +374,72 @@ impl DatabaseService {
             [],
         )?;
 
+        self.migrate()?;
+
         info!("Database schema created successfully");
         Ok(())
     }
 
+    fn schema_version(&self) -> SqlResult<i32> {
+        let value: String = self.conn.query_row(
+            "SELECT value FROM app_metadata WHERE key = 'schema_version'",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(value.parse().unwrap_or(1))
+    }
+
+    fn migrate(&self) -> SqlResult<()> {
+        let mut version = self.schema_version()?;
+
+        while version < SCHEMA_VERSION {
+            info!("Migrating database schema to version {}", version + 1);
+
+            let tx = self.conn.unchecked_transaction()?;
+            tx.execute_batch(MIGRATIONS[(version - 1) as usize])?;
+            version += 1;
+            tx.execute(
+                "UPDATE app_metadata SET value = ?1 WHERE key = 'schema_version'",
+                params![version.to_string()],
+            )?;
+            tx.commit()?;
+        }
+
+        Ok(())
+    }
+
+    fn photo_from_row(row: &Row) -> SqlResult<Photo> {
+        Ok(Photo {
+            id: row.get(0)?,
+            path: row.get(1)?,
+            filename: row.get(2)?,
+            file_size: row.get(3)?,
+            width: row.get(4)?,
+            height: row.get(5)?,
+            capture_date: row.get(6)?,
+            added_at: row.get(7)?,
+            rating: row.get(8)?,
+            is_favorite: row.get::<_, i32>(9)? != 0,
+            tags: row.get(10)?,
+            description: row.get(11)?,
+            thumbnail_path: row.get(12)?,
+            motion_type: row.get(13)?,
+            motion_video_path: row.get(14)?,
+            motion_video_offset: row.get(15)?,
+            content_identifier: row.get(16)?,
+            content_hash: row.get(17)?,
+            stack_id: row.get(18)?,
+            flag: row.get(19)?,
+            color_label: row.get(20)?,
+            is_offline: row.get::<_, i32>(21)? != 0,
+        })
+    }
+
     pub fn insert_photo(&self, photo: &Photo) -> SqlResult<i64> {
         self.conn.execute(
-            "INSERT INTO photos (path, filename, file_size, width, height, capture_date, rating, is_favorite, tags, description, thumbnail_path)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            "INSERT INTO photos (path, filename, file_size, width, height, capture_date, rating, is_favorite, tags, description, thumbnail_path,
+                                 motion_type, motion_video_path, motion_video_offset, content_identifier, content_hash, flag, color_label)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
             params![
                 photo.path,
                 photo.filename,
@@ -166,41 +452,67 @@ impl DatabaseService {
                 photo.tags,
                 photo.description,
                 photo.thumbnail_path,
+                photo.motion_type,
+                photo.motion_video_path,
+                photo.motion_video_offset,
+                photo.content_identifier,
+                photo.content_hash,
+                photo.flag,
+                photo.color_label,
             ],
         )?;
         Ok(self.conn.last_insert_rowid())
     }
 
-    pub fn get_all_photos(&self) -> SqlResult<Vec<Photo>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, path, filename, file_size, width, height, capture_date, added_at, rating, is_favorite, tags, description, thumbnail_path
-             FROM photos
-             ORDER BY added_at DESC"
-        )?;
-
-        let photos = stmt.query_map([], |row| {
-            Ok(Photo {
-                id: row.get(0)?,
-                path: row.get(1)?,
-                filename: row.get(2)?,
-                file_size: row.get(3)?,
-                width: row.get(4)?,
-                height: row.get(5)?,
-                capture_date: row.get(6)?,
-                added_at: row.get(7)?,
-                rating: row.get(8)?,
-                is_favorite: row.get::<_, i32>(9)? != 0,
-                tags: row.get(10)?,
-                description: row.get(11)?,
-                thumbnail_path: row.get(12)?,
-            })
-        })?
+    // With `stack_tops_only`, photos in collapsed stacks are represented by the
+    // top photo alone, or the lowest-id one if the stack has lost its top
+    pub fn get_all_photos(&self, stack_tops_only: bool) -> SqlResult<Vec<Photo>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}
+             FROM photos p
+             LEFT JOIN stacks s ON s.id = p.stack_id
+             WHERE ?1 = 0 OR s.id IS NULL OR s.is_collapsed = 0
+                OR p.id = COALESCE(s.top_photo_id, (SELECT MIN(sp.id) FROM photos sp WHERE sp.stack_id = s.id))
+             ORDER BY p.added_at DESC",
+            PHOTO_COLUMNS
+        ))?;
+
+        let photos = stmt.query_map(params![stack_tops_only as i32], Self::photo_from_row)?
         .collect::<SqlResult<Vec<_>>>()?;
 
         Ok(photos)
     }
 
-    pub fn update_metadata(&self, photo_id: i64, rating: Option<i32>, is_favorite: Option<bool>, tags: Option<String>, description: Option<String>) -> SqlResult<()> {
+    pub fn get_photo(&self, photo_id: i64) -> SqlResult<Photo> {
+        self.conn.query_row(
+            &format!("SELECT {} FROM photos p WHERE p.id = ?1", PHOTO_COLUMNS),
+            params![photo_id],
+            Self::photo_from_row,
+        )
+    }
+
+    pub fn get_photo_id_by_path(&self, path: &str) -> SqlResult<Option<i64>> {
+        self.conn.query_row(
+            "SELECT id FROM photos WHERE path = ?1",
+            params![path],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    // Any photo with these exact contents, to skip files that were already imported
+    pub fn get_photo_id_by_content_hash(&self, content_hash: &str) -> SqlResult<Option<i64>> {
+        self.conn.query_row(
+            "SELECT id FROM photos WHERE content_hash = ?1 ORDER BY id LIMIT 1",
+            params![content_hash],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    // An empty `color_label` clears the label
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_metadata(&self, photo_id: i64, rating: Option<i32>, is_favorite: Option<bool>, tags: Option<String>, description: Option<String>, flag: Option<String>, color_label: Option<String>) -> SqlResult<()> {
         let mut updates = Vec::new();
         let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
 
@@ -220,6 +532,14 @@ impl DatabaseService {
             updates.push("description = ?");
             params.push(Box::new(d));
         }
+        if let Some(f) = flag {
+            updates.push("flag = ?");
+            params.push(Box::new(f));
+        }
+        if let Some(c) = color_label {
+            updates.push("color_label = ?");
+            params.push(Box::new(Some(c).filter(|c| !c.is_empty())));
+        }
 
         if updates.is_empty() {
             return Ok(());
@@ -229,80 +549,307 @@ impl DatabaseService {
         let query = format!("UPDATE photos SET {} WHERE id = ?", updates.join(", "));
         
         let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
-        self.conn.execute(&query, param_refs.as_slice())?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        let action_id = self.begin_edit_action("Edit metadata")?;
+        self.journal_metadata_edit(action_id, photo_id, || {
+            self.conn.execute(&query, param_refs.as_slice()).map(|_| ())
+        })?;
+        self.finish_edit_action(action_id)?;
+        tx.commit()
+    }
+
+    pub fn get_photos_without_hash(&self) -> SqlResult<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, path FROM photos WHERE content_hash IS NULL"
+        )?;
+
+        let photos = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(photos)
+    }
+
+    pub fn set_content_hash(&self, photo_id: i64, content_hash: &str) -> SqlResult<()> {
+        self.conn.execute(
+            "UPDATE photos SET content_hash = ?1 WHERE id = ?2",
+            params![content_hash, photo_id],
+        )?;
         Ok(())
     }
 
-    pub fn create_collection(&self, name: &str) -> SqlResult<i64> {
+    pub fn find_duplicates(&self) -> SqlResult<Vec<DuplicateGroup>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}
+             FROM photos p
+             WHERE p.content_hash IN (
+                 SELECT content_hash FROM photos
+                 WHERE content_hash IS NOT NULL
+                 GROUP BY content_hash
+                 HAVING COUNT(*) > 1
+             )
+             ORDER BY p.content_hash, p.added_at",
+            PHOTO_COLUMNS
+        ))?;
+
+        let photos = stmt.query_map([], Self::photo_from_row)?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        let mut groups: Vec<DuplicateGroup> = Vec::new();
+        for photo in photos {
+            let hash = photo.content_hash.clone().unwrap_or_default();
+            match groups.last_mut() {
+                Some(group) if group.content_hash == hash => group.photos.push(photo),
+                _ => groups.push(DuplicateGroup {
+                    content_hash: hash,
+                    photos: vec![photo],
+                }),
+            }
+        }
+
+        Ok(groups)
+    }
+
+    pub fn get_photos_without_perceptual_hash(&self) -> SqlResult<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, thumbnail_path FROM photos WHERE phash IS NULL AND thumbnail_path IS NOT NULL"
+        )?;
+
+        let photos = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(photos)
+    }
+
+    pub fn set_perceptual_hash(&self, photo_id: i64, dhash: u64, phash: u64) -> SqlResult<()> {
         self.conn.execute(
-            "INSERT INTO collections (name) VALUES (?1)",
-            params![name],
+            "UPDATE photos SET dhash = ?1, phash = ?2 WHERE id = ?3",
+            params![dhash as i64, phash as i64, photo_id],
         )?;
-        Ok(self.conn.last_insert_rowid())
+        Ok(())
+    }
+
+    pub fn get_perceptual_hash(&self, photo_id: i64) -> SqlResult<Option<(u64, u64)>> {
+        self.conn.query_row(
+            "SELECT dhash, phash FROM photos WHERE id = ?1",
+            params![photo_id],
+            |row| {
+                let dhash: Option<i64> = row.get(0)?;
+                let phash: Option<i64> = row.get(1)?;
+                Ok(dhash.zip(phash).map(|(d, p)| (d as u64, p as u64)))
+            },
+        )
     }
 
-    pub fn get_all_collections(&self) -> SqlResult<Vec<Collection>> {
+    // (photo id, dhash, phash) for every photo that has been hashed
+    pub fn get_perceptual_hashes(&self) -> SqlResult<Vec<(i64, u64, u64)>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, name, created_at FROM collections ORDER BY created_at DESC"
+            "SELECT id, dhash, phash FROM photos WHERE dhash IS NOT NULL AND phash IS NOT NULL"
         )?;
 
-        let collections = stmt.query_map([], |row| {
-            Ok(Collection {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                created_at: row.get(2)?,
-            })
+        let hashes = stmt.query_map([], |row| {
+            Ok((row.get(0)?, row.get::<_, i64>(1)? as u64, row.get::<_, i64>(2)? as u64))
         })?
         .collect::<SqlResult<Vec<_>>>()?;
 
-        Ok(collections)
+        Ok(hashes)
     }
 
-    pub fn add_photo_to_collection(&self, photo_id: i64, collection_id: i64) -> SqlResult<()> {
-        self.conn.execute(
-            "INSERT OR IGNORE INTO photo_collections (photo_id, collection_id) VALUES (?1, ?2)",
-            params![photo_id, collection_id],
+    pub fn get_photos_without_color_signature(&self) -> SqlResult<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, thumbnail_path FROM photos WHERE color_histogram IS NULL AND thumbnail_path IS NOT NULL"
         )?;
-        Ok(())
+
+        let photos = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(photos)
     }
 
-    pub fn remove_photo_from_collection(&self, photo_id: i64, collection_id: i64) -> SqlResult<()> {
+    pub fn set_color_signature(&self, photo_id: i64, histogram: &[u8], palette: &str) -> SqlResult<()> {
         self.conn.execute(
-            "DELETE FROM photo_collections WHERE photo_id = ?1 AND collection_id = ?2",
-            params![photo_id, collection_id],
+            "UPDATE photos SET color_histogram = ?1, palette = ?2 WHERE id = ?3",
+            params![histogram, palette, photo_id],
         )?;
         Ok(())
     }
 
-    pub fn get_photos_in_collection(&self, collection_id: i64) -> SqlResult<Vec<Photo>> {
+    // (photo id, histogram, palette JSON) for every photo that has been analyzed
+    pub fn get_color_signatures(&self) -> SqlResult<Vec<(i64, Vec<u8>, String)>> {
         let mut stmt = self.conn.prepare(
-            "SELECT p.id, p.path, p.filename, p.file_size, p.width, p.height, p.capture_date, p.added_at, p.rating, p.is_favorite, p.tags, p.description, p.thumbnail_path
-             FROM photos p
-             INNER JOIN photo_collections pc ON p.id = pc.photo_id
-             WHERE pc.collection_id = ?1
-             ORDER BY p.added_at DESC"
+            "SELECT id, color_histogram, palette FROM photos WHERE color_histogram IS NOT NULL AND palette IS NOT NULL"
         )?;
 
-        let photos = stmt.query_map(params![collection_id], |row| {
-            Ok(Photo {
-                id: row.get(0)?,
-                path: row.get(1)?,
-                filename: row.get(2)?,
-                file_size: row.get(3)?,
-                width: row.get(4)?,
-                height: row.get(5)?,
-                capture_date: row.get(6)?,
-                added_at: row.get(7)?,
-                rating: row.get(8)?,
-                is_favorite: row.get::<_, i32>(9)? != 0,
-                tags: row.get(10)?,
-                description: row.get(11)?,
-                thumbnail_path: row.get(12)?,
-            })
-        })?
-        .collect::<SqlResult<Vec<_>>>()?;
+        let signatures = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<SqlResult<Vec<_>>>()?;
 
-        Ok(photos)
+        Ok(signatures)
+    }
+
+    // Folds ratings, favorites, tags, descriptions and collection memberships of
+    // `duplicate_ids` into `keep_id`, then removes the duplicates from the catalog.
+    // The merge is one undo step. Returns the removed photos so the caller can
+    // deal with their files.
+    pub fn merge_duplicates(&self, keep_id: i64, duplicate_ids: &[i64]) -> SqlResult<Vec<Photo>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let action_id = self.begin_edit_action("Merge duplicates")?;
+
+        let mut kept = self.get_photo(keep_id)?;
+        let mut tags: Vec<String> = serde_json::from_str(&kept.tags).unwrap_or_default();
+        let mut removed = Vec::new();
+
+        for &duplicate_id in duplicate_ids {
+            if duplicate_id == keep_id {
+                continue;
+            }
+            let duplicate = self.get_photo(duplicate_id)?;
+
+            kept.rating = kept.rating.max(duplicate.rating);
+            kept.is_favorite |= duplicate.is_favorite;
+            if kept.description.as_deref().is_none_or(str::is_empty) {
+                kept.description = duplicate.description.clone();
+            }
+            for tag in serde_json::from_str::<Vec<String>>(&duplicate.tags).unwrap_or_default() {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+
+            let mut stmt = self.conn.prepare(
+                "SELECT collection_id FROM photo_collections WHERE photo_id = ?1 ORDER BY sequence"
+            )?;
+            let collection_ids = stmt.query_map(params![duplicate_id], |row| row.get(0))?
+                .collect::<SqlResult<Vec<i64>>>()?;
+            for collection_id in collection_ids {
+                self.set_membership(action_id, keep_id, collection_id, true)?;
+                self.set_membership(action_id, duplicate_id, collection_id, false)?;
+            }
+            self.remove_photo_journaled(action_id, duplicate_id, Some(keep_id))?;
+
+            removed.push(duplicate);
+        }
+
+        let tags = serde_json::to_string(&tags)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.journal_metadata_edit(action_id, keep_id, || {
+            self.conn.execute(
+                "UPDATE photos SET rating = ?1, is_favorite = ?2, tags = ?3, description = ?4 WHERE id = ?5",
+                params![kept.rating, kept.is_favorite as i32, tags, kept.description, keep_id],
+            )?;
+            Ok(())
+        })?;
+
+        self.finish_edit_action(action_id)?;
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    // Points a photo at its new location after its files were moved or renamed
+    pub fn update_photo_location(
+        &self,
+        photo_id: i64,
+        path: &str,
+        filename: &str,
+        motion_video_path: Option<&str>,
+        thumbnail_path: Option<&str>,
+    ) -> SqlResult<()> {
+        let updated = self.conn.execute(
+            "UPDATE photos SET path = ?1, filename = ?2, motion_video_path = ?3, thumbnail_path = ?4 WHERE id = ?5",
+            params![path, filename, motion_video_path, thumbnail_path, photo_id],
+        )?;
+
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    // Catalogs a copy of a photo's file, carrying over its metadata, EXIF and
+    // search data but not its collections or stack
+    pub fn copy_photo_record(
+        &self,
+        photo_id: i64,
+        path: &str,
+        filename: &str,
+        motion_video_path: Option<&str>,
+        thumbnail_path: Option<&str>,
+    ) -> SqlResult<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        let inserted = tx.execute(
+            "INSERT INTO photos (path, filename, file_size, width, height, capture_date, rating, is_favorite, tags, description,
+                                 thumbnail_path, motion_type, motion_video_path, motion_video_offset, content_identifier,
+                                 content_hash, dhash, phash, color_histogram, palette, flag, color_label)
+             SELECT ?2, ?3, file_size, width, height, capture_date, rating, is_favorite, tags, description,
+                    ?5, motion_type, ?4, motion_video_offset, content_identifier,
+                    content_hash, dhash, phash, color_histogram, palette, flag, color_label
+             FROM photos WHERE id = ?1",
+            params![photo_id, path, filename, motion_video_path, thumbnail_path],
+        )?;
+        if inserted == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        let copy_id = tx.last_insert_rowid();
+
+        if let Some(exif) = self.get_exif(photo_id)? {
+            self.upsert_exif(copy_id, &exif)?;
+        }
+
+        tx.commit()?;
+        Ok(copy_id)
+    }
+
+    // Removes a photo from the catalog along with everything that refers to it
+    // Collection memberships, EXIF data and queued writes go with the photo;
+    // covers showing it are cleared by the foreign keys, and its stack is
+    // given a new top or dissolved
+    pub fn delete_photo(&self, photo_id: i64) -> SqlResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let stack_id: Option<i64> = tx.query_row(
+            "SELECT stack_id FROM photos WHERE id = ?1",
+            params![photo_id],
+            |row| row.get(0),
+        )?;
+
+        tx.execute("DELETE FROM photos WHERE id = ?1", params![photo_id])?;
+        if let Some(stack_id) = stack_id {
+            self.repair_stack(stack_id)?;
+        }
+        tx.commit()
+    }
+
+    // Removes photos from the catalog as one undoable action, for removals
+    // that leave the files in place; undo brings them back with their
+    // collections. Returns the ids that were removed; the others were not in
+    // the catalog.
+    pub fn remove_photos(&self, photo_ids: &[i64]) -> SqlResult<Vec<i64>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let action_id = self.begin_edit_action("Remove photos")?;
+        let mut removed = Vec::new();
+
+        for &photo_id in photo_ids {
+            let exists: bool = self.conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM photos WHERE id = ?1)",
+                params![photo_id],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                continue;
+            }
+
+            let mut stmt = self.conn.prepare("SELECT collection_id FROM photo_collections WHERE photo_id = ?1")?;
+            let collection_ids = stmt.query_map(params![photo_id], |row| row.get(0))?
+                .collect::<SqlResult<Vec<i64>>>()?;
+            for collection_id in collection_ids {
+                self.set_membership(action_id, photo_id, collection_id, false)?;
+            }
+            self.remove_photo_journaled(action_id, photo_id, None)?;
+            removed.push(photo_id);
+        }
+
+        self.finish_edit_action(action_id)?;
+        tx.commit()?;
+        Ok(removed)
     }
 
     pub fn export_to_json(&self) -> SqlResult<String> {
@@ -313,17 +860,9 @@ impl DatabaseService {
             photo_collections: Vec<PhotoCollection>,
         }
 
-        let photos = self.get_all_photos()?;
+        let photos = self.get_all_photos(false)?;
         let collections = self.get_all_collections()?;
-        
-        let mut stmt = self.conn.prepare("SELECT photo_id, collection_id FROM photo_collections")?;
-        let photo_collections = stmt.query_map([], |row| {
-            Ok(PhotoCollection {
-                photo_id: row.get(0)?,
-                collection_id: row.get(1)?,
-            })
-        })?
-        .collect::<SqlResult<Vec<_>>>()?;
+        let photo_collections = self.get_all_photo_collections()?;
 
         let backup = Backup {
             photos,
@@ -336,4 +875,50 @@ impl DatabaseService {
             rusqlite::Error::ToSqlConversionFailure(Box::new(e))
         })
     }
+
+    // Restores ratings, favorites, tags, descriptions, flags and color labels
+    // from an `export_to_json` backup, matching photos by path. The whole import
+    // is a single undo step.
+    pub fn import_metadata_from_json(&self, json: &str) -> SqlResult<MetadataImport> {
+        #[derive(Deserialize)]
+        struct Backup {
+            photos: Vec<Photo>,
+        }
+
+        let backup: Backup = serde_json::from_str(json)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        let action_id = self.begin_edit_action("Import metadata")?;
+        let mut report = MetadataImport::default();
+
+        for photo in backup.photos {
+            let Some(photo_id) = self.get_photo_id_by_path(&photo.path)? else {
+                report.unmatched_paths.push(photo.path);
+                continue;
+            };
+
+            self.journal_metadata_edit(action_id, photo_id, || {
+                self.conn.execute(
+                    "UPDATE photos SET rating = ?1, is_favorite = ?2, tags = ?3, description = ?4, flag = ?5, color_label = ?6
+                     WHERE id = ?7",
+                    params![
+                        photo.rating,
+                        photo.is_favorite as i32,
+                        photo.tags,
+                        photo.description,
+                        photo.flag,
+                        photo.color_label,
+                        photo_id,
+                    ],
+                )
+                .map(|_| ())
+            })?;
+            report.updated += 1;
+        }
+
+        self.finish_edit_action(action_id)?;
+        tx.commit()?;
+        Ok(report)
+    }
 }
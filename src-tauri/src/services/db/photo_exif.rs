@@ -0,0 +1,82 @@
+use rusqlite::{Result as SqlResult, params};
+use crate::services::exif::EXIFData;
+use super::DatabaseService;
+
+impl DatabaseService {
+    // One row per photo, written even when the file has no EXIF so it is not rescanned
+    pub fn upsert_exif(&self, photo_id: i64, exif: &EXIFData) -> SqlResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO photo_exif (
+                 photo_id, camera_make, camera_model, lens_model, focal_length, aperture, shutter_speed, iso,
+                 exposure_bias, exposure_mode, flash, orientation, capture_date, subsec_time, burst_id,
+                 gps_latitude, gps_longitude, gps_altitude
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            params![
+                photo_id,
+                exif.camera_make,
+                exif.camera_model,
+                exif.lens_model,
+                exif.focal_length,
+                exif.aperture,
+                exif.shutter_speed,
+                exif.iso,
+                exif.exposure_bias,
+                exif.exposure_mode,
+                exif.flash,
+                exif.orientation,
+                exif.capture_date,
+                exif.subsec_time,
+                exif.burst_id,
+                exif.gps_latitude,
+                exif.gps_longitude,
+                exif.gps_altitude,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_exif(&self, photo_id: i64) -> SqlResult<Option<EXIFData>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT camera_make, camera_model, lens_model, focal_length, aperture, shutter_speed, iso,
+                    exposure_bias, exposure_mode, flash, orientation, capture_date, subsec_time, burst_id,
+                    gps_latitude, gps_longitude, gps_altitude
+             FROM photo_exif WHERE photo_id = ?1"
+        )?;
+
+        let mut rows = stmt.query_map(params![photo_id], |row| {
+            Ok(EXIFData {
+                camera_make: row.get(0)?,
+                camera_model: row.get(1)?,
+                lens_model: row.get(2)?,
+                focal_length: row.get(3)?,
+                aperture: row.get(4)?,
+                shutter_speed: row.get(5)?,
+                iso: row.get(6)?,
+                exposure_bias: row.get(7)?,
+                exposure_mode: row.get(8)?,
+                flash: row.get(9)?,
+                orientation: row.get(10)?,
+                capture_date: row.get(11)?,
+                subsec_time: row.get(12)?,
+                burst_id: row.get(13)?,
+                gps_latitude: row.get(14)?,
+                gps_longitude: row.get(15)?,
+                gps_altitude: row.get(16)?,
+            })
+        })?;
+
+        rows.next().transpose()
+    }
+
+    pub fn get_photos_without_exif(&self) -> SqlResult<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT p.id, p.path FROM photos p
+             WHERE NOT EXISTS (SELECT 1 FROM photo_exif e WHERE e.photo_id = p.id)"
+        )?;
+
+        let photos = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(photos)
+    }
+}
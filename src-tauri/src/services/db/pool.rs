@@ -0,0 +1,134 @@
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use rusqlite::Result as SqlResult;
+use log::info;
+use crate::services::error::{AppError, AppResult};
+use super::DatabaseService;
+
+const MAX_READERS: usize = 8;
+
+// Idle connections of one kind, handed out one caller at a time
+struct Slots {
+    idle: Mutex<Idle>,
+    returned: Condvar,
+    count: usize,
+}
+
+struct Idle {
+    connections: Vec<DatabaseService>,
+    closed: bool,
+}
+
+impl Slots {
+    fn new(connections: Vec<DatabaseService>) -> Self {
+        let count = connections.len();
+        Slots { idle: Mutex::new(Idle { connections, closed: false }), returned: Condvar::new(), count }
+    }
+
+    // Waits until a connection is free; `None` once the pool is closed
+    fn take(&self) -> Option<DatabaseService> {
+        let mut idle = self.idle.lock().unwrap();
+        loop {
+            if idle.closed {
+                return None;
+            }
+            if let Some(conn) = idle.connections.pop() {
+                return Some(conn);
+            }
+            idle = self.returned.wait(idle).unwrap();
+        }
+    }
+
+    fn put(&self, conn: DatabaseService) {
+        self.idle.lock().unwrap().connections.push(conn);
+        self.returned.notify_all();
+    }
+
+    // Refuses further takes, waits for the connections in use to come back
+    // and closes them all
+    fn close(&self) {
+        let mut idle = self.idle.lock().unwrap();
+        idle.closed = true;
+        self.returned.notify_all();
+        while idle.connections.len() < self.count {
+            idle = self.returned.wait(idle).unwrap();
+        }
+        idle.connections.clear();
+    }
+}
+
+// Connections to one catalog. There is a single writer, so writes queue here
+// instead of failing as busy, and several readers that in WAL mode read the
+// last committed state while the writer works (an import, say).
+pub struct DatabasePool {
+    writer: Slots,
+    readers: Slots,
+}
+
+impl DatabasePool {
+    // Sets up the catalog (migrations and all) on the writer, then opens the readers
+    pub fn open(db_path: PathBuf) -> SqlResult<Arc<Self>> {
+        let writer = DatabaseService::new(db_path.clone())?;
+        let reader_count = std::thread::available_parallelism().map_or(4, |n| n.get()).clamp(2, MAX_READERS);
+        let readers = (0..reader_count)
+            .map(|_| DatabaseService::open_existing(&db_path))
+            .collect::<SqlResult<Vec<_>>>()?;
+        info!("Opened {} reader connections", readers.len());
+
+        Ok(Arc::new(DatabasePool {
+            writer: Slots::new(vec![writer]),
+            readers: Slots::new(readers),
+        }))
+    }
+
+    // For queries only. Do not take a second connection while holding this one.
+    pub fn reader(self: &Arc<Self>) -> AppResult<PooledConnection> {
+        let conn = self.readers.take().ok_or(AppError::DatabaseNotInitialized)?;
+        Ok(PooledConnection { pool: Arc::clone(self), conn: Some(conn), is_writer: false })
+    }
+
+    // The one connection that writes; held for the whole of a multi-step change
+    pub fn writer(self: &Arc<Self>) -> AppResult<PooledConnection> {
+        let conn = self.writer.take().ok_or(AppError::DatabaseNotInitialized)?;
+        Ok(PooledConnection { pool: Arc::clone(self), conn: Some(conn), is_writer: true })
+    }
+
+    // Waits for the connections in use and closes the catalog. Commands still
+    // holding the pool get an error instead of a connection from then on, so
+    // the file can be moved or replaced once this returns.
+    pub fn close(&self) {
+        self.writer.close();
+        self.readers.close();
+        info!("Closed catalog connections");
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.writer.idle.lock().unwrap().closed
+    }
+}
+
+// A connection borrowed from the pool, returned when dropped. Owns a handle to
+// the pool so it can be moved to a worker thread.
+pub struct PooledConnection {
+    pool: Arc<DatabasePool>,
+    conn: Option<DatabaseService>,
+    is_writer: bool,
+}
+
+impl Deref for PooledConnection {
+    type Target = DatabaseService;
+
+    fn deref(&self) -> &DatabaseService {
+        self.conn.as_ref().expect("connection is present until dropped")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            let slots = if self.is_writer { &self.pool.writer } else { &self.pool.readers };
+            slots.put(conn);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+use chrono::{NaiveDateTime, Utc};
+use rusqlite::{Connection, ErrorCode, OpenFlags, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use log::info;
+use crate::services::error::{AppError, AppResult};
+use super::BackupInfo;
+
+pub const DATABASE_FILE: &str = "photos.db";
+
+// How opening the library went, for the frontend to show the app, an error,
+// the recovery steps, or the library picker once it was closed
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum StartupState {
+    #[default]
+    Opening,
+    Ready { library_path: String },
+    Closed,
+    Failed { library_path: String, error: AppError },
+    Corrupt {
+        library_path: String,
+        problems: Vec<String>,      // As reported by SQLite's integrity check
+        backup: Option<BackupInfo>, // The newest backup, which a restore brings back
+    },
+}
+
+// What to do with a damaged catalog. Either way the damaged file is kept
+// next to it, renamed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryAction {
+    RestoreBackup,
+    StartFresh,
+}
+
+// SQLite's verdict on a damaged file, as opposed to e.g. a locked or unreadable one
+pub fn is_corruption(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(ErrorCode::DatabaseCorrupt) | Some(ErrorCode::NotADatabase)
+    )
+}
+
+fn open_for_check(db_path: &Path) -> SqlResult<Connection> {
+    Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+}
+
+fn integrity_problems(conn: &Connection, thorough: bool) -> SqlResult<Vec<String>> {
+    let pragma = if thorough { "PRAGMA integrity_check" } else { "PRAGMA quick_check" };
+    let mut stmt = conn.prepare(pragma)?;
+    let messages = stmt.query_map([], |row| row.get::<_, String>(0))?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+    Ok(messages.into_iter().filter(|m| m != "ok").collect())
+}
+
+// Problems found in the catalog file, empty if it is sound. The quick check
+// skips index contents; `thorough` runs the full integrity check.
+pub fn check_integrity(db_path: &Path, thorough: bool) -> SqlResult<Vec<String>> {
+    integrity_problems(&open_for_check(db_path)?, thorough)
+}
+
+// The full integrity check plus rows that point at rows that no longer
+// exist, e.g. the tags of a deleted photo. Backups must pass this.
+pub fn verify_catalog(db_path: &Path) -> SqlResult<Vec<String>> {
+    let conn = open_for_check(db_path)?;
+    let mut problems = integrity_problems(&conn, true)?;
+
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let violations = stmt.query_map([], |row| {
+        let table: String = row.get(0)?;
+        let rowid: Option<i64> = row.get(1)?;
+        let parent: String = row.get(2)?;
+        Ok(match rowid {
+            Some(rowid) => format!("{} row {} refers to a missing {} row", table, rowid, parent),
+            None => format!("A {} row refers to a missing {} row", table, parent),
+        })
+    })?;
+    for violation in violations {
+        problems.push(violation?);
+    }
+
+    Ok(problems)
+}
+
+const SET_ASIDE_STAMP: &str = "%Y%m%d-%H%M%S";
+
+// Renames the catalog and its WAL files to photos.<label>-<time>.db* so a new
+// or restored one can take its place
+pub fn set_aside(library_dir: &Path, label: &str) -> AppResult<PathBuf> {
+    let db_path = library_dir.join(DATABASE_FILE);
+    let stamp = Utc::now().format(SET_ASIDE_STAMP);
+    let aside = library_dir.join(format!("photos.{}-{}.db", label, stamp));
+
+    for suffix in ["", "-wal", "-shm"] {
+        let from = PathBuf::from(format!("{}{}", db_path.display(), suffix));
+        let to = PathBuf::from(format!("{}{}", aside.display(), suffix));
+        match std::fs::rename(&from, &to) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(AppError::io(&from, e).context("Failed to move catalog aside")),
+        }
+    }
+
+    info!("Moved catalog to {:?}", aside);
+    Ok(aside)
+}
+
+// The catalog, its WAL files, a restore in progress, or a catalog set aside
+// by `set_aside`, going by the file name
+pub(super) fn is_catalog_file(name: &str) -> bool {
+    let Some((stem, suffix)) = name.split_once(".db") else { return false };
+    if !["", "-wal", "-shm", ".restore"].contains(&suffix) {
+        return false;
+    }
+    if format!("{}.db", stem) == DATABASE_FILE {
+        return true;
+    }
+
+    // photos.<label>-<time>
+    let Some(labeled) = stem.strip_prefix("photos.") else { return false };
+    let stamp_len = "20000101-000000".len();
+    labeled.len() > stamp_len
+        && labeled.is_char_boundary(labeled.len() - stamp_len)
+        && NaiveDateTime::parse_from_str(&labeled[labeled.len() - stamp_len..], SET_ASIDE_STAMP).is_ok()
+}
@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use rusqlite::{Result as SqlResult, Row, params, types::Type};
+use serde::{Deserialize, Serialize};
+use log::info;
+use crate::services::fs::ScanOptions;
+use super::{BatchReport, DatabaseService};
+
+// A folder the user imported; photos are browsed by folder below these
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LibraryRoot {
+    pub id: i64,
+    pub path: String,
+    pub added_at: String,
+    pub scan_options: ScanOptions,
+    pub volume_id: Option<String>,
+    pub volume_label: Option<String>,
+    pub volume_path: Option<String>, // The root relative to the volume's mount point
+    pub is_online: bool,
+    pub last_seen_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FolderNode {
+    pub root_id: Option<i64>, // Set on the top node of each root
+    pub path: String,
+    pub name: String,
+    pub photo_count: i64,       // Photos directly in this folder
+    pub total_photo_count: i64, // Including subfolders
+    pub children: Vec<FolderNode>,
+}
+
+// Result of checking whether a root's drive is connected
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RootStatus {
+    pub root_id: i64,
+    pub path: String,
+    pub is_online: bool,
+    pub relocated_from: Option<String>, // Found on its volume under another mount point
+    pub written_back: BatchReport,      // Queued offline edits written to the files
+}
+
+// Photo counts per folder while the tree is being built
+#[derive(Default)]
+struct FolderCounts {
+    photo_count: i64,
+    children: BTreeMap<String, FolderCounts>,
+}
+
+impl FolderCounts {
+    fn into_node(self, path: PathBuf, root_id: Option<i64>) -> FolderNode {
+        let children: Vec<FolderNode> = self.children
+            .into_iter()
+            .map(|(name, counts)| counts.into_node(path.join(name), None))
+            .collect();
+        let total_photo_count = self.photo_count + children.iter().map(|c| c.total_photo_count).sum::<i64>();
+
+        FolderNode {
+            root_id,
+            name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| path.to_string_lossy().to_string()),
+            path: path.to_string_lossy().to_string(),
+            photo_count: self.photo_count,
+            total_photo_count,
+            children,
+        }
+    }
+}
+
+// The smallest set of folders that contains all `folders`
+fn outermost_folders(mut folders: Vec<PathBuf>) -> Vec<PathBuf> {
+    folders.sort();
+    folders.dedup();
+
+    let mut outermost: Vec<PathBuf> = Vec::new();
+    for folder in folders {
+        if !outermost.iter().any(|kept| folder.starts_with(kept)) {
+            outermost.push(folder);
+        }
+    }
+    outermost
+}
+
+impl DatabaseService {
+    fn library_root_from_row(row: &Row) -> SqlResult<LibraryRoot> {
+        let scan_options = match row.get::<_, Option<String>>(3)? {
+            Some(json) => serde_json::from_str(&json)
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e)))?,
+            None => ScanOptions::default(),
+        };
+
+        Ok(LibraryRoot {
+            id: row.get(0)?,
+            path: row.get(1)?,
+            added_at: row.get(2)?,
+            scan_options,
+            volume_id: row.get(4)?,
+            volume_label: row.get(5)?,
+            volume_path: row.get(6)?,
+            is_online: row.get::<_, i32>(7)? != 0,
+            last_seen_at: row.get(8)?,
+        })
+    }
+
+    pub fn get_library_roots(&self) -> SqlResult<Vec<LibraryRoot>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, path, added_at, scan_options, volume_id, volume_label, volume_path, is_online, last_seen_at
+             FROM library_roots ORDER BY path"
+        )?;
+
+        let roots = stmt.query_map([], Self::library_root_from_row)?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(roots)
+    }
+
+    // Roots do not nest: adding a folder inside an existing root returns that
+    // root, and adding a parent of existing roots replaces them
+    pub fn add_library_root(&self, path: &str) -> SqlResult<i64> {
+        let folder = Path::new(path);
+        let roots = self.get_library_roots()?;
+
+        if let Some(root) = roots.iter().find(|r| folder.starts_with(&r.path)) {
+            return Ok(root.id);
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        for root in roots.iter().filter(|r| Path::new(&r.path).starts_with(folder)) {
+            tx.execute("DELETE FROM library_roots WHERE id = ?1", params![root.id])?;
+        }
+        tx.execute("INSERT INTO library_roots (path) VALUES (?1)", params![path])?;
+        let id = tx.last_insert_rowid();
+        tx.commit()?;
+
+        Ok(id)
+    }
+
+    // Only forgets the root; its photos stay in the catalog
+    pub fn remove_library_root(&self, root_id: i64) -> SqlResult<()> {
+        let removed = self.conn.execute("DELETE FROM library_roots WHERE id = ?1", params![root_id])?;
+        if removed == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    pub fn set_scan_options(&self, root_id: i64, options: &ScanOptions) -> SqlResult<()> {
+        let json = serde_json::to_string(options)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let updated = self.conn.execute(
+            "UPDATE library_roots SET scan_options = ?1 WHERE id = ?2",
+            params![json, root_id],
+        )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    // The rules of the root that contains `folder`, or the defaults
+    pub fn scan_options_for(&self, folder: &Path) -> SqlResult<ScanOptions> {
+        Ok(self.get_library_roots()?
+            .into_iter()
+            .find(|root| folder.starts_with(&root.path))
+            .map(|root| root.scan_options)
+            .unwrap_or_default())
+    }
+
+    pub fn set_root_volume(
+        &self,
+        root_id: i64,
+        volume_id: Option<&str>,
+        volume_label: Option<&str>,
+        volume_path: Option<&str>,
+    ) -> SqlResult<()> {
+        self.conn.execute(
+            "UPDATE library_roots SET volume_id = ?1, volume_label = ?2, volume_path = ?3 WHERE id = ?4",
+            params![volume_id, volume_label, volume_path, root_id],
+        )?;
+        Ok(())
+    }
+
+    // Marks the root and every photo under it online or offline
+    pub fn set_root_online(&self, root_id: i64, online: bool) -> SqlResult<()> {
+        let root_path: String = self.conn.query_row(
+            "SELECT path FROM library_roots WHERE id = ?1",
+            params![root_id],
+            |row| row.get(0),
+        )?;
+        let mut prefix = root_path.clone();
+        if !prefix.ends_with(std::path::is_separator) {
+            prefix.push(std::path::MAIN_SEPARATOR);
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE library_roots SET is_online = ?1,
+                 last_seen_at = CASE WHEN ?1 = 1 THEN datetime('now') ELSE last_seen_at END
+             WHERE id = ?2",
+            params![online as i32, root_id],
+        )?;
+        tx.execute(
+            "UPDATE photos SET is_offline = ?1 WHERE substr(path, 1, length(?2)) = ?2 AND is_offline != ?1",
+            params![!online as i32, prefix],
+        )?;
+        tx.commit()
+    }
+
+    // Photos under `folder` with label edits made while they were offline
+    pub fn get_pending_writes(&self, folder: &Path) -> SqlResult<Vec<i64>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT w.photo_id, p.path FROM pending_writes w JOIN photos p ON p.id = w.photo_id ORDER BY w.queued_at"
+        )?;
+        let pending = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(pending.into_iter().filter(|(_, path)| Path::new(path).starts_with(folder)).map(|(id, _)| id).collect())
+    }
+
+    pub fn clear_pending_write(&self, photo_id: i64) -> SqlResult<()> {
+        self.conn.execute("DELETE FROM pending_writes WHERE photo_id = ?1", params![photo_id])?;
+        Ok(())
+    }
+
+    // Follows `relocate_photos` when a whole folder moved
+    pub fn relocate_library_roots(&self, old_prefix: &Path, new_prefix: &Path) -> SqlResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        for root in self.get_library_roots()? {
+            if let Ok(rest) = Path::new(&root.path).strip_prefix(old_prefix) {
+                tx.execute(
+                    "UPDATE library_roots SET path = ?1 WHERE id = ?2",
+                    params![new_prefix.join(rest).to_string_lossy(), root.id],
+                )?;
+            }
+        }
+
+        tx.commit()
+    }
+
+    // Catalogs created before roots were tracked get one root per outermost
+    // folder that holds photos
+    pub(super) fn backfill_library_roots(&self) -> SqlResult<()> {
+        let roots: i64 = self.conn.query_row("SELECT COUNT(*) FROM library_roots", [], |row| row.get(0))?;
+        if roots > 0 {
+            return Ok(());
+        }
+
+        let mut stmt = self.conn.prepare("SELECT path FROM photos")?;
+        let folders: Vec<PathBuf> = stmt.query_map([], |row| row.get::<_, String>(0))?
+            .filter_map(|path| Some(Path::new(&path.ok()?).parent()?.to_path_buf()))
+            .collect();
+
+        let folders = outermost_folders(folders);
+        if !folders.is_empty() {
+            info!("Registering {} library roots from existing photos", folders.len());
+        }
+        for folder in folders {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO library_roots (path) VALUES (?1)",
+                params![folder.to_string_lossy()],
+            )?;
+        }
+        Ok(())
+    }
+
+    // The folders below each root that contain photos, with their counts.
+    // Built from the catalog, so it works while a drive is disconnected.
+    pub fn get_folder_tree(&self) -> SqlResult<Vec<FolderNode>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path FROM photos WHERE substr(path, 1, length(?1)) = ?1"
+        )?;
+        let mut trees = Vec::new();
+
+        for root in self.get_library_roots()? {
+            let root_path = Path::new(&root.path);
+            let mut counts = FolderCounts::default();
+
+            for path in stmt.query_map(params![root.path], |row| row.get::<_, String>(0))? {
+                let path = PathBuf::from(path?);
+                let Some(folder) = path.parent().and_then(|p| p.strip_prefix(root_path).ok()) else { continue };
+
+                let node = folder.components().fold(&mut counts, |node, component| {
+                    node.children.entry(component.as_os_str().to_string_lossy().to_string()).or_default()
+                });
+                node.photo_count += 1;
+            }
+
+            trees.push(counts.into_node(root_path.to_path_buf(), Some(root.id)));
+        }
+
+        Ok(trees)
+    }
+}
@@ -0,0 +1,185 @@
+use rusqlite::{Result as SqlResult, ToSql, params, types::Type};
+use serde::{Deserialize, Serialize};
+use crate::services::error::AppResult;
+use super::{DatabaseService, Photo, PHOTO_COLUMNS};
+use super::collections::name_write_error;
+
+// LIKE pattern matching `text` anywhere, with `%` and `_` taken literally;
+// used with `ESCAPE '\'`
+fn contains_pattern(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn sql(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::Ne => "<>",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+}
+
+// Rule tree of a smart collection, stored as JSON on the collection, e.g.
+// {"type":"and","rules":[{"type":"rating","op":"ge","value":4},{"type":"tag","value":"portfolio"},
+//                        {"type":"capture_year","op":"eq","value":2025}]}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SmartRule {
+    And { rules: Vec<SmartRule> },
+    Or { rules: Vec<SmartRule> },
+    Not { rule: Box<SmartRule> },
+    Rating { op: Comparison, value: i32 },
+    Favorite { value: bool },
+    Flag { value: String },        // "none", "picked" or "rejected"
+    ColorLabel { value: Option<String> }, // None matches unlabeled photos
+    Tag { value: String },
+    CaptureYear { op: Comparison, value: i32 },
+    CaptureDate { op: Comparison, value: String }, // "YYYY-MM-DD"
+    Filename { contains: String },
+    Camera { contains: String },
+    Lens { contains: String },
+}
+
+impl SmartRule {
+    // SQL condition over `photos p`, pushing its parameters onto `params` in order
+    pub fn to_sql(&self, params: &mut Vec<Box<dyn ToSql>>) -> String {
+        match self {
+            SmartRule::And { rules } if rules.is_empty() => "1".to_string(),
+            SmartRule::Or { rules } if rules.is_empty() => "0".to_string(),
+            SmartRule::And { rules } => Self::join(rules, " AND ", params),
+            SmartRule::Or { rules } => Self::join(rules, " OR ", params),
+            SmartRule::Not { rule } => format!("NOT ({})", rule.to_sql(params)),
+            SmartRule::Rating { op, value } => {
+                params.push(Box::new(*value));
+                format!("p.rating {} ?", op.sql())
+            }
+            SmartRule::Favorite { value } => {
+                params.push(Box::new(*value as i32));
+                "p.is_favorite = ?".to_string()
+            }
+            SmartRule::Flag { value } => {
+                params.push(Box::new(value.clone()));
+                "p.flag = ?".to_string()
+            }
+            SmartRule::ColorLabel { value } => {
+                params.push(Box::new(value.clone()));
+                "p.color_label IS ?".to_string()
+            }
+            SmartRule::Tag { value } => {
+                params.push(Box::new(value.clone()));
+                "EXISTS (SELECT 1 FROM json_each(p.tags) WHERE json_each.value = ?)".to_string()
+            }
+            SmartRule::CaptureYear { op, value } => {
+                params.push(Box::new(*value));
+                format!("CAST(substr(p.capture_date, 1, 4) AS INTEGER) {} ?", op.sql())
+            }
+            SmartRule::CaptureDate { op, value } => {
+                params.push(Box::new(value.clone()));
+                format!("substr(p.capture_date, 1, 10) {} ?", op.sql())
+            }
+            SmartRule::Filename { contains } => {
+                params.push(Box::new(contains_pattern(contains)));
+                "p.filename LIKE ? ESCAPE '\\'".to_string()
+            }
+            SmartRule::Camera { contains } => {
+                params.push(Box::new(contains_pattern(contains)));
+                "EXISTS (SELECT 1 FROM photo_exif e WHERE e.photo_id = p.id \
+                 AND (COALESCE(e.camera_make, '') || ' ' || COALESCE(e.camera_model, '')) LIKE ? ESCAPE '\\')".to_string()
+            }
+            SmartRule::Lens { contains } => {
+                params.push(Box::new(contains_pattern(contains)));
+                "EXISTS (SELECT 1 FROM photo_exif e WHERE e.photo_id = p.id AND e.lens_model LIKE ? ESCAPE '\\')".to_string()
+            }
+        }
+    }
+
+    fn join(rules: &[SmartRule], separator: &str, params: &mut Vec<Box<dyn ToSql>>) -> String {
+        let parts: Vec<String> = rules
+            .iter()
+            .map(|rule| format!("({})", rule.to_sql(params)))
+            .collect();
+        parts.join(separator)
+    }
+}
+
+impl DatabaseService {
+    pub fn create_smart_collection(&self, name: &str, rules: &SmartRule) -> AppResult<i64> {
+        self.conn.execute(
+            "INSERT INTO collections (name, rules, sequence, updated_at)
+             VALUES (?1, ?2, (SELECT COALESCE(MAX(sequence), -1) + 1 FROM collections WHERE parent_id IS NULL), datetime('now'))",
+            params![name, Self::rules_to_json(rules)?],
+        )
+        .map_err(|e| name_write_error(e, name))?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    // Passing `None` turns a smart collection back into a manual one
+    pub fn set_collection_rules(&self, collection_id: i64, rules: Option<&SmartRule>) -> SqlResult<()> {
+        let rules = rules.map(Self::rules_to_json).transpose()?;
+        self.conn.execute(
+            "UPDATE collections SET rules = ?1, updated_at = datetime('now') WHERE id = ?2",
+            params![rules, collection_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_collection_rules(&self, collection_id: i64) -> SqlResult<Option<SmartRule>> {
+        let rules: Option<String> = self.conn.query_row(
+            "SELECT rules FROM collections WHERE id = ?1",
+            params![collection_id],
+            |row| row.get(0),
+        )?;
+        rules.map(|r| Self::rules_from_json(&r)).transpose()
+    }
+
+    pub fn get_photos_matching_rule(&self, rule: &SmartRule) -> SqlResult<Vec<Photo>> {
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+        let condition = rule.to_sql(&mut params);
+        let query = format!(
+            "SELECT {} FROM photos p WHERE {} ORDER BY p.added_at DESC",
+            PHOTO_COLUMNS, condition
+        );
+
+        let param_refs: Vec<&dyn ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = self.conn.prepare(&query)?;
+        let photos = stmt.query_map(param_refs.as_slice(), Self::photo_from_row)?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(photos)
+    }
+
+    pub fn count_photos_matching_rule(&self, rule: &SmartRule) -> SqlResult<i64> {
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+        let condition = rule.to_sql(&mut params);
+        let query = format!("SELECT COUNT(*) FROM photos p WHERE {}", condition);
+
+        let param_refs: Vec<&dyn ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        self.conn.query_row(&query, param_refs.as_slice(), |row| row.get(0))
+    }
+
+    fn rules_to_json(rules: &SmartRule) -> SqlResult<String> {
+        serde_json::to_string(rules)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+    }
+
+    pub(super) fn rules_from_json(json: &str) -> SqlResult<SmartRule> {
+        serde_json::from_str(json)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
+    }
+}
@@ -0,0 +1,234 @@
+use chrono::NaiveDateTime;
+use rusqlite::{Result as SqlResult, Row, params};
+use serde::{Deserialize, Serialize};
+use log::info;
+use super::{DatabaseService, Photo, PHOTO_COLUMNS};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Stack {
+    pub id: i64,
+    pub top_photo_id: Option<i64>,
+    pub kind: String, // "burst" or "bracket"
+    pub is_collapsed: bool,
+    pub created_at: String,
+    pub photo_count: i64,
+}
+
+// EXIF fields of an unstacked photo that decide which frames belong together
+struct Frame {
+    photo_id: i64,
+    camera: (Option<String>, Option<String>),
+    time: f64, // seconds, including the sub-second part
+    exposure_bias: Option<f64>,
+    exposure_mode: Option<u32>,
+    burst_id: Option<String>,
+    rating: i32,
+}
+
+const STACK_COLUMNS: &str = "s.id, s.top_photo_id, s.kind, s.is_collapsed, s.created_at, \
+     (SELECT COUNT(*) FROM photos sp WHERE sp.stack_id = s.id)";
+
+// EXIF "YYYY-MM-DD HH:MM:SS" plus SubSecTimeOriginal digits as fractional seconds
+fn capture_seconds(capture_date: &str, subsec: Option<&str>) -> Option<f64> {
+    let time = NaiveDateTime::parse_from_str(capture_date, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(capture_date, "%Y:%m:%d %H:%M:%S"))
+        .ok()?;
+
+    let fraction = subsec
+        .filter(|s| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()))
+        .and_then(|s| format!("0.{}", s).parse::<f64>().ok())
+        .unwrap_or(0.0);
+
+    Some(time.and_utc().timestamp() as f64 + fraction)
+}
+
+impl DatabaseService {
+    fn stack_from_row(row: &Row) -> SqlResult<Stack> {
+        Ok(Stack {
+            id: row.get(0)?,
+            top_photo_id: row.get(1)?,
+            kind: row.get(2)?,
+            is_collapsed: row.get::<_, i32>(3)? != 0,
+            created_at: row.get(4)?,
+            photo_count: row.get(5)?,
+        })
+    }
+
+    // Groups unstacked photos shot by the same camera no more than `max_gap_seconds`
+    // apart (or sharing an Apple burst id) into stacks. Groups with differing
+    // exposure bias or auto-bracket mode become "bracket" stacks topped by the
+    // frame closest to 0 EV; others become "burst" stacks topped by the best-rated frame.
+    pub fn auto_stack(&self, max_gap_seconds: f64) -> SqlResult<Vec<Stack>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT p.id, e.camera_make, e.camera_model, e.capture_date, e.subsec_time,
+                    e.exposure_bias, e.exposure_mode, e.burst_id, p.rating
+             FROM photos p
+             INNER JOIN photo_exif e ON e.photo_id = p.id
+             WHERE p.stack_id IS NULL AND e.capture_date IS NOT NULL"
+        )?;
+
+        let mut frames: Vec<Frame> = stmt.query_map([], |row| {
+            let capture_date: String = row.get(3)?;
+            let subsec: Option<String> = row.get(4)?;
+            let frame = Frame {
+                photo_id: row.get(0)?,
+                camera: (row.get(1)?, row.get(2)?),
+                time: 0.0,
+                exposure_bias: row.get(5)?,
+                exposure_mode: row.get(6)?,
+                burst_id: row.get(7)?,
+                rating: row.get(8)?,
+            };
+            Ok(capture_seconds(&capture_date, subsec.as_deref()).map(|time| Frame { time, ..frame }))
+        })?
+        .filter_map(|frame| frame.transpose())
+        .collect::<SqlResult<Vec<_>>>()?;
+
+        frames.sort_by(|a, b| a.camera.cmp(&b.camera).then(a.time.total_cmp(&b.time)));
+
+        let mut groups: Vec<Vec<Frame>> = Vec::new();
+        for frame in frames {
+            let continues = groups.last().and_then(|g| g.last()).is_some_and(|prev| {
+                let same_burst = prev.burst_id.is_some() && prev.burst_id == frame.burst_id;
+                let close = prev.camera == frame.camera && frame.time - prev.time <= max_gap_seconds;
+                same_burst || close
+            });
+
+            match groups.last_mut() {
+                Some(group) if continues => group.push(frame),
+                _ => groups.push(vec![frame]),
+            }
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        let mut stack_ids = Vec::new();
+
+        for group in groups.iter().filter(|g| g.len() > 1) {
+            let mut biases: Vec<i64> = group
+                .iter()
+                .filter_map(|f| f.exposure_bias)
+                .map(|b| (b * 100.0).round() as i64)
+                .collect();
+            biases.sort_unstable();
+            biases.dedup();
+            let is_bracket = biases.len() > 1 || group.iter().any(|f| f.exposure_mode == Some(2));
+
+            let top = if is_bracket {
+                group.iter().min_by(|a, b| {
+                    let a = a.exposure_bias.unwrap_or(0.0).abs();
+                    let b = b.exposure_bias.unwrap_or(0.0).abs();
+                    a.total_cmp(&b)
+                })
+            } else {
+                // max_by_key keeps the last maximum, so reverse to prefer the earliest frame
+                group.iter().rev().max_by_key(|f| f.rating)
+            };
+
+            tx.execute(
+                "INSERT INTO stacks (top_photo_id, kind) VALUES (?1, ?2)",
+                params![top.map(|f| f.photo_id), if is_bracket { "bracket" } else { "burst" }],
+            )?;
+            let stack_id = tx.last_insert_rowid();
+
+            for frame in group {
+                tx.execute(
+                    "UPDATE photos SET stack_id = ?1 WHERE id = ?2",
+                    params![stack_id, frame.photo_id],
+                )?;
+            }
+            stack_ids.push(stack_id);
+        }
+
+        tx.commit()?;
+        info!("Auto-stacking created {} stacks", stack_ids.len());
+
+        stack_ids.into_iter().map(|id| self.get_stack(id)).collect()
+    }
+
+    pub fn get_stack(&self, stack_id: i64) -> SqlResult<Stack> {
+        self.conn.query_row(
+            &format!("SELECT {} FROM stacks s WHERE s.id = ?1", STACK_COLUMNS),
+            params![stack_id],
+            Self::stack_from_row,
+        )
+    }
+
+    pub fn get_all_stacks(&self) -> SqlResult<Vec<Stack>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM stacks s ORDER BY s.created_at DESC",
+            STACK_COLUMNS
+        ))?;
+
+        let stacks = stmt.query_map([], Self::stack_from_row)?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(stacks)
+    }
+
+    pub fn get_stack_photos(&self, stack_id: i64) -> SqlResult<Vec<Photo>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}
+             FROM photos p
+             LEFT JOIN photo_exif e ON e.photo_id = p.id
+             WHERE p.stack_id = ?1
+             ORDER BY e.capture_date, e.subsec_time, p.id",
+            PHOTO_COLUMNS
+        ))?;
+
+        let photos = stmt.query_map(params![stack_id], Self::photo_from_row)?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        Ok(photos)
+    }
+
+    pub fn set_stack_collapsed(&self, stack_id: i64, collapsed: bool) -> SqlResult<()> {
+        self.conn.execute(
+            "UPDATE stacks SET is_collapsed = ?1 WHERE id = ?2",
+            params![collapsed as i32, stack_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_stack_top(&self, stack_id: i64, photo_id: i64) -> SqlResult<()> {
+        let updated = self.conn.execute(
+            "UPDATE stacks SET top_photo_id = ?1
+             WHERE id = ?2 AND EXISTS (SELECT 1 FROM photos WHERE id = ?1 AND stack_id = ?2)",
+            params![photo_id, stack_id],
+        )?;
+
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    // Keeps a stack usable after photos left it: with fewer than two photos
+    // left it is dissolved, and if its top is gone the lowest-id photo becomes
+    // the top. Runs in the caller's transaction.
+    pub(super) fn repair_stack(&self, stack_id: i64) -> SqlResult<()> {
+        let remaining: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM photos WHERE stack_id = ?1",
+            params![stack_id],
+            |row| row.get(0),
+        )?;
+        if remaining < 2 {
+            self.conn.execute("UPDATE photos SET stack_id = NULL WHERE stack_id = ?1", params![stack_id])?;
+            self.conn.execute("DELETE FROM stacks WHERE id = ?1", params![stack_id])?;
+            return Ok(());
+        }
+
+        self.conn.execute(
+            "UPDATE stacks SET top_photo_id = (SELECT MIN(id) FROM photos WHERE stack_id = ?1)
+             WHERE id = ?1 AND (top_photo_id IS NULL OR top_photo_id NOT IN (SELECT id FROM photos WHERE stack_id = ?1))",
+            params![stack_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn unstack(&self, stack_id: i64) -> SqlResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("UPDATE photos SET stack_id = NULL WHERE stack_id = ?1", params![stack_id])?;
+        tx.execute("DELETE FROM stacks WHERE id = ?1", params![stack_id])?;
+        tx.commit()
+    }
+}
@@ -0,0 +1,244 @@
+use rusqlite::{Result as SqlResult, ToSql};
+use serde::{Deserialize, Serialize};
+use super::DatabaseService;
+
+// A keeper is a picked photo, or one rated at least this and not rejected
+const KEEPER_MIN_RATING: i32 = 3;
+
+// Capture day as "YYYY-MM-DD", preferring the EXIF date; EXIF-style
+// "YYYY:MM:DD" dates are normalized
+const CAPTURE_DAY_SQL: &str = "replace(substr(COALESCE(e.capture_date, p.capture_date), 1, 10), ':', '-')";
+
+// Folder of the photo's file: the path up to its last separator, without it
+const FOLDER_SQL: &str = "rtrim(rtrim(p.path, replace(replace(p.path, '/', ''), '\\', '')), '/\\')";
+
+// Upper bounds (exclusive) and labels; anything longer falls in the last one
+const FOCAL_LENGTH_BUCKETS: &[(f64, &str)] = &[
+    (24.0, "< 24 mm"),
+    (35.0, "24-35 mm"),
+    (50.0, "35-50 mm"),
+    (85.0, "50-85 mm"),
+    (135.0, "85-135 mm"),
+    (200.0, "135-200 mm"),
+    (400.0, "200-400 mm"),
+];
+const LONGEST_FOCAL_LENGTH: &str = "400+ mm";
+
+// One bucket per full stop, named after its lowest value
+const ISO_BUCKETS: &[(f64, &str)] = &[
+    (100.0, "< 100"),
+    (200.0, "100"),
+    (400.0, "200"),
+    (800.0, "400"),
+    (1600.0, "800"),
+    (3200.0, "1600"),
+    (6400.0, "3200"),
+    (12800.0, "6400"),
+    (25600.0, "12800"),
+];
+const HIGHEST_ISO: &str = "25600+";
+
+// Which photos `get_library_stats` covers; everything when empty. Filters
+// combine, e.g. a collection within a date range.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct StatsScope {
+    pub collection_id: Option<i64>,
+    pub folder: Option<String>,    // Including subfolders
+    pub date_from: Option<String>, // "YYYY-MM-DD" capture date, inclusive
+    pub date_to: Option<String>,   // "YYYY-MM-DD" capture date, inclusive
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatCount {
+    pub label: String,
+    pub count: i64,
+}
+
+// Photos taken on one day in one folder
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShootStats {
+    pub date: String,
+    pub folder: String,
+    pub photo_count: i64,
+    pub keeper_count: i64,
+    pub keeper_rate: f64, // keeper_count / photo_count
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FolderStorage {
+    pub folder: String,
+    pub photo_count: i64,
+    pub total_size: i64, // Bytes
+}
+
+// Photos without the EXIF field or capture date a breakdown needs are left
+// out of it, so its counts can add up to less than `photo_count`
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LibraryStats {
+    pub photo_count: i64,
+    pub total_size: i64,
+    pub keeper_count: i64,
+    pub by_month: Vec<StatCount>,        // "YYYY-MM", oldest first
+    pub by_camera: Vec<StatCount>,       // Most used first
+    pub by_lens: Vec<StatCount>,         // Most used first
+    pub by_focal_length: Vec<StatCount>, // Shortest first
+    pub by_aperture: Vec<StatCount>,     // "f/2.8", widest first
+    pub by_iso: Vec<StatCount>,          // Lowest first
+    pub by_rating: Vec<StatCount>,       // "0" to "5"
+    pub shoots: Vec<ShootStats>,         // Newest first
+    pub storage: Vec<FolderStorage>,     // Largest first
+}
+
+// CASE expression naming the bucket `column` falls in, NULL when unknown
+fn bucket_sql(column: &str, buckets: &[(f64, &str)], last: &str) -> String {
+    let whens: String = buckets
+        .iter()
+        .map(|(upper, label)| format!(" WHEN {} < {} THEN '{}'", column, upper, label))
+        .collect();
+    format!("CASE WHEN {} IS NULL OR {} <= 0 THEN NULL{} ELSE '{}' END", column, column, whens, last)
+}
+
+impl StatsScope {
+    // SQL condition over `photos p` and `photo_exif e`, pushing its
+    // parameters onto `params` in order
+    fn to_sql(&self, db: &DatabaseService, params: &mut Vec<Box<dyn ToSql>>) -> SqlResult<String> {
+        let mut conditions = Vec::new();
+
+        if let Some(collection_id) = self.collection_id {
+            match db.get_collection_rules(collection_id)? {
+                Some(rules) => conditions.push(format!("({})", rules.to_sql(params))),
+                None => {
+                    params.push(Box::new(collection_id));
+                    conditions.push("p.id IN (SELECT photo_id FROM photo_collections WHERE collection_id = ?)".to_string());
+                }
+            }
+        }
+        if let Some(folder) = &self.folder {
+            // Compared with a trailing separator so /photos does not match /photos2
+            let prefix = format!("{}{}", folder.trim_end_matches(['/', '\\']), std::path::MAIN_SEPARATOR);
+            params.push(Box::new(prefix.clone()));
+            params.push(Box::new(prefix));
+            conditions.push("substr(p.path, 1, length(?)) = ?".to_string());
+        }
+        if let Some(date_from) = &self.date_from {
+            params.push(Box::new(date_from.clone()));
+            conditions.push(format!("{} >= ?", CAPTURE_DAY_SQL));
+        }
+        if let Some(date_to) = &self.date_to {
+            params.push(Box::new(date_to.clone()));
+            conditions.push(format!("{} <= ?", CAPTURE_DAY_SQL));
+        }
+
+        Ok(match conditions.is_empty() {
+            true => "1".to_string(),
+            false => conditions.join(" AND "),
+        })
+    }
+}
+
+impl DatabaseService {
+    // Aggregates over the photos in `scope` and their EXIF data, all read
+    // from the same snapshot of the catalog
+    pub fn get_library_stats(&self, scope: &StatsScope) -> SqlResult<LibraryStats> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+        let condition = scope.to_sql(self, &mut params)?;
+        let param_refs: Vec<&dyn ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let scoped = format!(
+            "WITH scoped AS (
+                SELECT p.id, p.file_size, p.rating, p.flag,
+                       {day} AS capture_day, {folder} AS folder,
+                       (p.flag = 'picked' OR (p.flag <> 'rejected' AND p.rating >= {keeper})) AS is_keeper,
+                       e.camera_make, e.camera_model, e.lens_model, e.focal_length, e.aperture, e.iso
+                FROM photos p
+                LEFT JOIN photo_exif e ON e.photo_id = p.id
+                WHERE {condition}
+             )",
+            day = CAPTURE_DAY_SQL,
+            folder = FOLDER_SQL,
+            keeper = KEEPER_MIN_RATING,
+            condition = condition,
+        );
+
+        let counts = |label: &str, order_by: &str| -> SqlResult<Vec<StatCount>> {
+            let query = format!(
+                "{} SELECT {} AS label, COUNT(*) FROM scoped WHERE label IS NOT NULL GROUP BY label ORDER BY {}",
+                scoped, label, order_by
+            );
+            let mut stmt = tx.prepare(&query)?;
+            let counts = stmt.query_map(param_refs.as_slice(), |row| {
+                Ok(StatCount { label: row.get(0)?, count: row.get(1)? })
+            })?
+            .collect::<SqlResult<Vec<_>>>()?;
+            Ok(counts)
+        };
+
+        let (photo_count, total_size, keeper_count) = tx.query_row(
+            &format!(
+                "{} SELECT COUNT(*), COALESCE(SUM(file_size), 0), COALESCE(SUM(is_keeper), 0) FROM scoped",
+                scoped
+            ),
+            param_refs.as_slice(),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        let camera = "NULLIF(trim(CASE WHEN camera_model LIKE camera_make || '%' THEN camera_model \
+                      ELSE COALESCE(camera_make, '') || ' ' || COALESCE(camera_model, '') END), '')";
+        let mut stats = LibraryStats {
+            photo_count,
+            total_size,
+            keeper_count,
+            by_month: counts("substr(capture_day, 1, 7)", "label")?,
+            by_camera: counts(camera, "COUNT(*) DESC, label")?,
+            by_lens: counts("NULLIF(trim(lens_model), '')", "COUNT(*) DESC, label")?,
+            by_focal_length: counts(
+                &bucket_sql("focal_length", FOCAL_LENGTH_BUCKETS, LONGEST_FOCAL_LENGTH),
+                "MIN(focal_length)",
+            )?,
+            by_aperture: counts(
+                "CASE WHEN aperture > 0 THEN printf('f/%g', round(aperture, 1)) END",
+                "MIN(aperture)",
+            )?,
+            by_iso: counts(&bucket_sql("iso", ISO_BUCKETS, HIGHEST_ISO), "MIN(iso)")?,
+            by_rating: counts("CAST(rating AS TEXT)", "MIN(rating)")?,
+            ..Default::default()
+        };
+
+        let mut shoots = tx.prepare(&format!(
+            "{} SELECT capture_day, folder, COUNT(*), SUM(is_keeper)
+             FROM scoped WHERE capture_day IS NOT NULL
+             GROUP BY capture_day, folder
+             ORDER BY capture_day DESC, folder",
+            scoped
+        ))?;
+        stats.shoots = shoots.query_map(param_refs.as_slice(), |row| {
+            let photo_count: i64 = row.get(2)?;
+            let keeper_count: i64 = row.get(3)?;
+            Ok(ShootStats {
+                date: row.get(0)?,
+                folder: row.get(1)?,
+                photo_count,
+                keeper_count,
+                keeper_rate: keeper_count as f64 / photo_count as f64,
+            })
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+        let mut storage = tx.prepare(&format!(
+            "{} SELECT folder, COUNT(*), COALESCE(SUM(file_size), 0)
+             FROM scoped GROUP BY folder
+             ORDER BY 3 DESC, folder",
+            scoped
+        ))?;
+        stats.storage = storage.query_map(param_refs.as_slice(), |row| {
+            Ok(FolderStorage { folder: row.get(0)?, photo_count: row.get(1)?, total_size: row.get(2)? })
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+        drop((shoots, storage));
+        tx.commit()?;
+        Ok(stats)
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::services::db::is_corruption;
+
+// Errors returned to the frontend, serialized as {"code": "file_not_found",
+// "path": "..."} and so on. `Context` records what was being done when a
+// lower-level error occurred; follow `source` to the underlying error.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum AppError {
+    DatabaseNotInitialized,
+    NotFound { message: String },
+    FileNotFound { path: String },
+    PermissionDenied { path: String },
+    AlreadyExists { path: String },
+    DuplicateName { name: String },
+    UnsupportedFormat { path: Option<String>, message: String },
+    InvalidInput { message: String },
+    Conflict { message: String },
+    ChecksumMismatch { path: String },
+    Io { path: Option<String>, message: String },
+    Image { path: Option<String>, message: String },
+    Exif { path: Option<String>, message: String },
+    Database { message: String },
+    DatabaseCorrupt { message: String },
+    Context {
+        message: String,
+        photo_id: Option<i64>,
+        source: Box<AppError>,
+    },
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+fn path_string(path: impl AsRef<Path>) -> String {
+    path.as_ref().to_string_lossy().to_string()
+}
+
+impl AppError {
+    // Classifies an I/O error on `path`
+    pub fn io(path: impl AsRef<Path>, e: std::io::Error) -> Self {
+        let path = path_string(path);
+        match e.kind() {
+            ErrorKind::NotFound => AppError::FileNotFound { path },
+            ErrorKind::PermissionDenied => AppError::PermissionDenied { path },
+            ErrorKind::AlreadyExists => AppError::AlreadyExists { path },
+            _ => AppError::Io { path: Some(path), message: e.to_string() },
+        }
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        AppError::InvalidInput { message: message.into() }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        AppError::NotFound { message: message.into() }
+    }
+
+    pub fn exif(path: impl AsRef<Path>, message: impl fmt::Display) -> Self {
+        AppError::Exif { path: Some(path_string(path)), message: message.to_string() }
+    }
+
+    // Decoding and encoding failures; unknown formats get their own code
+    pub fn image(path: impl AsRef<Path>, e: image::ImageError) -> Self {
+        let path = path_string(path);
+        match e {
+            image::ImageError::Unsupported(e) => AppError::UnsupportedFormat { path: Some(path), message: e.to_string() },
+            image::ImageError::IoError(e) => AppError::io(path, e),
+            e => AppError::Image { path: Some(path), message: e.to_string() },
+        }
+    }
+
+    pub fn context(self, message: impl Into<String>) -> Self {
+        AppError::Context { message: message.into(), photo_id: None, source: Box::new(self) }
+    }
+
+    // Adds the photo the failed operation was about
+    pub fn for_photo(self, photo_id: i64) -> Self {
+        match self {
+            AppError::Context { message, source, .. } => {
+                AppError::Context { message, photo_id: Some(photo_id), source }
+            }
+            e => AppError::Context {
+                message: format!("Photo {}", photo_id),
+                photo_id: Some(photo_id),
+                source: Box::new(e),
+            },
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::DatabaseNotInitialized => write!(f, "Database not initialized"),
+            AppError::NotFound { message } => write!(f, "{}", message),
+            AppError::FileNotFound { path } => write!(f, "File not found: {}", path),
+            AppError::PermissionDenied { path } => write!(f, "Permission denied: {}", path),
+            AppError::AlreadyExists { path } => write!(f, "{} already exists", path),
+            AppError::DuplicateName { name } => write!(f, "A collection named \"{}\" already exists", name),
+            AppError::UnsupportedFormat { path: Some(path), message } => write!(f, "Unsupported format of {}: {}", path, message),
+            AppError::UnsupportedFormat { path: None, message } => write!(f, "Unsupported format: {}", message),
+            AppError::InvalidInput { message } | AppError::Conflict { message } | AppError::Database { message } => {
+                write!(f, "{}", message)
+            }
+            AppError::DatabaseCorrupt { message } => write!(f, "The catalog is damaged: {}", message),
+            AppError::ChecksumMismatch { path } => write!(f, "Checksum mismatch: {}", path),
+            AppError::Io { path: Some(path), message }
+            | AppError::Image { path: Some(path), message }
+            | AppError::Exif { path: Some(path), message } => write!(f, "{}: {}", path, message),
+            AppError::Io { path: None, message }
+            | AppError::Image { path: None, message }
+            | AppError::Exif { path: None, message } => write!(f, "{}", message),
+            AppError::Context { message, source, .. } => write!(f, "{}: {}", message, source),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        match &e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("No such record"),
+            rusqlite::Error::SqliteFailure(err, _) if err.code == rusqlite::ErrorCode::ConstraintViolation => {
+                AppError::Conflict { message: e.to_string() }
+            }
+            _ if is_corruption(&e) => AppError::DatabaseCorrupt { message: e.to_string() },
+            _ => AppError::Database { message: e.to_string() },
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::invalid(e.to_string())
+    }
+}
+
+// Failures resolving app directories
+impl From<tauri::Error> for AppError {
+    fn from(e: tauri::Error) -> Self {
+        AppError::Io { path: None, message: e.to_string() }
+    }
+}
+
+// `.context("Failed to get photo")` on any result whose error converts to `AppError`
+pub trait ResultExt<T> {
+    fn context(self, message: &str) -> AppResult<T>;
+    fn for_photo(self, photo_id: i64) -> AppResult<T>;
+}
+
+impl<T, E: Into<AppError>> ResultExt<T> for Result<T, E> {
+    fn context(self, message: &str) -> AppResult<T> {
+        self.map_err(|e| e.into().context(message))
+    }
+
+    fn for_photo(self, photo_id: i64) -> AppResult<T> {
+        self.map_err(|e| e.into().for_photo(photo_id))
+    }
+}
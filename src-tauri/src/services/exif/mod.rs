@@ -1,9 +1,14 @@
 use std::fs::File;
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
 use log::{info, warn};
+use crate::services::error::{AppError, AppResult};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+mod motion;
+mod xmp;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct EXIFData {
     pub camera_make: Option<String>,
     pub camera_model: Option<String>,
@@ -19,16 +24,38 @@ pub struct EXIFData {
     pub gps_latitude: Option<f64>,
     pub gps_longitude: Option<f64>,
     pub gps_altitude: Option<f64>,
+    pub subsec_time: Option<String>,
+    pub exposure_mode: Option<u32>, // 0 = auto, 1 = manual, 2 = auto bracket
+    pub burst_id: Option<String>,
+}
+
+// Culling state exchanged with other tools through XMP
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct XmpLabels {
+    pub rating: Option<i32>,         // 0-5, None if absent or rejected
+    pub rejected: bool,              // xmp:Rating = -1
+    pub color_label: Option<String>, // "red" through "purple"
 }
 
 pub struct EXIFService;
 
+// ASCII fields without the quoting that `display_value` adds
+fn ascii_value(field: &exif::Field) -> Option<String> {
+    match field.value {
+        exif::Value::Ascii(ref values) => values
+            .first()
+            .map(|v| String::from_utf8_lossy(v).trim_end_matches('\0').trim().to_string())
+            .filter(|v| !v.is_empty()),
+        _ => None,
+    }
+}
+
 impl EXIFService {
-    pub fn extract_exif(path: &str) -> Result<EXIFData, String> {
+    pub fn extract_exif(path: &str) -> AppResult<EXIFData> {
         info!("Extracting EXIF from: {}", path);
 
         let file = File::open(path)
-            .map_err(|e| format!("Failed to open file: {}", e))?;
+            .map_err(|e| AppError::io(path, e).context("Failed to open file"))?;
 
         let mut bufreader = BufReader::new(&file);
         let exifreader = exif::Reader::new();
@@ -45,17 +72,17 @@ impl EXIFService {
 
         // Camera make
         if let Some(field) = exif.get_field(exif::Tag::Make, exif::In::PRIMARY) {
-            data.camera_make = Some(field.display_value().to_string());
+            data.camera_make = ascii_value(field);
         }
 
         // Camera model
         if let Some(field) = exif.get_field(exif::Tag::Model, exif::In::PRIMARY) {
-            data.camera_model = Some(field.display_value().to_string());
+            data.camera_model = ascii_value(field);
         }
 
         // Lens model
         if let Some(field) = exif.get_field(exif::Tag::LensModel, exif::In::PRIMARY) {
-            data.lens_model = Some(field.display_value().to_string());
+            data.lens_model = ascii_value(field);
         }
 
         // Focal length
@@ -153,27 +180,103 @@ impl EXIFService {
             }
         }
 
+        // Sub-second part of the capture time, needed to order burst frames
+        if let Some(field) = exif.get_field(exif::Tag::SubSecTimeOriginal, exif::In::PRIMARY) {
+            data.subsec_time = ascii_value(field);
+        }
+
+        // Exposure mode (auto bracketing marks HDR sets)
+        if let Some(field) = exif.get_field(exif::Tag::ExposureMode, exif::In::PRIMARY) {
+            data.exposure_mode = field.value.get_uint(0);
+        }
+
+        // Apple burst identifier, shared by all frames of an iPhone burst
+        if let Some(field) = exif.get_field(exif::Tag::MakerNote, exif::In::PRIMARY) {
+            if let exif::Value::Undefined(ref bytes, _) = field.value {
+                data.burst_id = motion::apple_maker_note_string(bytes, motion::APPLE_BURST_UUID_TAG);
+            }
+        }
+
         Ok(data)
     }
-}
 
-impl Default for EXIFData {
-    fn default() -> Self {
-        EXIFData {
-            camera_make: None,
-            camera_model: None,
-            lens_model: None,
-            focal_length: None,
-            aperture: None,
-            shutter_speed: None,
-            iso: None,
-            exposure_bias: None,
-            flash: None,
-            orientation: None,
-            capture_date: None,
-            gps_latitude: None,
-            gps_longitude: None,
-            gps_altitude: None,
+    // Apple ContentIdentifier of a Live Photo still (HEIC/JPEG), stored in the maker note
+    pub fn read_content_identifier(path: &str) -> Option<String> {
+        let file = File::open(path).ok()?;
+        let exif = exif::Reader::new()
+            .read_from_container(&mut BufReader::new(&file))
+            .ok()?;
+
+        match exif.get_field(exif::Tag::MakerNote, exif::In::PRIMARY)?.value {
+            exif::Value::Undefined(ref bytes, _) => {
+                motion::apple_maker_note_string(bytes, motion::APPLE_CONTENT_IDENTIFIER_TAG)
+            }
+            _ => None,
         }
     }
+
+    // Apple ContentIdentifier of a Live Photo video (MOV), stored in QuickTime metadata
+    pub fn read_video_content_identifier(path: &str) -> Option<String> {
+        let mut file = File::open(path).ok()?;
+        motion::quicktime_content_identifier(&mut file)
+    }
+
+    // Byte offset of the MP4 clip embedded in a Google/Android Motion Photo
+    pub fn find_motion_photo_offset(path: &str) -> AppResult<Option<u64>> {
+        let mut file = File::open(path)
+            .map_err(|e| AppError::io(path, e).context("Failed to open file"))?;
+
+        motion::motion_photo_video_offset(&mut file)
+            .map_err(|e| AppError::exif(path, e).context("Failed to read motion photo data"))
+    }
+
+    // XMP sidecars next to the photo, in both the IMG_0001.xmp and IMG_0001.CR2.xmp conventions
+    pub fn find_xmp_sidecars(path: &str) -> Vec<PathBuf> {
+        xmp::existing_sidecars(Path::new(path))
+    }
+
+    // Rating, reject and color label from the photo's XMP sidecar, or its
+    // embedded XMP if there is no sidecar. Labels other than Lightroom's five
+    // colors are ignored.
+    pub fn read_xmp_labels(path: &str) -> AppResult<XmpLabels> {
+        let packet = xmp::read_packet(Path::new(path))
+            .map_err(|e| AppError::exif(path, e).context("Failed to read XMP"))?;
+        let Some(packet) = packet else { return Ok(XmpLabels::default()) };
+
+        let rating = xmp::property(&packet, "xmp:Rating")
+            .and_then(|r| r.parse::<f64>().ok())
+            .map(|r| r.round() as i32);
+        let color_label = xmp::property(&packet, "xmp:Label").and_then(|label| {
+            xmp::COLOR_LABELS
+                .iter()
+                .find(|c| c.eq_ignore_ascii_case(label))
+                .map(|c| c.to_lowercase())
+        });
+
+        Ok(XmpLabels {
+            rating: rating.filter(|r| (0..=5).contains(r)),
+            rejected: rating == Some(xmp::REJECT_RATING),
+            color_label,
+        })
+    }
+
+    // Writes rating, reject and color label to the photo's XMP sidecar, creating
+    // it if needed, and returns the sidecar path
+    pub fn write_xmp_labels(path: &str, labels: &XmpLabels) -> AppResult<String> {
+        let rating = if labels.rejected {
+            Some(xmp::REJECT_RATING.to_string())
+        } else {
+            labels.rating.map(|r| r.to_string())
+        };
+        let color_label = labels.color_label.as_deref().and_then(|label| {
+            xmp::COLOR_LABELS.iter().find(|c| c.eq_ignore_ascii_case(label)).copied()
+        });
+
+        xmp::write_sidecar_properties(
+            Path::new(path),
+            &[("xmp:Rating", rating.as_deref()), ("xmp:Label", color_label)],
+        )
+        .map(|p| p.to_string_lossy().to_string())
+        .map_err(|e| AppError::io(path, e).context("Failed to write XMP sidecar"))
+    }
 }
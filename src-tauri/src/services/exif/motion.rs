@@ -0,0 +1,213 @@
+// Low-level parsers for Live Photo and Motion Photo metadata that kamadak-exif
+// does not expose (Apple maker notes, QuickTime metadata atoms, Google XMP).
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use super::xmp;
+
+const APPLE_MAKER_NOTE_HEADER: &[u8] = b"Apple iOS\0";
+pub const APPLE_BURST_UUID_TAG: u16 = 0x000b;
+pub const APPLE_CONTENT_IDENTIFIER_TAG: u16 = 0x0011;
+const QUICKTIME_CONTENT_IDENTIFIER_KEY: &[u8] = b"com.apple.quicktime.content.identifier";
+// Guards against reading a corrupt size field as a multi-gigabyte atom
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+
+fn be_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn trim_ascii(bytes: &[u8]) -> Option<String> {
+    let value = String::from_utf8_lossy(bytes)
+        .trim_end_matches('\0')
+        .trim()
+        .to_string();
+    if value.is_empty() { None } else { Some(value) }
+}
+
+// Reads an ASCII entry such as the ContentIdentifier from an Apple maker note
+// ("Apple iOS" IFD, big-endian, offsets relative to the start of the maker note).
+pub fn apple_maker_note_string(maker_note: &[u8], wanted_tag: u16) -> Option<String> {
+    if !maker_note.starts_with(APPLE_MAKER_NOTE_HEADER) || maker_note.get(12..14)? != b"MM" {
+        return None;
+    }
+
+    let count = be_u16(maker_note, 14)? as usize;
+    for i in 0..count {
+        let entry = 16 + i * 12;
+        let tag = be_u16(maker_note, entry)?;
+        let field_type = be_u16(maker_note, entry + 2)?;
+        let len = be_u32(maker_note, entry + 4)? as usize;
+
+        // Type 2 is ASCII
+        if tag != wanted_tag || field_type != 2 {
+            continue;
+        }
+
+        let bytes = if len <= 4 {
+            maker_note.get(entry + 8..entry + 8 + len)?
+        } else {
+            let offset = be_u32(maker_note, entry + 8)? as usize;
+            maker_note.get(offset..offset + len)?
+        };
+        return trim_ascii(bytes);
+    }
+
+    None
+}
+
+// Iterates over the atoms directly contained in `data` as (type, payload) pairs.
+fn atoms(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut result = Vec::new();
+    let mut pos = 0;
+
+    while pos + 8 <= data.len() {
+        let size = be_u32(data, pos).unwrap_or(0) as usize;
+        let kind = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
+        let end = match size {
+            0 => data.len(),
+            1 => break, // 64-bit sizes never occur inside moov metadata
+            s if s < 8 || pos + s > data.len() => break,
+            s => pos + s,
+        };
+        result.push((kind, &data[pos + 8..end]));
+        pos = end;
+    }
+
+    result
+}
+
+fn find_atom<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    atoms(data)
+        .into_iter()
+        .find(|(k, _)| k == kind)
+        .map(|(_, payload)| payload)
+}
+
+fn read_moov(file: &mut File) -> std::io::Result<Option<Vec<u8>>> {
+    let len = file.metadata()?.len();
+    let mut pos = 0u64;
+
+    while len - pos >= 8 {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = len - pos;
+        }
+
+        // Sizes come from the file; one running past its end means it is corrupt
+        if size < header_len || size > len - pos {
+            return Ok(None);
+        }
+
+        if &header[4..8] == b"moov" {
+            if size > MAX_MOOV_SIZE {
+                return Ok(None);
+            }
+            let mut moov = vec![0u8; (size - header_len) as usize];
+            file.read_exact(&mut moov)?;
+            return Ok(Some(moov));
+        }
+
+        pos = match pos.checked_add(size) {
+            Some(next) if next <= len => next,
+            _ => return Ok(None),
+        };
+    }
+
+    Ok(None)
+}
+
+// Reads `com.apple.quicktime.content.identifier` from a Live Photo MOV.
+pub fn quicktime_content_identifier(file: &mut File) -> Option<String> {
+    let moov = read_moov(file).ok()??;
+    let mut meta = find_atom(&moov, b"meta")?;
+
+    // QuickTime-style meta has no version/flags, ISO-style meta does
+    if meta.len() >= 4 && meta[..4] == [0, 0, 0, 0] {
+        meta = &meta[4..];
+    }
+
+    // keys: version/flags, entry count, then (size, namespace, name) entries
+    let keys = find_atom(meta, b"keys")?;
+    let count = be_u32(keys, 4)? as usize;
+    let mut pos = 8;
+    let mut key_index = None;
+    for index in 1..=count {
+        let size = be_u32(keys, pos)? as usize;
+        if size < 8 {
+            return None;
+        }
+        if keys.get(pos + 8..pos + size)? == QUICKTIME_CONTENT_IDENTIFIER_KEY {
+            key_index = Some(index as u32);
+            break;
+        }
+        pos += size;
+    }
+    let key_index = key_index?;
+
+    // ilst items are named by their 1-based key index and wrap a data atom:
+    // type indicator (4), locale (4), value
+    let ilst = find_atom(meta, b"ilst")?;
+    atoms(ilst)
+        .into_iter()
+        .find(|(kind, _)| u32::from_be_bytes(*kind) == key_index)
+        .and_then(|(_, item)| find_atom(item, b"data"))
+        .and_then(|data| trim_ascii(data.get(8..)?))
+}
+
+// Returns the length of the MP4 trailer declared in a Google Motion Photo's XMP,
+// covering both the `MicroVideo` (v1) and `MotionPhoto` container (v2) formats.
+fn motion_photo_video_length(xmp: &str) -> Option<u64> {
+    if xmp::property(xmp, "GCamera:MicroVideo") == Some("1") {
+        return xmp::property(xmp, "GCamera:MicroVideoOffset")?.parse().ok();
+    }
+
+    if xmp::property(xmp, "GCamera:MotionPhoto") == Some("1") {
+        let semantic = xmp.find("Item:Semantic=\"MotionPhoto\"")?;
+        let start = xmp[..semantic].rfind('<')?;
+        let end = semantic + xmp[semantic..].find('>')?;
+        return xmp::property(&xmp[start..end], "Item:Length")?.parse().ok();
+    }
+
+    None
+}
+
+// Locates the MP4 embedded at the end of a Google/Android Motion Photo JPEG and
+// returns its byte offset. The offset is only trusted if an `ftyp` box sits there.
+pub fn motion_photo_video_offset(file: &mut File) -> std::io::Result<Option<u64>> {
+    let file_len = file.metadata()?.len();
+
+    let xmp = match xmp::embedded_packet(file)? {
+        Some(xmp) => xmp,
+        None => return Ok(None),
+    };
+
+    let length = match motion_photo_video_length(&xmp) {
+        Some(length) if length > 0 && length < file_len => length,
+        _ => return Ok(None),
+    };
+    let offset = file_len - length;
+
+    let mut box_header = [0u8; 8];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut box_header)?;
+    if &box_header[4..8] != b"ftyp" {
+        return Ok(None);
+    }
+
+    Ok(Some(offset))
+}
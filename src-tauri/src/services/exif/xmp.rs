@@ -0,0 +1,202 @@
+// Minimal XMP reading and sidecar writing for the properties the catalog shares
+// with other tools (Lightroom, Bridge, darktable): xmp:Rating and xmp:Label.
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const XMP_NAMESPACE: &str = "http://ns.adobe.com/xap/1.0/";
+
+// XMP lives in the first APP1 segments, so there is no need to read the whole file
+const XMP_SEARCH_LIMIT: u64 = 256 * 1024;
+
+// xmp:Rating value Adobe applications use for rejected photos
+pub const REJECT_RATING: i32 = -1;
+
+// The label names Lightroom writes for its five color labels
+pub const COLOR_LABELS: [&str; 5] = ["Red", "Yellow", "Green", "Blue", "Purple"];
+
+const SIDECAR_TEMPLATE: &str = "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">
+  <rdf:Description rdf:about=\"\"
+    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"/>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end=\"w\"?>
+";
+
+// Byte range of an attribute-form property (`xmp:Rating="3"`), from the start
+// of its name to the end of its value
+fn find_attribute(xmp: &str, name: &str) -> Option<(usize, std::ops::Range<usize>)> {
+    let pattern = format!("{}=\"", name);
+    let mut from = 0;
+    while let Some(pos) = xmp[from..].find(&pattern).map(|i| from + i) {
+        // Skip longer names that merely end with `name`
+        if xmp[..pos].ends_with(char::is_whitespace) {
+            let value_start = pos + pattern.len();
+            let value_end = value_start + xmp[value_start..].find('"')?;
+            return Some((pos, value_start..value_end));
+        }
+        from = pos + pattern.len();
+    }
+    None
+}
+
+// Byte range of an element-form property (`<xmp:Rating>3</xmp:Rating>`),
+// including the tags, and of its content
+fn find_element(xmp: &str, name: &str) -> Option<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xmp.find(&open)?;
+    let content_start = start + open.len();
+    let content_end = content_start + xmp[content_start..].find(&close)?;
+    Some((start..content_end + close.len(), content_start..content_end))
+}
+
+// Reads a simple property in either attribute or element form
+pub fn property<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    if let Some((_, value)) = find_attribute(xmp, name) {
+        return Some(&xmp[value]);
+    }
+    find_element(xmp, name).map(|(_, content)| xmp[content].trim())
+}
+
+// Replaces, inserts or (with `None`) removes a simple property, keeping
+// everything else in the packet intact
+fn set_property(xmp: &str, name: &str, value: Option<&str>) -> String {
+    if let Some((start, range)) = find_attribute(xmp, name) {
+        return match value {
+            Some(value) => format!("{}{}{}", &xmp[..range.start], value, &xmp[range.end..]),
+            // Also drop the whitespace that separated the attribute from the previous one
+            None => format!("{}{}", xmp[..start].trim_end(), &xmp[range.end + 1..]),
+        };
+    }
+
+    if let Some((whole, content)) = find_element(xmp, name) {
+        return match value {
+            Some(value) => format!("{}{}{}", &xmp[..content.start], value, &xmp[content.end..]),
+            None => format!("{}{}", &xmp[..whole.start], &xmp[whole.end..]),
+        };
+    }
+
+    let Some(value) = value else { return xmp.to_string() };
+    let Some(description) = xmp.find("<rdf:Description") else { return xmp.to_string() };
+    let insert_at = description + "<rdf:Description".len();
+    let namespace = if xmp.contains("xmlns:xmp=") {
+        String::new()
+    } else {
+        format!("\n    xmlns:xmp=\"{}\"", XMP_NAMESPACE)
+    };
+    format!("{}{}\n    {}=\"{}\"{}", &xmp[..insert_at], namespace, name, value, &xmp[insert_at..])
+}
+
+// The Adobe convention: IMG_0001.CR2 -> IMG_0001.xmp
+fn stem_sidecar_path(image_path: &Path) -> PathBuf {
+    image_path.with_extension("xmp")
+}
+
+// darktable and others append instead: IMG_0001.CR2 -> IMG_0001.CR2.xmp
+fn appended_sidecar_path(image_path: &Path) -> PathBuf {
+    let mut path = image_path.as_os_str().to_owned();
+    path.push(".xmp");
+    PathBuf::from(path)
+}
+
+// Camera RAW formats, whose files own IMG_0001.xmp in Adobe applications
+const RAW_EXTENSIONS: &[&str] = &[
+    "3fr", "arw", "cr2", "cr3", "crw", "dng", "erf", "iiq", "kdc", "mrw", "nef", "nrw", "orf", "pef", "raf", "raw",
+    "rw2", "rwl", "sr2", "srf", "srw", "x3f",
+];
+
+// Clips that share a photo's name without being photos, e.g. a Live Photo's
+const VIDEO_EXTENSIONS: &[&str] = &["mov", "mp4", "m4v"];
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+// Whether IMG_0001.xmp belongs to `image_path`: it is the only photo named
+// IMG_0001 in its folder, or the one RAW among them, like the RAW of a
+// RAW+JPEG pair. Sidecars and clips do not count, so the HEIC of a Live
+// Photo keeps it. Names are compared without case, as on case-insensitive
+// file systems.
+fn owns_stem_sidecar(image_path: &Path) -> bool {
+    let (Some(dir), Some(stem)) = (image_path.parent(), image_path.file_stem()) else { return true };
+    let stem = stem.to_string_lossy();
+    let Ok(entries) = fs::read_dir(dir) else { return true };
+
+    let mut others = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).filter(|path| {
+        path != image_path
+            && path.file_stem().is_some_and(|other| other.to_string_lossy().eq_ignore_ascii_case(&stem))
+            && !has_extension(path, &["xmp"])
+            && !has_extension(path, VIDEO_EXTENSIONS)
+    });
+    match has_extension(image_path, RAW_EXTENSIONS) {
+        true => !others.any(|path| has_extension(&path, RAW_EXTENSIONS)),
+        false => others.next().is_none(),
+    }
+}
+
+// The sidecar written for `image_path`. IMG_0001.xmp would be shared by every
+// file named IMG_0001, so the JPEG of a RAW+JPEG pair (or any photo that is
+// one of several) uses the appended form, leaving IMG_0001.xmp to the RAW.
+pub fn sidecar_path(image_path: &Path) -> PathBuf {
+    match owns_stem_sidecar(image_path) {
+        true => stem_sidecar_path(image_path),
+        false => appended_sidecar_path(image_path),
+    }
+}
+
+// Sidecars of `image_path` that exist, in either naming convention; the
+// IMG_0001.xmp form only for the photo that owns it
+pub fn existing_sidecars(image_path: &Path) -> Vec<PathBuf> {
+    let stem_sidecar = Some(stem_sidecar_path(image_path)).filter(|_| owns_stem_sidecar(image_path));
+    stem_sidecar
+        .into_iter()
+        .chain(std::iter::once(appended_sidecar_path(image_path)))
+        .filter(|p| p.is_file() && p != image_path)
+        .collect()
+}
+
+pub fn embedded_packet(file: &mut File) -> std::io::Result<Option<String>> {
+    let mut head = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.by_ref().take(XMP_SEARCH_LIMIT).read_to_end(&mut head)?;
+
+    let start = match head.windows(XMP_HEADER.len()).position(|w| w == XMP_HEADER) {
+        Some(pos) => pos + XMP_HEADER.len(),
+        None => return Ok(None),
+    };
+    Ok(Some(String::from_utf8_lossy(&head[start..]).into_owned()))
+}
+
+// The packet a photo's XMP properties are read from: a sidecar wins over the
+// embedded packet, as in Lightroom
+pub fn read_packet(image_path: &Path) -> std::io::Result<Option<String>> {
+    if let Some(sidecar) = existing_sidecars(image_path).into_iter().next() {
+        return fs::read_to_string(sidecar).map(Some);
+    }
+    embedded_packet(&mut File::open(image_path)?)
+}
+
+// Updates (or creates) the sidecar of `image_path`. A `None` value removes the property.
+pub fn write_sidecar_properties(image_path: &Path, properties: &[(&str, Option<&str>)]) -> std::io::Result<PathBuf> {
+    let path = existing_sidecars(image_path)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| sidecar_path(image_path));
+
+    let mut xmp = if path.is_file() {
+        fs::read_to_string(&path)?
+    } else {
+        SIDECAR_TEMPLATE.to_string()
+    };
+    for (name, value) in properties {
+        xmp = set_property(&xmp, name, *value);
+    }
+
+    fs::write(&path, xmp)?;
+    Ok(path)
+}
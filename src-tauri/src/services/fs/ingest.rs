@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::services::error::{AppError, AppResult};
+use super::{FileJournal, FileSystemService};
+
+const DEFAULT_FOLDER_TEMPLATE: &str = "{YYYY}/{YYYY-MM-DD}";
+
+fn default_folder_template() -> String {
+    DEFAULT_FOLDER_TEMPLATE.to_string()
+}
+
+// Copying photos from a card (or any folder) into the library
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IngestOptions {
+    pub source: String,
+    pub destination: String,
+    #[serde(default = "default_folder_template")]
+    pub folder_template: String,            // Relative to `destination`, see `FolderTemplate`
+    pub backup_destination: Option<String>, // Second copy, same folder layout
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub collection_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IngestedPhoto {
+    pub source: String,
+    pub photo_id: i64,
+    pub path: String,
+    pub backup_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IngestSkip {
+    pub source: String,
+    pub existing_photo_id: Option<i64>, // None when the card holds the same file twice
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IngestFailure {
+    pub source: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct IngestReport {
+    pub imported: Vec<IngestedPhoto>,
+    pub skipped: Vec<IngestSkip>,
+    pub failed: Vec<IngestFailure>,
+}
+
+// `path`, or `name_1.ext`, `name_2.ext`... if it is taken. Two cameras (or one
+// camera after a counter reset) easily produce the same file names.
+pub fn unique_destination(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{}_{}{}", stem, n, ext)))
+        .find(|candidate| !candidate.exists())
+        .expect("unbounded counter")
+}
+
+// Copies `from` to `to` and checks the copy against the source checksum
+pub fn copy_verified(journal: &mut FileJournal, from: &Path, to: &Path, content_hash: &str) -> AppResult<()> {
+    journal.copy_file(from, to)?;
+
+    let copied = FileSystemService::hash_file(&to.to_string_lossy())?;
+    if copied != content_hash {
+        return Err(AppError::ChecksumMismatch { path: to.to_string_lossy().to_string() }
+            .context(format!("Failed to copy {}", from.display())));
+    }
+    Ok(())
+}
@@ -1,64 +1,127 @@
-use std::path::Path;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
 use log::{info, warn};
+use crate::services::error::{AppError, AppResult};
+use crate::services::exif::EXIFService;
+use scan::ExcludeRules;
+
+mod ingest;
+mod ops;
+mod relocate;
+mod scan;
+mod template;
+mod volume;
+
+pub use ingest::{IngestFailure, IngestOptions, IngestReport, IngestSkip, IngestedPhoto, copy_verified, unique_destination};
+pub use ops::{FileJournal, companion_destination};
+pub use relocate::{
+    AmbiguousMatch, LocateReport, RelocationReport, find_by_name_and_size, name_and_size_key, rebase_path, spread_sample,
+    verify_file,
+};
+pub use scan::{ScanError, ScanOptions, ScanResult};
+pub use template::{FolderTemplate, RenameOptions, RenamePreview, RenameTemplate, TemplateContext, parse_capture_date};
+pub use volume::{VolumeInfo, mounted_volumes, volume_containing};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ImageFile {
     pub path: String,
     pub filename: String,
     pub file_size: u64,
+    pub live_photo_video: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MotionInfo {
+    pub motion_type: Option<String>, // "live_photo" or "motion_photo"
+    pub video_path: Option<String>,
+    pub video_offset: Option<u64>,
+    pub content_identifier: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ScanProgress {
     pub current: usize,
     pub total: usize,
     pub current_file: String,
 }
 
+// Per-photo outcome of a move, rename, copy or trash
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct FileOperationResult {
+    pub photo_id: i64,
+    pub path: Option<String>,      // Where the file ended up
+    pub new_photo_id: Option<i64>, // Catalog entry of a copy
+    pub error: Option<String>,
+}
+
 const SUPPORTED_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "heic", "heif"];
+const LIVE_PHOTO_VIDEO_EXTENSIONS: &[&str] = &["mov", "MOV"];
+const MOTION_PHOTO_EXTENSIONS: &[&str] = &["jpg", "jpeg"];
 
 pub struct FileSystemService;
 
 impl FileSystemService {
-    pub fn scan_images(folder_path: &str) -> Result<Vec<ImageFile>, String> {
+    pub fn scan_images(folder_path: &str, options: &ScanOptions) -> AppResult<ScanResult> {
         info!("Scanning folder: {}", folder_path);
         
         let path = Path::new(folder_path);
         if !path.exists() {
-            return Err(format!("Folder does not exist: {}", folder_path));
+            return Err(AppError::FileNotFound { path: folder_path.to_string() });
         }
         if !path.is_dir() {
-            return Err(format!("Path is not a directory: {}", folder_path));
+            return Err(AppError::invalid(format!("Path is not a directory: {}", folder_path)));
         }
 
-        let mut images = Vec::new();
+        let rules = ExcludeRules::parse(&options.exclude_patterns)?;
+        let mut walker = WalkDir::new(path).follow_links(options.follow_symlinks);
+        if let Some(max_depth) = options.max_depth {
+            walker = walker.max_depth(max_depth);
+        }
+
+        let mut result = ScanResult::default();
         
-        for entry in WalkDir::new(path)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
+        // walkdir detects symlink loops and reports them as errors
+        for entry in walker.into_iter().filter_entry(|e| rules.accepts(e, path, options)) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Failed to scan {:?}: {}", e.path(), e);
+                    result.errors.push(ScanError {
+                        path: e.path().map(|p| p.to_string_lossy().to_string()),
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
             let entry_path = entry.path();
             
-            if !entry_path.is_file() {
+            if !entry.file_type().is_file() {
                 continue;
             }
 
             if let Some(ext) = entry_path.extension() {
                 let ext_str = ext.to_string_lossy().to_lowercase();
                 if SUPPORTED_EXTENSIONS.contains(&ext_str.as_str()) {
-                    let metadata = match std::fs::metadata(entry_path) {
+                    let metadata = match entry.metadata() {
                         Ok(m) => m,
                         Err(e) => {
                             warn!("Failed to read metadata for {:?}: {}", entry_path, e);
+                            result.errors.push(ScanError {
+                                path: Some(entry_path.to_string_lossy().to_string()),
+                                error: e.to_string(),
+                            });
                             continue;
                         }
                     };
+                    if metadata.len() < options.min_file_size.unwrap_or(0) {
+                        continue;
+                    }
 
-                    images.push(ImageFile {
+                    result.images.push(ImageFile {
                         path: entry_path.to_string_lossy().to_string(),
                         filename: entry_path
                             .file_name()
@@ -66,13 +129,14 @@ impl FileSystemService {
                             .to_string_lossy()
                             .to_string(),
                         file_size: metadata.len(),
+                        live_photo_video: Self::find_live_photo_video(entry_path),
                     });
                 }
             }
         }
 
-        info!("Found {} images in {}", images.len(), folder_path);
-        Ok(images)
+        info!("Found {} images in {} ({} errors)", result.images.len(), folder_path, result.errors.len());
+        Ok(result)
     }
 
     #[allow(dead_code)]
@@ -86,15 +150,11 @@ impl FileSystemService {
     }
 
     #[allow(dead_code)]
-    pub fn get_file_info(path: &str) -> Result<ImageFile, String> {
+    pub fn get_file_info(path: &str) -> AppResult<ImageFile> {
         let path_obj = Path::new(path);
-        
-        if !path_obj.exists() {
-            return Err(format!("File does not exist: {}", path));
-        }
 
         let metadata = std::fs::metadata(path_obj)
-            .map_err(|e| format!("Failed to read file metadata: {}", e))?;
+            .map_err(|e| AppError::io(path, e).context("Failed to read file metadata"))?;
 
         Ok(ImageFile {
             path: path.to_string(),
@@ -104,6 +164,122 @@ impl FileSystemService {
                 .to_string_lossy()
                 .to_string(),
             file_size: metadata.len(),
+            live_photo_video: Self::find_live_photo_video(path_obj),
+        })
+    }
+
+    // Live Photos are a still plus a MOV with the same stem next to it
+    pub fn find_live_photo_video(image_path: &Path) -> Option<String> {
+        LIVE_PHOTO_VIDEO_EXTENSIONS
+            .iter()
+            .map(|ext| image_path.with_extension(ext))
+            .find(|candidate| candidate.is_file())
+            .map(|candidate| candidate.to_string_lossy().to_string())
+    }
+
+    pub fn detect_motion(path: &str) -> AppResult<MotionInfo> {
+        let path_obj = Path::new(path);
+        let content_identifier = EXIFService::read_content_identifier(path);
+
+        if let Some(video_path) = Self::find_live_photo_video(path_obj) {
+            let video_identifier = EXIFService::read_video_content_identifier(&video_path);
+
+            // A same-named MOV from another shot is not a pair
+            match (&content_identifier, &video_identifier) {
+                (Some(still), Some(video)) if still != video => {
+                    warn!("Content identifier mismatch between {} and {}", path, video_path);
+                }
+                _ => {
+                    return Ok(MotionInfo {
+                        motion_type: Some("live_photo".to_string()),
+                        video_path: Some(video_path),
+                        video_offset: None,
+                        content_identifier: content_identifier.or(video_identifier),
+                    });
+                }
+            }
+        }
+
+        let is_jpeg = path_obj
+            .extension()
+            .map(|ext| MOTION_PHOTO_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+            .unwrap_or(false);
+
+        if is_jpeg {
+            if let Some(offset) = EXIFService::find_motion_photo_offset(path)? {
+                return Ok(MotionInfo {
+                    motion_type: Some("motion_photo".to_string()),
+                    video_path: Some(path.to_string()),
+                    video_offset: Some(offset),
+                    content_identifier,
+                });
+            }
+        }
+
+        Ok(MotionInfo {
+            content_identifier,
+            ..Default::default()
         })
     }
+
+    // Copies the MP4 trailer of a Motion Photo (everything from `offset` on) to `dest_path`
+    pub fn extract_motion_video(path: &str, offset: u64, dest_path: &Path) -> AppResult<()> {
+        info!("Extracting motion video from {} at offset {}", path, offset);
+
+        if let Some(parent) = dest_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| AppError::io(parent, e).context("Failed to create directory"))?;
+        }
+
+        let mut source = File::open(path)
+            .map_err(|e| AppError::io(path, e).context("Failed to open file"))?;
+        source
+            .seek(SeekFrom::Start(offset))
+            .map_err(|e| AppError::io(path, e).context("Failed to seek in file"))?;
+
+        let mut dest = File::create(dest_path)
+            .map_err(|e| AppError::io(dest_path, e).context("Failed to create file"))?;
+        std::io::copy(&mut source, &mut dest)
+            .map_err(|e| AppError::io(dest_path, e).context("Failed to write motion video"))?;
+
+        Ok(())
+    }
+
+    // SHA-256 of the file contents, used to spot the same photo imported twice
+    pub fn hash_file(path: &str) -> AppResult<String> {
+        let file = File::open(path)
+            .map_err(|e| AppError::io(path, e).context("Failed to open file"))?;
+
+        let mut reader = BufReader::new(file);
+        let mut hasher = Sha256::new();
+        let mut buffer = [0u8; 64 * 1024];
+
+        loop {
+            let read = reader
+                .read(&mut buffer)
+                .map_err(|e| AppError::io(path, e).context("Failed to read file"))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    // Files that belong to a photo and travel with it: XMP sidecars and the
+    // paired Live Photo clip
+    pub fn companion_files(path: &str, live_photo_video: Option<&str>) -> Vec<PathBuf> {
+        let mut files = EXIFService::find_xmp_sidecars(path);
+        files.extend(live_photo_video.map(PathBuf::from).filter(|p| p.is_file()));
+        files
+    }
+
+    // Uses the freedesktop.org trash on Linux, the Recycle Bin and Trash elsewhere
+    pub fn move_to_trash(path: &str) -> AppResult<()> {
+        info!("Moving to trash: {}", path);
+
+        trash::delete(path)
+            .map_err(|e| AppError::Io { path: Some(path.to_string()), message: e.to_string() }.context("Failed to move to trash"))
+    }
 }
@@ -0,0 +1,154 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use log::{info, warn};
+use crate::services::error::{AppError, AppResult};
+
+enum FileChange {
+    Moved { from: PathBuf, to: PathBuf },
+    Copied { to: PathBuf },
+    Removed { path: PathBuf, aside: PathBuf }, // Deleted on commit
+}
+
+// Filesystem changes made for one operation, so they can be reverted if a
+// later step (another companion file, the database update) fails. Call
+// `commit` once the operation succeeded.
+#[derive(Default)]
+pub struct FileJournal {
+    changes: Vec<FileChange>,
+}
+
+fn ensure_parent(path: &Path) -> AppResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| AppError::io(parent, e).context("Failed to create directory"))?;
+    }
+    Ok(())
+}
+
+fn ensure_free(path: &Path) -> AppResult<()> {
+    if path.exists() {
+        return Err(AppError::AlreadyExists { path: path.to_string_lossy().to_string() });
+    }
+    Ok(())
+}
+
+// `fs::rename` cannot cross filesystems; fall back to copy and delete
+fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from).inspect_err(|_| {
+                let _ = fs::remove_file(to);
+            })
+        }
+        result => result,
+    }
+}
+
+impl FileJournal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Never overwrites an existing file
+    pub fn move_file(&mut self, from: &Path, to: &Path) -> AppResult<()> {
+        if from == to {
+            return Ok(());
+        }
+        ensure_free(to)?;
+        ensure_parent(to)?;
+        move_path(from, to)
+            .map_err(|e| AppError::io(from, e).context(format!("Failed to move to {}", to.display())))?;
+
+        self.changes.push(FileChange::Moved { from: from.to_path_buf(), to: to.to_path_buf() });
+        Ok(())
+    }
+
+    // Never overwrites an existing file
+    pub fn copy_file(&mut self, from: &Path, to: &Path) -> AppResult<()> {
+        ensure_free(to)?;
+        ensure_parent(to)?;
+        fs::copy(from, to)
+            .map_err(|e| AppError::io(from, e).context(format!("Failed to copy to {}", to.display())))?;
+
+        self.changes.push(FileChange::Copied { to: to.to_path_buf() });
+        Ok(())
+    }
+
+    // Moves `path` aside so a rollback can put it back; `commit` deletes it.
+    // A missing file is not an error.
+    pub fn remove_file(&mut self, path: &Path) -> AppResult<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let name = path.file_name()
+            .ok_or_else(|| AppError::invalid(format!("Invalid file name: {}", path.display())))?;
+        let mut aside_name = name.to_owned();
+        aside_name.push(".removed");
+        let aside = path.with_file_name(aside_name);
+
+        // Left over from an operation that was interrupted before committing
+        let _ = fs::remove_file(&aside);
+        fs::rename(path, &aside)
+            .map_err(|e| AppError::io(path, e).context("Failed to remove file"))?;
+
+        self.changes.push(FileChange::Removed { path: path.to_path_buf(), aside });
+        Ok(())
+    }
+
+    // Keeps all changes, deleting the files `remove_file` set aside
+    pub fn commit(self) {
+        for change in self.changes {
+            if let FileChange::Removed { aside, .. } = change {
+                if let Err(e) = fs::remove_file(&aside) {
+                    warn!("Failed to delete {}: {}", aside.display(), e);
+                }
+            }
+        }
+    }
+
+    // Reverts all changes, newest first. Failures are logged since there is
+    // nothing better to do with them at this point.
+    pub fn rollback(self) {
+        for change in self.changes.into_iter().rev() {
+            let result = match &change {
+                FileChange::Moved { from, to } => move_path(to, from),
+                FileChange::Copied { to } => fs::remove_file(to),
+                FileChange::Removed { path, aside } => fs::rename(aside, path),
+            };
+            match (result, change) {
+                (Ok(()), FileChange::Moved { from, .. }) => info!("Rolled back move of {}", from.display()),
+                (Ok(()), FileChange::Copied { to }) => info!("Rolled back copy to {}", to.display()),
+                (Err(e), FileChange::Moved { from, to }) => {
+                    warn!("Failed to move {} back to {}: {}", to.display(), from.display(), e)
+                }
+                (Ok(()), FileChange::Removed { path, .. }) => info!("Rolled back removal of {}", path.display()),
+                (Err(e), FileChange::Copied { to }) => warn!("Failed to remove copy {}: {}", to.display(), e),
+                (Err(e), FileChange::Removed { path, aside }) => {
+                    warn!("Failed to move {} back to {}: {}", aside.display(), path.display(), e)
+                }
+            }
+        }
+    }
+}
+
+// Where a companion file (sidecar, Live Photo clip) of `old_main` goes when the
+// main file becomes `new_main`, keeping whatever follows the shared name:
+// a.CR2.xmp -> b.CR2.xmp, a.xmp -> b.xmp, a.MOV -> b.MOV
+pub fn companion_destination(companion: &Path, old_main: &Path, new_main: &Path) -> Option<PathBuf> {
+    let companion_name = companion.file_name()?.to_string_lossy().to_string();
+    let old_name = old_main.file_name()?.to_string_lossy().to_string();
+    let new_name = new_main.file_name()?.to_string_lossy().to_string();
+    let old_stem = old_main.file_stem()?.to_string_lossy().to_string();
+    let new_stem = new_main.file_stem()?.to_string_lossy().to_string();
+
+    let name = if let Some(rest) = companion_name.strip_prefix(&old_name) {
+        format!("{}{}", new_name, rest)
+    } else if let Some(rest) = companion_name.strip_prefix(&old_stem) {
+        format!("{}{}", new_stem, rest)
+    } else {
+        companion_name
+    };
+    Some(new_main.with_file_name(name))
+}
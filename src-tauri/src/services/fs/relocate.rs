@@ -0,0 +1,97 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+use log::warn;
+use crate::services::error::{AppError, AppResult};
+use super::{FileOperationResult, FileSystemService};
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct RelocationReport {
+    pub relocated: usize,
+    pub sampled: usize,           // Files checked at the new location
+    pub missing_in_sample: usize, // Sampled files not found there either
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AmbiguousMatch {
+    pub photo_id: i64,
+    pub candidates: Vec<String>,
+}
+
+// Outcome of searching a folder for missing photos by file name and size
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct LocateReport {
+    pub found: Vec<FileOperationResult>,
+    pub ambiguous: Vec<AmbiguousMatch>, // Left for the user to pick
+    pub not_found: Vec<i64>,
+}
+
+// `path` moved from under `old_root` to under `new_root`, or None if it is not under `old_root`
+pub fn rebase_path(path: &str, old_root: &Path, new_root: &Path) -> Option<String> {
+    Path::new(path)
+        .strip_prefix(old_root)
+        .ok()
+        .map(|rest| new_root.join(rest).to_string_lossy().to_string())
+}
+
+// Checks that `path` holds the expected file: same size, and same contents
+// when a hash is given
+pub fn verify_file(path: &Path, size: u64, content_hash: Option<&str>) -> AppResult<()> {
+    let metadata = std::fs::metadata(path).map_err(|e| AppError::io(path, e))?;
+    if metadata.len() != size {
+        return Err(AppError::ChecksumMismatch { path: path.to_string_lossy().to_string() }
+            .context(format!("File is {} bytes, expected {}", metadata.len(), size)));
+    }
+
+    if let Some(expected) = content_hash {
+        if FileSystemService::hash_file(&path.to_string_lossy())? != expected {
+            return Err(AppError::ChecksumMismatch { path: path.to_string_lossy().to_string() });
+        }
+    }
+    Ok(())
+}
+
+// Up to `count` items spread evenly over `items`
+pub fn spread_sample<T>(items: &[T], count: usize) -> Vec<&T> {
+    if items.is_empty() || count == 0 {
+        return Vec::new();
+    }
+    let step = (items.len() / count).max(1);
+    items.iter().step_by(step).take(count).collect()
+}
+
+fn name_key(path: &Path) -> Option<String> {
+    path.file_name().map(|n| n.to_string_lossy().to_lowercase())
+}
+
+// Files under `root` whose (case-insensitive) name and size are in `wanted`
+pub fn find_by_name_and_size(root: &Path, wanted: &HashSet<(String, u64)>) -> HashMap<(String, u64), Vec<PathBuf>> {
+    let mut found: HashMap<(String, u64), Vec<PathBuf>> = HashMap::new();
+
+    for entry in WalkDir::new(root) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Skipping unreadable entry while searching {}: {}", root.display(), e);
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let (Some(name), Ok(metadata)) = (name_key(entry.path()), entry.metadata()) else { continue };
+
+        let key = (name, metadata.len());
+        if wanted.contains(&key) {
+            found.entry(key).or_default().push(entry.into_path());
+        }
+    }
+
+    found
+}
+
+// Key under which `find_by_name_and_size` reports a file named like `path`
+pub fn name_and_size_key(path: &str, size: u64) -> Option<(String, u64)> {
+    name_key(Path::new(path)).map(|name| (name, size))
+}
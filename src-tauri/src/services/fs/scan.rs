@@ -0,0 +1,127 @@
+use std::path::Path;
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use walkdir::DirEntry;
+use crate::services::error::{AppError, AppResult};
+
+// Folders NAS boxes and operating systems litter photo shares with
+const DEFAULT_EXCLUDES: &[&str] = &["@eaDir/", "#recycle/", "#snapshot/", "$RECYCLE.BIN/", "System Volume Information/"];
+
+// What a folder scan picks up; stored per library root
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ScanOptions {
+    // gitignore-style: `*.tmp`, `exports/` (folders only), `/Private` (relative
+    // to the scanned folder), `**/cache/**`, and `!keep.jpg` to re-include
+    pub exclude_patterns: Vec<String>,
+    pub include_hidden: bool, // Names starting with a dot
+    pub max_depth: Option<usize>, // 0 is the folder itself, 1 its direct children...
+    pub follow_symlinks: bool,
+    pub min_file_size: Option<u64>, // In bytes, to skip stray thumbnails
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            exclude_patterns: DEFAULT_EXCLUDES.iter().map(|p| p.to_string()).collect(),
+            include_hidden: false,
+            max_depth: None,
+            follow_symlinks: false,
+            min_file_size: None,
+        }
+    }
+}
+
+// A file or folder the scan could not read; reported instead of silently skipped
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScanError {
+    pub path: Option<String>,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ScanResult {
+    pub images: Vec<super::ImageFile>,
+    pub errors: Vec<ScanError>,
+}
+
+struct ExcludeRule {
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool, // Matched against the whole relative path instead of the name
+}
+
+pub struct ExcludeRules {
+    rules: Vec<ExcludeRule>,
+}
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl ExcludeRules {
+    pub fn parse(patterns: &[String]) -> AppResult<Self> {
+        let mut rules = Vec::new();
+
+        for line in patterns {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let line = line.trim_start_matches('/');
+
+            let pattern = Pattern::new(line)
+                .map_err(|e| AppError::invalid(format!("Invalid exclude pattern {}: {}", line, e)))?;
+            rules.push(ExcludeRule { pattern, negated, dir_only, anchored });
+        }
+
+        Ok(ExcludeRules { rules })
+    }
+
+    // As in gitignore, the last matching rule decides
+    fn is_excluded(&self, relative_path: &str, name: &str, is_dir: bool) -> bool {
+        let mut excluded = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let subject = if rule.anchored { relative_path } else { name };
+            if rule.pattern.matches_with(subject, MATCH_OPTIONS) {
+                excluded = !rule.negated;
+            }
+        }
+        excluded
+    }
+
+    // Whether the walk should enter or report `entry`; the scanned folder itself is always accepted
+    pub fn accepts(&self, entry: &DirEntry, root: &Path, options: &ScanOptions) -> bool {
+        if entry.depth() == 0 {
+            return true;
+        }
+        let name = entry.file_name().to_string_lossy();
+        if !options.include_hidden && name.starts_with('.') {
+            return false;
+        }
+
+        let relative_path = entry.path()
+            .strip_prefix(root)
+            .unwrap_or(entry.path())
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        !self.is_excluded(&relative_path, &name, entry.file_type().is_dir())
+    }
+}
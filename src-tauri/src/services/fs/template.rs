@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use chrono::NaiveDateTime;
+use chrono::format::{Item, StrftimeItems};
+use serde::{Deserialize, Serialize};
+use crate::services::error::{AppError, AppResult};
+
+const DEFAULT_DATE_FORMAT: &str = "%Y%m%d";
+
+// Shorthands for common date parts, so `{YYYY}/{YYYY-MM-DD}` reads like other importers
+const DATE_SHORTHANDS: &[(&str, &str)] = &[
+    ("YYYY", "%Y"),
+    ("YY", "%y"),
+    ("MM", "%m"),
+    ("DD", "%d"),
+    ("YYYY-MM", "%Y-%m"),
+    ("YYYY-MM-DD", "%Y-%m-%d"),
+    ("YYYYMMDD", "%Y%m%d"),
+];
+
+// Characters that cannot appear in a file name on at least one supported OS
+const FORBIDDEN_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Date(String),    // chrono format
+    Sequence(usize), // zero-padded width
+    Value(String),   // looked up in the context
+}
+
+// A file name template such as `{date:%Y%m%d}_{client}_{seq:04}.{ext}`.
+// `{date:FORMAT}` formats the capture date (`{YYYY}`, `{MM}` etc. are shorthands),
+// `{seq:WIDTH}` is a counter and any other `{name}` is taken from the context
+// values. `{{` and `}}` are literal braces.
+#[derive(Debug, Clone)]
+pub struct RenameTemplate {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RenameOptions {
+    pub template: String,
+    #[serde(default)]
+    pub variables: HashMap<String, String>, // User-defined tokens such as {client}
+    pub collection_id: Option<i64>,         // Source of {collection}
+    pub start: Option<u64>,                 // First {seq} value, 1 by default
+}
+
+// Planned rename of one photo. Nothing is renamed while any entry has a conflict.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RenamePreview {
+    pub photo_id: i64,
+    pub old_path: String,
+    pub new_path: Option<String>,
+    pub conflict: Option<String>,
+}
+
+// Values for one photo
+#[derive(Debug, Default)]
+pub struct TemplateContext {
+    pub date: Option<NaiveDateTime>,
+    pub sequence: u64,
+    pub values: HashMap<String, String>,
+}
+
+// EXIF and catalog dates: "YYYY-MM-DD HH:MM:SS", "YYYY:MM:DD HH:MM:SS" or just the date
+pub fn parse_capture_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y:%m:%d %H:%M:%S"))
+        .ok()
+        .or_else(|| {
+            chrono::NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })
+}
+
+fn sanitize(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| if FORBIDDEN_CHARS.contains(&c) || c.is_control() { '_' } else { c })
+        .collect()
+}
+
+// `date` in a chrono format; unlike `to_string` this reports a specifier the
+// date cannot fill instead of panicking
+fn format_date(date: &NaiveDateTime, format: &str) -> AppResult<String> {
+    let mut formatted = String::new();
+    write!(formatted, "{}", date.format(format))
+        .map_err(|_| AppError::invalid(format!("Invalid date format: {}", format)))?;
+    Ok(formatted)
+}
+
+impl RenameTemplate {
+    pub fn parse(template: &str) -> AppResult<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut token = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => token.push(c),
+                            None => return Err(AppError::invalid(format!("Unclosed token {{{}", token))),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Self::parse_token(&token)?);
+                }
+                '}' => return Err(AppError::invalid("Unmatched }")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        if segments.is_empty() {
+            return Err(AppError::invalid("Template is empty"));
+        }
+        Ok(RenameTemplate { segments })
+    }
+
+    fn parse_token(token: &str) -> AppResult<Segment> {
+        let (name, spec) = match token.split_once(':') {
+            Some((name, spec)) => (name.trim(), Some(spec)),
+            None => (token.trim(), None),
+        };
+
+        match (name, spec) {
+            ("", _) => Err(AppError::invalid("Empty token {}")),
+            ("date", spec) => {
+                let format = spec.unwrap_or(DEFAULT_DATE_FORMAT);
+                // Some specifiers parse but cannot format a date without a
+                // time zone (%z, %Z), so a sample date is formatted as well
+                if StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+                    || format_date(&NaiveDateTime::default(), format).is_err()
+                {
+                    return Err(AppError::invalid(format!("Invalid date format: {}", format)));
+                }
+                Ok(Segment::Date(format.to_string()))
+            }
+            ("seq", spec) => {
+                let width = spec
+                    .map(|s| s.parse::<usize>().map_err(|_| AppError::invalid(format!("Invalid counter width: {}", s))))
+                    .transpose()?
+                    .unwrap_or(1);
+                Ok(Segment::Sequence(width))
+            }
+            (name, None) => match DATE_SHORTHANDS.iter().find(|(shorthand, _)| *shorthand == name) {
+                Some((_, format)) => Ok(Segment::Date(format.to_string())),
+                None => Ok(Segment::Value(name.to_string())),
+            },
+            (name, Some(_)) => Err(AppError::invalid(format!("Token {{{}}} takes no format", name))),
+        }
+    }
+
+    // Renders the file name. Values are sanitized so they cannot introduce path
+    // separators; a missing date or value is an error rather than an empty string.
+    pub fn render(&self, context: &TemplateContext) -> AppResult<String> {
+        let mut name = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => name.push_str(text),
+                Segment::Date(format) => {
+                    let date = context.date.ok_or_else(|| AppError::invalid("Photo has no capture date"))?;
+                    name.push_str(&sanitize(&format_date(&date, format)?));
+                }
+                Segment::Sequence(width) => {
+                    name.push_str(&format!("{:0width$}", context.sequence, width = *width));
+                }
+                Segment::Value(key) => {
+                    let value = context
+                        .values
+                        .get(key)
+                        .filter(|v| !v.trim().is_empty())
+                        .ok_or_else(|| AppError::invalid(format!("No value for {{{}}}", key)))?;
+                    name.push_str(&sanitize(value));
+                }
+            }
+        }
+
+        if name.trim().is_empty() || name.contains(FORBIDDEN_CHARS) || name == "." || name == ".." {
+            return Err(AppError::invalid(format!("Invalid file name: {}", name)));
+        }
+        Ok(name)
+    }
+}
+
+// A relative folder path template such as `{YYYY}/{YYYY-MM-DD}`; each
+// component is a `RenameTemplate`
+#[derive(Debug, Clone)]
+pub struct FolderTemplate {
+    components: Vec<RenameTemplate>,
+}
+
+impl FolderTemplate {
+    pub fn parse(template: &str) -> AppResult<Self> {
+        let components = template
+            .split(['/', '\\'])
+            .filter(|component| !component.trim().is_empty())
+            .map(RenameTemplate::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(FolderTemplate { components })
+    }
+
+    pub fn render(&self, context: &TemplateContext) -> AppResult<PathBuf> {
+        self.components.iter().map(|component| component.render(context)).collect()
+    }
+}
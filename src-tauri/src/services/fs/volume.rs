@@ -0,0 +1,160 @@
+// Identifies the volume (drive, partition) a folder lives on, so a library on
+// an external drive is recognized when it comes back under another mount
+// point or drive letter.
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct VolumeInfo {
+    pub id: Option<String>,    // Filesystem UUID (volume serial number on Windows)
+    pub label: Option<String>,
+    pub mount_point: PathBuf,
+}
+
+impl VolumeInfo {
+    // Same volume: by id when both have one, otherwise by label
+    pub fn matches(&self, id: Option<&str>, label: Option<&str>) -> bool {
+        match (self.id.as_deref(), id) {
+            (Some(a), Some(b)) => a == b,
+            _ => label.is_some() && self.label.as_deref() == label,
+        }
+    }
+}
+
+// The volume among `volumes` that contains `path`: the one with the longest mount point
+pub fn volume_containing<'a>(volumes: &'a [VolumeInfo], path: &Path) -> Option<&'a VolumeInfo> {
+    volumes
+        .iter()
+        .filter(|volume| path.starts_with(&volume.mount_point))
+        .max_by_key(|volume| volume.mount_point.components().count())
+}
+
+#[cfg(target_os = "linux")]
+pub fn mounted_volumes() -> Vec<VolumeInfo> {
+    use std::collections::HashMap;
+
+    // /dev/disk/by-uuid/<uuid> and /dev/disk/by-label/<label> link to the device
+    fn device_names(dir: &str) -> HashMap<PathBuf, String> {
+        let Ok(entries) = std::fs::read_dir(dir) else { return HashMap::new() };
+        entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let device = std::fs::canonicalize(entry.path()).ok()?;
+                Some((device, unescape(&entry.file_name().to_string_lossy())))
+            })
+            .collect()
+    }
+
+    // mountinfo escapes as \040 (octal), udev link names as \x20 (hex)
+    fn unescape(value: &str) -> String {
+        let mut out = Vec::new();
+        let bytes = value.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let decoded = match bytes.get(i..i + 4) {
+                Some([b'\\', b'x', h, l]) => std::str::from_utf8(&[*h, *l]).ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                Some([b'\\', a, b, c]) if [a, b, c].iter().all(|d| d.is_ascii_digit()) => std::str::from_utf8(&[*a, *b, *c]).ok()
+                    .and_then(|oct| u8::from_str_radix(oct, 8).ok()),
+                _ => None,
+            };
+            match decoded {
+                Some(byte) => {
+                    out.push(byte);
+                    i += 4;
+                }
+                None => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    let Ok(mountinfo) = std::fs::read_to_string("/proc/self/mountinfo") else { return Vec::new() };
+    let uuids = device_names("/dev/disk/by-uuid");
+    let labels = device_names("/dev/disk/by-label");
+
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            // "<id> <parent> <major:minor> <root> <mount point> <options>... - <fstype> <source> <options>"
+            let (fields, filesystem) = line.split_once(" - ")?;
+            let mount_point = PathBuf::from(unescape(fields.split(' ').nth(4)?));
+            let source = filesystem.split(' ').nth(1)?;
+            let device = std::fs::canonicalize(source).ok();
+
+            Some(VolumeInfo {
+                id: device.as_ref().and_then(|d| uuids.get(d)).cloned(),
+                label: device.as_ref().and_then(|d| labels.get(d)).cloned(),
+                mount_point,
+            })
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+pub fn mounted_volumes() -> Vec<VolumeInfo> {
+    use std::process::Command;
+
+    let mut mount_points = vec![PathBuf::from("/")];
+    if let Ok(entries) = std::fs::read_dir("/Volumes") {
+        mount_points.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()));
+    }
+
+    mount_points
+        .into_iter()
+        .map(|mount_point| {
+            let info = Command::new("diskutil")
+                .arg("info")
+                .arg(&mount_point)
+                .output()
+                .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+                .unwrap_or_default();
+            let field = |name: &str| {
+                info.lines()
+                    .find_map(|line| line.trim().strip_prefix(name))
+                    .map(|value| value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+            };
+
+            VolumeInfo {
+                id: field("Volume UUID:"),
+                label: field("Volume Name:"),
+                mount_point,
+            }
+        })
+        .collect()
+}
+
+#[cfg(windows)]
+pub fn mounted_volumes() -> Vec<VolumeInfo> {
+    use std::process::Command;
+
+    (b'A'..=b'Z')
+        .map(|letter| PathBuf::from(format!("{}:\\", letter as char)))
+        .filter(|drive| drive.exists())
+        .map(|mount_point| {
+            // `vol` prints the serial number as XXXX-XXXX in every locale
+            let output = Command::new("cmd")
+                .args(["/C", "vol", &mount_point.to_string_lossy()[..2]])
+                .output()
+                .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+                .unwrap_or_default();
+            let id = output
+                .split_whitespace()
+                .find(|word| word.len() == 9 && word.as_bytes()[4] == b'-'
+                    && word.chars().filter(|c| *c != '-').all(|c| c.is_ascii_hexdigit()))
+                .map(str::to_string);
+
+            VolumeInfo { id, label: None, mount_point }
+        })
+        .collect()
+}
+
+// Elsewhere volumes are recognized by path only
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn mounted_volumes() -> Vec<VolumeInfo> {
+    Vec::new()
+}
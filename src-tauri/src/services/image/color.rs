@@ -0,0 +1,131 @@
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+// 4 levels per channel -> 64 bins
+const LEVELS: usize = 4;
+const BINS: usize = LEVELS * LEVELS * LEVELS;
+const PALETTE_SIZE: usize = 5;
+// CIE76 distance at which two colors no longer count as matching
+const MATCH_DISTANCE: f32 = 40.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PaletteColor {
+    pub color: String, // "#rrggbb"
+    pub weight: f32,   // share of the image's pixels
+}
+
+// Compact color description of a photo: a normalized 64-bin RGB histogram
+// (one byte per bin) and the dominant colors taken from its largest bins
+#[derive(Debug, Clone)]
+pub struct ColorSignature {
+    pub histogram: Vec<u8>,
+    pub palette: Vec<PaletteColor>,
+}
+
+impl ColorSignature {
+    pub fn from_image(img: &DynamicImage) -> Self {
+        let rgb = img.to_rgb8();
+        let mut counts = [0u32; BINS];
+        let mut sums = [[0u64; 3]; BINS];
+
+        for pixel in rgb.pixels() {
+            let [r, g, b] = pixel.0;
+            let bin = Self::bin(r) * LEVELS * LEVELS + Self::bin(g) * LEVELS + Self::bin(b);
+            counts[bin] += 1;
+            sums[bin][0] += r as u64;
+            sums[bin][1] += g as u64;
+            sums[bin][2] += b as u64;
+        }
+
+        let total = counts.iter().sum::<u32>().max(1) as f32;
+        let histogram = counts
+            .iter()
+            .map(|&c| (c as f32 / total * 255.0).round() as u8)
+            .collect();
+
+        // The mean color of each large bin is a better swatch than the bin center
+        let mut bins: Vec<usize> = (0..BINS).filter(|&i| counts[i] > 0).collect();
+        bins.sort_by(|a, b| counts[*b].cmp(&counts[*a]));
+        let palette = bins
+            .into_iter()
+            .take(PALETTE_SIZE)
+            .map(|i| {
+                let n = counts[i] as u64;
+                let mean = [sums[i][0] / n, sums[i][1] / n, sums[i][2] / n];
+                PaletteColor {
+                    color: format!("#{:02x}{:02x}{:02x}", mean[0], mean[1], mean[2]),
+                    weight: counts[i] as f32 / total,
+                }
+            })
+            .collect();
+
+        ColorSignature { histogram, palette }
+    }
+
+    fn bin(value: u8) -> usize {
+        value as usize * LEVELS / 256
+    }
+}
+
+pub fn parse_hex_color(hex: &str) -> Option<[u8; 3]> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn to_lab([r, g, b]: [u8; 3]) -> [f32; 3] {
+    fn linear(c: u8) -> f32 {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    }
+    fn f(t: f32) -> f32 {
+        if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 }
+    }
+
+    let (r, g, b) = (linear(r), linear(g), linear(b));
+    // sRGB -> XYZ (D65), normalized by the reference white
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn lab_distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+// How much of the photo is close to each target color, averaged over the targets
+// (0 = none of it, 1 = all of it matches exactly)
+pub fn palette_similarity(palette: &[PaletteColor], targets: &[[u8; 3]]) -> f32 {
+    if targets.is_empty() {
+        return 0.0;
+    }
+
+    let score: f32 = targets
+        .iter()
+        .map(|&target| {
+            let target = to_lab(target);
+            palette
+                .iter()
+                .filter_map(|entry| {
+                    let color = to_lab(parse_hex_color(&entry.color)?);
+                    let closeness = 1.0 - lab_distance(target, color) / MATCH_DISTANCE;
+                    Some(entry.weight * closeness.max(0.0))
+                })
+                .sum::<f32>()
+        })
+        .sum();
+
+    score / targets.len() as f32
+}
+
+// Histogram intersection (0 = no colors in common, 1 = identical distribution)
+pub fn histogram_similarity(a: &[u8], b: &[u8]) -> f32 {
+    let shared: u32 = a.iter().zip(b).map(|(&x, &y)| x.min(y) as u32).sum();
+    shared as f32 / 255.0
+}
@@ -1,9 +1,16 @@
 use image::{ImageFormat, imageops::FilterType};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
 use sha2::{Sha256, Digest};
 use serde::{Deserialize, Serialize};
 use log::{info, warn};
+use crate::services::error::{AppError, AppResult};
+
+mod color;
+mod similarity;
+
+pub use color::{ColorSignature, PaletteColor, histogram_similarity, palette_similarity, parse_hex_color};
+pub use similarity::{PerceptualHash, SimilarityIndex};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ImageDimensions {
@@ -23,9 +30,9 @@ const THUMBNAIL_SIZE: u32 = 200;
 pub struct ImageService;
 
 impl ImageService {
-    pub fn get_dimensions(path: &str) -> Result<ImageDimensions, String> {
+    pub fn get_dimensions(path: &str) -> AppResult<ImageDimensions> {
         let img = image::open(path)
-            .map_err(|e| format!("Failed to open image: {}", e))?;
+            .map_err(|e| AppError::image(path, e).context("Failed to open image"))?;
 
         Ok(ImageDimensions {
             width: img.width(),
@@ -36,25 +43,22 @@ impl ImageService {
     pub fn generate_thumbnail(
         image_path: &str,
         cache_dir: &Path,
-    ) -> Result<ThumbnailResult, String> {
+    ) -> AppResult<ThumbnailResult> {
         info!("Generating thumbnail for: {}", image_path);
 
         // Create cache directory if not exists
         if !cache_dir.exists() {
             fs::create_dir_all(cache_dir)
-                .map_err(|e| format!("Failed to create cache directory: {}", e))?;
+                .map_err(|e| AppError::io(cache_dir, e).context("Failed to create cache directory"))?;
         }
 
-        // Generate hash-based filename
-        let hash = Self::hash_file_path(image_path);
-        let thumbnail_filename = format!("{}.jpg", hash);
-        let thumbnail_path = cache_dir.join(&thumbnail_filename);
+        let thumbnail_path = Self::thumbnail_path(image_path, cache_dir);
 
         // Check if thumbnail already exists
         if thumbnail_path.exists() {
             info!("Using cached thumbnail: {:?}", thumbnail_path);
             let img = image::open(&thumbnail_path)
-                .map_err(|e| format!("Failed to open cached thumbnail: {}", e))?;
+                .map_err(|e| AppError::image(&thumbnail_path, e).context("Failed to open cached thumbnail"))?;
             
             return Ok(ThumbnailResult {
                 thumbnail_path: thumbnail_path.to_string_lossy().to_string(),
@@ -65,14 +69,14 @@ impl ImageService {
 
         // Load and resize image
         let img = image::open(image_path)
-            .map_err(|e| format!("Failed to open image: {}", e))?;
+            .map_err(|e| AppError::image(image_path, e).context("Failed to open image"))?;
 
         let thumbnail = img.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Lanczos3);
 
         // Save thumbnail
         thumbnail
             .save_with_format(&thumbnail_path, ImageFormat::Jpeg)
-            .map_err(|e| format!("Failed to save thumbnail: {}", e))?;
+            .map_err(|e| AppError::image(&thumbnail_path, e).context("Failed to save thumbnail"))?;
 
         info!("Thumbnail saved: {:?}", thumbnail_path);
 
@@ -83,17 +87,33 @@ impl ImageService {
         })
     }
 
+    // Perceptual hashes are computed from the cached thumbnail, which is small
+    // enough to decode quickly and already normalizes size and encoding
+    pub fn compute_perceptual_hash(thumbnail_path: &str) -> AppResult<PerceptualHash> {
+        let img = image::open(thumbnail_path)
+            .map_err(|e| AppError::image(thumbnail_path, e).context("Failed to open thumbnail"))?;
+
+        Ok(PerceptualHash::from_image(&img))
+    }
+
+    pub fn compute_color_signature(thumbnail_path: &str) -> AppResult<ColorSignature> {
+        let img = image::open(thumbnail_path)
+            .map_err(|e| AppError::image(thumbnail_path, e).context("Failed to open thumbnail"))?;
+
+        Ok(ColorSignature::from_image(&img))
+    }
+
     pub fn resize_image(
         source_path: &str,
         dest_path: &str,
         width: Option<u32>,
         height: Option<u32>,
         preserve_exif: bool,
-    ) -> Result<(), String> {
+    ) -> AppResult<()> {
         info!("Resizing image: {} -> {}", source_path, dest_path);
 
         let img = image::open(source_path)
-            .map_err(|e| format!("Failed to open image: {}", e))?;
+            .map_err(|e| AppError::image(source_path, e).context("Failed to open image"))?;
 
         let resized = match (width, height) {
             (Some(w), Some(h)) => img.resize_exact(w, h, FilterType::Lanczos3),
@@ -115,7 +135,7 @@ impl ImageService {
         
         resized
             .save_with_format(dest_path, format)
-            .map_err(|e| format!("Failed to save resized image: {}", e))?;
+            .map_err(|e| AppError::image(dest_path, e).context("Failed to save resized image"))?;
 
         // Copy EXIF data if requested (note: basic implementation, full EXIF preservation requires additional library)
         if preserve_exif {
@@ -126,7 +146,7 @@ impl ImageService {
         Ok(())
     }
 
-    pub fn clear_cache(cache_dir: &Path) -> Result<usize, String> {
+    pub fn clear_cache(cache_dir: &Path) -> AppResult<usize> {
         info!("Clearing thumbnail cache: {:?}", cache_dir);
 
         if !cache_dir.exists() {
@@ -135,14 +155,14 @@ impl ImageService {
 
         let mut count = 0;
         for entry in fs::read_dir(cache_dir)
-            .map_err(|e| format!("Failed to read cache directory: {}", e))?
+            .map_err(|e| AppError::io(cache_dir, e).context("Failed to read cache directory"))?
         {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+            let entry = entry.map_err(|e| AppError::io(cache_dir, e).context("Failed to read entry"))?;
             let path = entry.path();
             
             if path.is_file() {
                 fs::remove_file(&path)
-                    .map_err(|e| format!("Failed to remove file: {}", e))?;
+                    .map_err(|e| AppError::io(&path, e).context("Failed to remove file"))?;
                 count += 1;
             }
         }
@@ -151,18 +171,18 @@ impl ImageService {
         Ok(count)
     }
 
-    pub fn get_cache_size(cache_dir: &Path) -> Result<u64, String> {
+    pub fn get_cache_size(cache_dir: &Path) -> AppResult<u64> {
         if !cache_dir.exists() {
             return Ok(0);
         }
 
         let mut total_size = 0u64;
         for entry in fs::read_dir(cache_dir)
-            .map_err(|e| format!("Failed to read cache directory: {}", e))?
+            .map_err(|e| AppError::io(cache_dir, e).context("Failed to read cache directory"))?
         {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+            let entry = entry.map_err(|e| AppError::io(cache_dir, e).context("Failed to read entry"))?;
             let metadata = entry.metadata()
-                .map_err(|e| format!("Failed to read metadata: {}", e))?;
+                .map_err(|e| AppError::io(entry.path(), e).context("Failed to read metadata"))?;
             
             if metadata.is_file() {
                 total_size += metadata.len();
@@ -172,24 +192,29 @@ impl ImageService {
         Ok(total_size)
     }
 
-    fn hash_file_path(path: &str) -> String {
+    // Thumbnails are cached under a hash of the image path
+    pub fn thumbnail_path(image_path: &str, cache_dir: &Path) -> PathBuf {
+        cache_dir.join(format!("{}.jpg", Self::hash_file_path(image_path)))
+    }
+
+    pub fn hash_file_path(path: &str) -> String {
         let mut hasher = Sha256::new();
         hasher.update(path.as_bytes());
         format!("{:x}", hasher.finalize())
     }
 
-    fn image_format_from_path(path: &str) -> Result<ImageFormat, String> {
+    fn image_format_from_path(path: &str) -> AppResult<ImageFormat> {
         let path_obj = Path::new(path);
         let ext = path_obj
             .extension()
             .and_then(|s| s.to_str())
-            .ok_or_else(|| "No file extension found".to_string())?;
+            .ok_or_else(|| AppError::UnsupportedFormat { path: Some(path.to_string()), message: "No file extension found".to_string() })?;
 
         match ext.to_lowercase().as_str() {
             "jpg" | "jpeg" => Ok(ImageFormat::Jpeg),
             "png" => Ok(ImageFormat::Png),
             "webp" => Ok(ImageFormat::WebP),
-            _ => Err(format!("Unsupported image format: {}", ext)),
+            _ => Err(AppError::UnsupportedFormat { path: Some(path.to_string()), message: format!("Cannot write .{} files", ext) }),
         }
     }
 }
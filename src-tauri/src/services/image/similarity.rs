@@ -0,0 +1,196 @@
+use image::{DynamicImage, imageops::FilterType};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::f64::consts::PI;
+
+const PHASH_SAMPLE_SIZE: usize = 32;
+const PHASH_BLOCK_SIZE: usize = 8;
+
+// dHash and pHash of a photo, compared as one 128-bit fingerprint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerceptualHash {
+    pub dhash: u64,
+    pub phash: u64,
+}
+
+impl PerceptualHash {
+    pub fn from_image(img: &DynamicImage) -> Self {
+        PerceptualHash {
+            dhash: dhash(img),
+            phash: phash(img),
+        }
+    }
+
+    // Hamming distance over both hashes (0 = identical, 128 = opposite)
+    pub fn distance(&self, other: &PerceptualHash) -> u32 {
+        (self.dhash ^ other.dhash).count_ones() + (self.phash ^ other.phash).count_ones()
+    }
+}
+
+// Difference hash: one bit per horizontal brightness gradient on a 9x8 grayscale
+fn dhash(img: &DynamicImage) -> u64 {
+    let gray = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if gray.get_pixel(x, y)[0] < gray.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+// Perceptual hash: low-frequency DCT coefficients of a 32x32 grayscale compared to their median
+fn phash(img: &DynamicImage) -> u64 {
+    let n = PHASH_SAMPLE_SIZE;
+    let gray = img.resize_exact(n as u32, n as u32, FilterType::Triangle).to_luma8();
+    let pixels: Vec<f64> = gray.pixels().map(|p| p[0] as f64).collect();
+
+    let cosines: Vec<Vec<f64>> = (0..PHASH_BLOCK_SIZE)
+        .map(|u| {
+            (0..n)
+                .map(|x| ((2 * x + 1) as f64 * u as f64 * PI / (2 * n) as f64).cos())
+                .collect()
+        })
+        .collect();
+
+    // Only the top-left 8x8 block of the 2D DCT-II is needed
+    let mut coefficients = Vec::with_capacity(PHASH_BLOCK_SIZE * PHASH_BLOCK_SIZE);
+    for v in 0..PHASH_BLOCK_SIZE {
+        for u in 0..PHASH_BLOCK_SIZE {
+            let mut sum = 0.0;
+            for y in 0..n {
+                for x in 0..n {
+                    sum += pixels[y * n + x] * cosines[u][x] * cosines[v][y];
+                }
+            }
+            coefficients.push(sum);
+        }
+    }
+
+    // The DC term only reflects average brightness, so leave it out of the median
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+
+    coefficients
+        .iter()
+        .fold(0u64, |hash, &c| (hash << 1) | (c > median) as u64)
+}
+
+struct Node {
+    photo_id: i64,
+    hash: PerceptualHash,
+    children: HashMap<u32, Node>,
+}
+
+// BK-tree over `PerceptualHash::distance`, so radius queries only visit
+// subtrees that can contain a match instead of the whole library
+#[derive(Default)]
+pub struct SimilarityIndex {
+    root: Option<Node>,
+    len: usize,
+}
+
+impl SimilarityIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn insert(&mut self, photo_id: i64, hash: PerceptualHash) {
+        let mut node = match self.root {
+            Some(ref mut root) => root,
+            None => {
+                self.root = Some(Node { photo_id, hash, children: HashMap::new() });
+                self.len = 1;
+                return;
+            }
+        };
+
+        loop {
+            match node.children.entry(node.hash.distance(&hash)) {
+                Entry::Occupied(child) => node = child.into_mut(),
+                Entry::Vacant(slot) => {
+                    slot.insert(Node { photo_id, hash, children: HashMap::new() });
+                    self.len += 1;
+                    return;
+                }
+            }
+        }
+    }
+
+    // All photos within `threshold` of `hash`, as (photo_id, distance) pairs
+    pub fn query(&self, hash: &PerceptualHash, threshold: u32) -> Vec<(i64, u32)> {
+        let mut matches = Vec::new();
+        let mut stack: Vec<&Node> = self.root.iter().collect();
+
+        while let Some(node) = stack.pop() {
+            let distance = node.hash.distance(hash);
+            if distance <= threshold {
+                matches.push((node.photo_id, distance));
+            }
+
+            let low = distance.saturating_sub(threshold);
+            let high = distance + threshold;
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| (low..=high).contains(*d))
+                    .map(|(_, child)| child),
+            );
+        }
+
+        matches
+    }
+
+    // Connected groups of photos that are transitively within `threshold` of each
+    // other. Singletons are left out.
+    pub fn clusters(&self, threshold: u32) -> Vec<Vec<i64>> {
+        let mut entries = Vec::with_capacity(self.len);
+        let mut stack: Vec<&Node> = self.root.iter().collect();
+        while let Some(node) = stack.pop() {
+            entries.push((node.photo_id, node.hash));
+            stack.extend(node.children.values());
+        }
+
+        let position: HashMap<i64, usize> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, (id, _))| (*id, i))
+            .collect();
+        let mut parent: Vec<usize> = (0..entries.len()).collect();
+
+        fn find(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+
+        for (i, (_, hash)) in entries.iter().enumerate() {
+            for (other_id, _) in self.query(hash, threshold) {
+                let j = position[&other_id];
+                let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                if a != b {
+                    parent[b] = a;
+                }
+            }
+        }
+
+        let mut groups: HashMap<usize, Vec<i64>> = HashMap::new();
+        for (i, (id, _)) in entries.iter().enumerate() {
+            let root = find(&mut parent, i);
+            groups.entry(root).or_default().push(*id);
+        }
+
+        groups.into_values().filter(|g| g.len() > 1).collect()
+    }
+}
@@ -3,3 +3,5 @@ pub mod fs;
 pub mod db;
 pub mod exif;
 pub mod image;
+pub mod error;
+pub mod settings;
@@ -0,0 +1,77 @@
+use std::path::Path;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use crate::services::db::BackupPolicy;
+use crate::services::error::{AppError, AppResult};
+
+const MAX_RECENT_LIBRARIES: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecentLibrary {
+    pub path: String,
+    pub name: String,
+    pub last_opened_at: String,
+}
+
+// Preferences that live outside any library, in the app config folder
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AppSettings {
+    pub library_path: Option<String>, // Library opened at startup; the one in the app data folder when unset
+    pub recent_libraries: Vec<RecentLibrary>, // Most recently opened first
+    pub backup_policy: BackupPolicy,
+    pub xmp_sync: bool, // Edits queued while a drive was offline may create XMP sidecars, not just update existing ones
+}
+
+impl AppSettings {
+    // A missing file means nothing was configured yet
+    pub fn load(path: &Path) -> AppResult<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| AppError::from(e).context("Invalid settings file")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AppSettings::default()),
+            Err(e) => Err(AppError::io(path, e).context("Failed to read settings")),
+        }
+    }
+
+    // Written to a temporary file first so a crash cannot leave half a file
+    pub fn save(&self, path: &Path) -> AppResult<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| AppError::io(parent, e).context("Failed to create config directory"))?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, json)
+            .and_then(|_| std::fs::rename(&temp_path, path))
+            .map_err(|e| AppError::io(path, e).context("Failed to save settings"))
+    }
+
+    // Makes `library_path` the library to open at startup and moves it to the
+    // front of the recent list. Without `name` it keeps the one it had, or
+    // is named after its folder.
+    pub fn remember_library(&mut self, library_path: &str, name: Option<String>) {
+        let previous = self.forget_library(library_path);
+        let name = name
+            .or(previous.map(|library| library.name))
+            .unwrap_or_else(|| {
+                Path::new(library_path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| library_path.to_string())
+            });
+
+        self.recent_libraries.insert(0, RecentLibrary {
+            path: library_path.to_string(),
+            name,
+            last_opened_at: Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        });
+        self.recent_libraries.truncate(MAX_RECENT_LIBRARIES);
+        self.library_path = Some(library_path.to_string());
+    }
+
+    // Drops `library_path` from the recent list, returning its entry
+    pub fn forget_library(&mut self, library_path: &str) -> Option<RecentLibrary> {
+        let index = self.recent_libraries.iter().position(|library| library.path == library_path)?;
+        Some(self.recent_libraries.remove(index))
+    }
+}
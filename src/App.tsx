@@ -1,15 +1,26 @@
 import { useEffect } from 'react';
 import { AppLayout, PhotoGrid, PhotoViewer } from './components';
 import { usePhotoStore } from './stores';
-import { photoFlows } from './utils';
+import { photoFlows, tauriCommands } from './utils';
+import type { StartupState } from './utils';
 import './App.css';
 
 function App() {
   const viewMode = usePhotoStore((state) => state.viewMode);
 
   useEffect(() => {
-    // Initialize database on app start
-    const initApp = async () => {
+    // The library opens in the background, and again after a switch; load
+    // photos once it is ready
+    const handleStartup = async (startup: StartupState) => {
+      if (startup.status === 'opening') return;
+      if (startup.status === 'closed') {
+        usePhotoStore.getState().setPhotos([]);
+        return;
+      }
+      if (startup.status !== 'ready') {
+        console.error('Failed to open library:', startup);
+        return;
+      }
       try {
         await photoFlows.reloadPhotos();
       } catch (error) {
@@ -17,7 +28,12 @@ function App() {
       }
     };
 
-    initApp();
+    const unlisten = tauriCommands.onStartupState(handleStartup);
+    tauriCommands.getStartupState().then(handleStartup);
+
+    return () => {
+      unlisten.then((stop) => stop());
+    };
   }, []);
 
   return (
@@ -1,6 +1,6 @@
 import React, { useState } from 'react';
 import { usePhotoStore } from '../../stores';
-import { photoFlows, ScanProgress } from '../../utils';
+import { photoFlows, ScanProgress, errorMessage } from '../../utils';
 import './AppLayout.css';
 
 interface AppLayoutProps {
@@ -38,7 +38,7 @@ export const AppLayout: React.FC<AppLayoutProps> = ({ children }) => {
         showToast(result.error, 'error');
       }
     } catch (err) {
-      const message = err instanceof Error ? err.message : 'Unknown error';
+      const message = errorMessage(err);
       setError(message);
       showToast(message, 'error');
     } finally {
@@ -52,7 +52,7 @@ export const AppLayout: React.FC<AppLayoutProps> = ({ children }) => {
       await photoFlows.reloadPhotos();
       showToast('Photos reloaded', 'success');
     } catch (err) {
-      const message = err instanceof Error ? err.message : 'Unknown error';
+      const message = errorMessage(err);
       showToast(message, 'error');
     }
   };
@@ -1,4 +1,4 @@
-export { tauriCommands } from './tauriCommands';
+export { tauriCommands, errorCode, errorMessage, isAppError } from './tauriCommands';
 export { photoFlows } from './photoFlows';
 export { collectionFlows } from './collectionFlows';
 export {
@@ -10,5 +10,17 @@ export {
   getPhotosByRating,
 } from './photoUtils';
 
-export type { DbPhoto, DbCollection } from './tauriCommands';
+export type {
+  DbPhoto,
+  DbCollection,
+  AppError,
+  AppErrorCode,
+  StartupState,
+  BackupInfo,
+  BackupPolicy,
+  RecoveryAction,
+  RecentLibrary,
+  LibraryStats,
+  StatsScope,
+} from './tauriCommands';
 export type { ScanProgress, ProgressCallback } from './photoFlows';
@@ -1,4 +1,4 @@
-import { tauriCommands, DbPhoto } from './tauriCommands';
+import { tauriCommands, DbPhoto, errorMessage } from './tauriCommands';
 import { Photo, EXIFData, PhotoMetadata } from '../types/photo';
 import { usePhotoStore } from '../stores/photoStore';
 
@@ -51,9 +51,6 @@ export const photoFlows = {
     onProgress?: ProgressCallback
   ): Promise<{ success: boolean; count: number; error?: string }> {
     try {
-      // Initialize database
-      await tauriCommands.initDatabase();
-
       // Select folder
       const folderPath = await tauriCommands.selectFolder();
       if (!folderPath) {
@@ -61,7 +58,10 @@ export const photoFlows = {
       }
 
       // Scan images
-      const imageFiles = await tauriCommands.scanImages(folderPath);
+      await tauriCommands.addLibraryRoot(folderPath);
+      const scan = await tauriCommands.scanImages(folderPath);
+      scan.errors.forEach((e) => console.warn(`Could not scan ${e.path ?? folderPath}: ${e.error}`));
+      const imageFiles = scan.images;
       const total = imageFiles.length;
 
       if (total === 0) {
@@ -138,7 +138,7 @@ export const photoFlows = {
 
       return { success: true, count: successCount };
     } catch (error) {
-      const message = error instanceof Error ? error.message : 'Unknown error';
+      const message = errorMessage(error);
       return { success: false, count: 0, error: message };
     }
   },
@@ -1,4 +1,5 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen, UnlistenFn } from '@tauri-apps/api/event';
 import { open } from '@tauri-apps/plugin-dialog';
 
 // Database commands
@@ -16,26 +17,486 @@ export interface DbPhoto {
   tags: string;
   description: string | null;
   thumbnail_path: string | null;
+  motion_type?: 'live_photo' | 'motion_photo' | null;
+  motion_video_path?: string | null;
+  motion_video_offset?: number | null;
+  content_identifier?: string | null;
+  content_hash?: string | null;
+  stack_id?: number | null;
+  flag?: PhotoFlag;
+  color_label?: ColorLabel | null;
+  is_offline?: boolean; // On a disconnected drive; browse from the cached thumbnail
+}
+
+export type PhotoFlag = 'none' | 'picked' | 'rejected';
+
+export type ColorLabel = 'red' | 'yellow' | 'green' | 'blue' | 'purple';
+
+export type Comparison = 'eq' | 'ne' | 'lt' | 'le' | 'gt' | 'ge';
+
+export type SmartRule =
+  | { type: 'and'; rules: SmartRule[] }
+  | { type: 'or'; rules: SmartRule[] }
+  | { type: 'not'; rule: SmartRule }
+  | { type: 'rating'; op: Comparison; value: number }
+  | { type: 'favorite'; value: boolean }
+  | { type: 'flag'; value: PhotoFlag }
+  | { type: 'color_label'; value: ColorLabel | null }
+  | { type: 'tag'; value: string }
+  | { type: 'capture_year'; op: Comparison; value: number }
+  | { type: 'capture_date'; op: Comparison; value: string }
+  | { type: 'filename'; contains: string }
+  | { type: 'camera'; contains: string }
+  | { type: 'lens'; contains: string };
+
+export type MetadataOp =
+  | { op: 'set_rating'; value: number }
+  | { op: 'set_favorite'; value: boolean }
+  | { op: 'toggle_favorite' }
+  | { op: 'add_tags'; tags: string[] }
+  | { op: 'remove_tags'; tags: string[] }
+  | { op: 'set_description'; value: string | null }
+  | { op: 'append_description'; text: string }
+  | { op: 'set_flag'; value: PhotoFlag }
+  | { op: 'set_color_label'; value: ColorLabel | null };
+
+export interface MetadataImport {
+  updated: number;
+  unmatched_paths: string[];
+}
+
+export interface BatchReport {
+  succeeded: number[];
+  failed: { photo_id: number; error: string }[];
+}
+
+export interface EditAction {
+  id: number;
+  label: string;
+  created_at: string;
+  undone: boolean;
+  photo_ids: number[];
 }
 
 export interface DbCollection {
   id: number;
   name: string;
   created_at: string;
+  rules: SmartRule | null;
+  parent_id: number | null;
+  sequence: number;
+  description: string | null;
+  cover_photo_id: number | null;
+  updated_at: string;
+  photo_count: number;
+}
+
+// Rejection value of every command. `context` wraps a lower-level error with
+// what was being done; `errorCode` and `errorMessage` unwrap it.
+export type AppError =
+  | { code: 'database_not_initialized' }
+  | { code: 'not_found'; message: string }
+  | { code: 'file_not_found'; path: string }
+  | { code: 'permission_denied'; path: string }
+  | { code: 'already_exists'; path: string }
+  | { code: 'duplicate_name'; name: string }
+  | { code: 'unsupported_format'; path: string | null; message: string }
+  | { code: 'invalid_input'; message: string }
+  | { code: 'conflict'; message: string }
+  | { code: 'checksum_mismatch'; path: string }
+  | { code: 'io'; path: string | null; message: string }
+  | { code: 'image'; path: string | null; message: string }
+  | { code: 'exif'; path: string | null; message: string }
+  | { code: 'database'; message: string }
+  | { code: 'database_corrupt'; message: string }
+  | { code: 'context'; message: string; photo_id: number | null; source: AppError };
+
+export type AppErrorCode = AppError['code'];
+
+export function isAppError(error: unknown): error is AppError {
+  return typeof error === 'object' && error !== null && 'code' in error;
+}
+
+// The innermost error, whose code says what actually went wrong
+export function rootError(error: AppError): AppError {
+  return error.code === 'context' ? rootError(error.source) : error;
+}
+
+export function errorCode(error: unknown): AppErrorCode | null {
+  return isAppError(error) ? rootError(error).code : null;
+}
+
+export function errorMessage(error: unknown): string {
+  if (error instanceof Error) return error.message;
+  if (typeof error === 'string') return error;
+  if (!isAppError(error)) return 'Unknown error';
+
+  switch (error.code) {
+    case 'database_not_initialized':
+      return 'Database not initialized';
+    case 'context':
+      return `${error.message}: ${errorMessage(error.source)}`;
+    case 'file_not_found':
+      return `File not found: ${error.path}`;
+    case 'permission_denied':
+      return `Permission denied: ${error.path}`;
+    case 'already_exists':
+      return `${error.path} already exists`;
+    case 'duplicate_name':
+      return `A collection named "${error.name}" already exists`;
+    case 'checksum_mismatch':
+      return `Checksum mismatch: ${error.path}`;
+    case 'database_corrupt':
+      return `The catalog is damaged: ${error.message}`;
+    case 'unsupported_format':
+    case 'io':
+    case 'image':
+    case 'exif':
+      return error.path ? `${error.path}: ${error.message}` : error.message;
+    default:
+      return error.message;
+  }
+}
+
+export interface BackupInfo {
+  path: string;
+  created_at: string;
+  size: number;
+}
+
+// Rotation keeps the newest backup of each of the last `daily` days and
+// `weekly` weeks. Backups are taken on close and every `interval_hours`.
+export interface BackupPolicy {
+  daily: number;
+  weekly: number;
+  interval_hours: number;
+}
+
+// How opening the library went. `corrupt` offers `recoverDatabase`.
+export type StartupState =
+  | { status: 'opening' }
+  | { status: 'ready'; library_path: string }
+  | { status: 'closed' }
+  | { status: 'failed'; library_path: string; error: AppError }
+  | { status: 'corrupt'; library_path: string; problems: string[]; backup: BackupInfo | null };
+
+export type RecoveryAction = 'restore_backup' | 'start_fresh';
+
+export interface RecentLibrary {
+  path: string;
+  name: string;
+  last_opened_at: string;
+}
+
+export interface FileOperationResult {
+  photo_id: number;
+  path: string | null;
+  new_photo_id: number | null;
+  error: string | null;
+}
+
+// What `runLibraryMaintenance` fixes; everything off only reports
+export interface MaintenanceOptions {
+  fix_orphaned_rows?: boolean;
+  delete_dangling_thumbnails?: boolean;
+  remove_missing_photos?: boolean;
+}
+
+export interface OrphanedRows {
+  table: string;
+  problem: string;
+  count: number;
+}
+
+export interface MaintenanceReport {
+  orphaned_rows: OrphanedRows[];
+  dangling_thumbnails: string[];
+  missing_photos: DbPhoto[];
+}
+
+// Filters of `getLibraryStats`; dates are "YYYY-MM-DD" capture dates, inclusive
+export interface StatsScope {
+  collection_id?: number | null;
+  folder?: string | null;
+  date_from?: string | null;
+  date_to?: string | null;
+}
+
+export interface StatCount {
+  label: string;
+  count: number;
+}
+
+export interface ShootStats {
+  date: string;
+  folder: string;
+  photo_count: number;
+  keeper_count: number;
+  keeper_rate: number;
+}
+
+export interface FolderStorage {
+  folder: string;
+  photo_count: number;
+  total_size: number;
+}
+
+export interface LibraryStats {
+  photo_count: number;
+  total_size: number;
+  keeper_count: number;
+  by_month: StatCount[];
+  by_camera: StatCount[];
+  by_lens: StatCount[];
+  by_focal_length: StatCount[];
+  by_aperture: StatCount[];
+  by_iso: StatCount[];
+  by_rating: StatCount[];
+  shoots: ShootStats[];
+  storage: FolderStorage[];
+}
+
+// Template tokens: {date:%Y%m%d}, {seq:04}, {name}, {ext}, {filename}, {camera},
+// {make}, {model}, {lens}, {iso}, {rating}, {flag}, {label}, {collection} and
+// any key of `variables`
+export interface RenameOptions {
+  template: string;
+  variables?: Record<string, string>;
+  collection_id?: number | null;
+  start?: number | null;
+}
+
+export interface RenamePreview {
+  photo_id: number;
+  old_path: string;
+  new_path: string | null;
+  conflict: string | null;
+}
+
+// Folder templates use the rename tokens, plus {YYYY}, {YY}, {MM}, {DD},
+// {YYYY-MM} and {YYYY-MM-DD} for the capture date
+export interface IngestOptions {
+  source: string;
+  destination: string;
+  folder_template?: string; // Default '{YYYY}/{YYYY-MM-DD}'
+  backup_destination?: string | null;
+  tags?: string[];
+  collection_id?: number | null;
+}
+
+export interface IngestReport {
+  imported: Array<{
+    source: string;
+    photo_id: number;
+    path: string;
+    backup_path: string | null;
+  }>;
+  skipped: Array<{ source: string; existing_photo_id: number | null }>;
+  failed: Array<{ source: string; error: string }>;
+}
+
+// Payload of the 'ingest-progress' event
+export interface ScanProgress {
+  current: number;
+  total: number;
+  current_file: string;
+}
+
+export interface RelocationReport {
+  relocated: number;
+  sampled: number;
+  missing_in_sample: number;
+}
+
+export interface LocateReport {
+  found: FileOperationResult[];
+  ambiguous: Array<{ photo_id: number; candidates: string[] }>;
+  not_found: number[];
+}
+
+// exclude_patterns are gitignore-style: '*.tmp', 'exports/' (folders only),
+// '/Private' (relative to the root), '**/cache/**', '!keep.jpg'
+export interface ScanOptions {
+  exclude_patterns: string[];
+  include_hidden: boolean;
+  max_depth: number | null;
+  follow_symlinks: boolean;
+  min_file_size: number | null;
+}
+
+export interface ScanResult {
+  images: Array<{
+    path: string;
+    filename: string;
+    file_size: number;
+    live_photo_video: string | null;
+  }>;
+  errors: Array<{ path: string | null; error: string }>;
+}
+
+export interface LibraryRoot {
+  id: number;
+  path: string;
+  added_at: string;
+  scan_options: ScanOptions;
+  volume_id: string | null;
+  volume_label: string | null;
+  volume_path: string | null;
+  is_online: boolean;
+  last_seen_at: string | null;
+}
+
+export interface RootStatus {
+  root_id: number;
+  path: string;
+  is_online: boolean;
+  relocated_from: string | null;
+  written_back: BatchReport; // Label edits queued while offline, now in XMP sidecars
+}
+
+export interface FolderNode {
+  root_id: number | null;
+  path: string;
+  name: string;
+  photo_count: number;
+  total_photo_count: number;
+  children: FolderNode[];
+}
+
+export interface DuplicateGroup {
+  content_hash: string;
+  photos: DbPhoto[];
+}
+
+export interface DuplicateResolution {
+  kept_photo_id: number;
+  removed_photo_ids: number[];
+  trashed_paths: string[];
+  failed_paths: string[];
+}
+
+export interface SimilarPhoto {
+  photo: DbPhoto;
+  distance: number;
+}
+
+export interface ColorMatch {
+  photo: DbPhoto;
+  score: number;
+}
+
+export interface DbStack {
+  id: number;
+  top_photo_id: number | null;
+  kind: 'burst' | 'bracket';
+  is_collapsed: boolean;
+  created_at: string;
+  photo_count: number;
+}
+
+export interface ExifData {
+  camera_make?: string;
+  camera_model?: string;
+  lens_model?: string;
+  focal_length?: number;
+  aperture?: number;
+  shutter_speed?: string;
+  iso?: number;
+  exposure_bias?: number;
+  flash?: string;
+  orientation?: number;
+  capture_date?: string;
+  gps_latitude?: number;
+  gps_longitude?: number;
+  gps_altitude?: number;
+  subsec_time?: string;
+  exposure_mode?: number;
+  burst_id?: string;
 }
 
 export const tauriCommands = {
-  // Database
-  async initDatabase(): Promise<string> {
-    return invoke('init_database');
+  // Library. It opens in the background at startup; wait for a `ready` state
+  // before calling anything that uses the catalog.
+  async getStartupState(): Promise<StartupState> {
+    return invoke('get_startup_state');
+  },
+
+  async onStartupState(handler: (state: StartupState) => void): Promise<UnlistenFn> {
+    return listen<StartupState>('startup-state', (event) => handler(event.payload));
+  },
+
+  // Most recently opened first
+  async getRecentLibraries(): Promise<RecentLibrary[]> {
+    return invoke('get_recent_libraries');
+  },
+
+  // Creating or opening a library closes the one that is open
+  async createLibrary(path: string, name?: string): Promise<StartupState> {
+    return invoke('create_library', { path, name });
+  },
+
+  async openLibrary(path: string): Promise<StartupState> {
+    return invoke('open_library', { path });
+  },
+
+  async closeLibrary(): Promise<StartupState> {
+    return invoke('close_library');
+  },
+
+  async removeLibrary(path: string, deleteFiles: boolean): Promise<void> {
+    return invoke('remove_library', { path, deleteFiles });
+  },
+
+  // Problems found by a full integrity and foreign key check, empty if the
+  // catalog is sound
+  async checkDatabaseIntegrity(): Promise<string[]> {
+    return invoke('check_database_integrity');
+  },
+
+  async recoverDatabase(action: RecoveryAction): Promise<StartupState> {
+    return invoke('recover_database', { action });
+  },
+
+  // Newest first
+  async listBackups(): Promise<BackupInfo[]> {
+    return invoke('list_backups');
+  },
+
+  async backUpNow(): Promise<BackupInfo> {
+    return invoke('back_up_now');
+  },
+
+  // Reopens the library with the catalog from `path`, one of `listBackups`
+  async restoreBackup(path: string): Promise<StartupState> {
+    return invoke('restore_backup', { path });
+  },
+
+  async getBackupPolicy(): Promise<BackupPolicy> {
+    return invoke('get_backup_policy');
+  },
+
+  async setBackupPolicy(policy: BackupPolicy): Promise<void> {
+    return invoke('set_backup_policy', { policy });
+  },
+
+  // With XMP sync on, edits queued while a drive was offline also create
+  // sidecars when it reconnects; otherwise only existing sidecars are updated
+  async getXmpSync(): Promise<boolean> {
+    return invoke('get_xmp_sync');
+  },
+
+  async setXmpSync(enabled: boolean): Promise<void> {
+    return invoke('set_xmp_sync', { enabled });
   },
 
+  // Database
+
   async insertPhoto(photo: Omit<DbPhoto, 'id'>): Promise<number> {
     return invoke('insert_photo', { photo });
   },
 
-  async getAllPhotos(): Promise<DbPhoto[]> {
-    return invoke('get_all_photos');
+  async getAllPhotos(stackTopsOnly?: boolean): Promise<DbPhoto[]> {
+    return invoke('get_all_photos', { stackTopsOnly });
   },
 
   async updateMetadata(
@@ -43,7 +504,9 @@ export const tauriCommands = {
     rating?: number,
     isFavorite?: boolean,
     tags?: string,
-    description?: string
+    description?: string,
+    flag?: PhotoFlag,
+    colorLabel?: ColorLabel | '' // '' clears the label
   ): Promise<void> {
     return invoke('update_metadata', {
       photoId,
@@ -51,18 +514,104 @@ export const tauriCommands = {
       isFavorite,
       tags,
       description,
+      flag,
+      colorLabel,
     });
   },
 
+  async batchUpdateMetadata(
+    photoIds: number[],
+    operations: MetadataOp[]
+  ): Promise<BatchReport> {
+    return invoke('batch_update_metadata', { photoIds, operations });
+  },
+
+  // Undo history
+  async undo(): Promise<EditAction | null> {
+    return invoke('undo');
+  },
+
+  async redo(): Promise<EditAction | null> {
+    return invoke('redo');
+  },
+
+  async getEditHistory(limit?: number): Promise<EditAction[]> {
+    return invoke('get_edit_history', { limit: limit ?? null });
+  },
+
+  async getEditHistoryRetention(): Promise<number> {
+    return invoke('get_edit_history_retention');
+  },
+
+  async setEditHistoryRetention(days: number): Promise<void> {
+    return invoke('set_edit_history_retention', { days });
+  },
+
   // Collections
-  async createCollection(name: string): Promise<number> {
-    return invoke('create_collection', { name });
+  async createCollection(name: string, parentId?: number): Promise<number> {
+    return invoke('create_collection', { name, parentId: parentId ?? null });
+  },
+
+  async getCollection(collectionId: number): Promise<DbCollection> {
+    return invoke('get_collection', { collectionId });
+  },
+
+  async renameCollection(collectionId: number, name: string): Promise<void> {
+    return invoke('rename_collection', { collectionId, name });
+  },
+
+  async setCollectionDescription(
+    collectionId: number,
+    description: string | null
+  ): Promise<void> {
+    return invoke('set_collection_description', { collectionId, description });
+  },
+
+  async setCollectionCover(
+    collectionId: number,
+    photoId: number | null
+  ): Promise<void> {
+    return invoke('set_collection_cover', { collectionId, photoId });
+  },
+
+  async createSmartCollection(name: string, rules: SmartRule): Promise<number> {
+    return invoke('create_smart_collection', { name, rules });
+  },
+
+  async setCollectionRules(
+    collectionId: number,
+    rules: SmartRule | null
+  ): Promise<void> {
+    return invoke('set_collection_rules', { collectionId, rules });
   },
 
   async getAllCollections(): Promise<DbCollection[]> {
     return invoke('get_all_collections');
   },
 
+  async moveCollection(
+    collectionId: number,
+    parentId: number | null,
+    position?: number
+  ): Promise<void> {
+    return invoke('move_collection', {
+      collectionId,
+      parentId,
+      position: position ?? null,
+    });
+  },
+
+  async reorderCollections(
+    parentId: number | null,
+    collectionIds: number[]
+  ): Promise<void> {
+    return invoke('reorder_collections', { parentId, collectionIds });
+  },
+
+  async deleteCollection(collectionId: number, cascade = false): Promise<void> {
+    return invoke('delete_collection', { collectionId, cascade });
+  },
+
   async addPhotoToCollection(
     photoId: number,
     collectionId: number
@@ -77,14 +626,193 @@ export const tauriCommands = {
     return invoke('remove_photo_from_collection', { photoId, collectionId });
   },
 
+  async batchAddToCollection(
+    collectionId: number,
+    photoIds: number[]
+  ): Promise<BatchReport> {
+    return invoke('batch_add_to_collection', { collectionId, photoIds });
+  },
+
+  async batchRemoveFromCollection(
+    collectionId: number,
+    photoIds: number[]
+  ): Promise<BatchReport> {
+    return invoke('batch_remove_from_collection', { collectionId, photoIds });
+  },
+
   async getPhotosInCollection(collectionId: number): Promise<DbPhoto[]> {
     return invoke('get_photos_in_collection', { collectionId });
   },
 
+  async reorderCollectionPhotos(
+    collectionId: number,
+    photoIds: number[]
+  ): Promise<void> {
+    return invoke('reorder_collection_photos', { collectionId, photoIds });
+  },
+
   async exportDatabaseToJson(): Promise<string> {
     return invoke('export_database_to_json');
   },
 
+  async importMetadataFromJson(json: string): Promise<MetadataImport> {
+    return invoke('import_metadata_from_json', { json });
+  },
+
+  async writeXmpSidecars(photoIds: number[]): Promise<BatchReport> {
+    return invoke('write_xmp_sidecars', { photoIds });
+  },
+
+  async findDuplicates(): Promise<DuplicateGroup[]> {
+    return invoke('find_duplicates');
+  },
+
+  async resolveDuplicates(
+    keepPhotoId: number,
+    duplicatePhotoIds: number[],
+    trashFiles: boolean
+  ): Promise<DuplicateResolution> {
+    return invoke('resolve_duplicates', {
+      keepPhotoId,
+      duplicatePhotoIds,
+      trashFiles,
+    });
+  },
+
+  // File operations
+  async movePhotos(
+    photoIds: number[],
+    destFolder: string
+  ): Promise<FileOperationResult[]> {
+    return invoke('move_photos', { photoIds, destFolder });
+  },
+
+  async copyPhotos(
+    photoIds: number[],
+    destFolder: string
+  ): Promise<FileOperationResult[]> {
+    return invoke('copy_photos', { photoIds, destFolder });
+  },
+
+  async renamePhoto(
+    photoId: number,
+    newName: string
+  ): Promise<FileOperationResult> {
+    return invoke('rename_photo', { photoId, newName });
+  },
+
+  async trashPhotos(photoIds: number[]): Promise<FileOperationResult[]> {
+    return invoke('trash_photos', { photoIds });
+  },
+
+  // Catalog only, or with `deleteFiles` also moving the files to the trash
+  async removePhotos(photoIds: number[], deleteFiles: boolean): Promise<FileOperationResult[]> {
+    return invoke('remove_photos', { photoIds, deleteFiles });
+  },
+
+  async runLibraryMaintenance(options?: MaintenanceOptions): Promise<MaintenanceReport> {
+    return invoke('run_library_maintenance', { options });
+  },
+
+  async getLibraryStats(scope?: StatsScope): Promise<LibraryStats> {
+    return invoke('get_library_stats', { scope });
+  },
+
+  async findMissingPhotos(): Promise<DbPhoto[]> {
+    return invoke('find_missing_photos');
+  },
+
+  async relocateRoot(
+    oldPrefix: string,
+    newPrefix: string,
+    sampleSize?: number,
+    verifyHash?: boolean
+  ): Promise<RelocationReport> {
+    return invoke('relocate_root', { oldPrefix, newPrefix, sampleSize, verifyHash });
+  },
+
+  async locateMissingPhotos(searchFolder: string, photoIds?: number[]): Promise<LocateReport> {
+    return invoke('locate_missing_photos', { searchFolder, photoIds });
+  },
+
+  async getLibraryRoots(): Promise<LibraryRoot[]> {
+    return invoke('get_library_roots');
+  },
+
+  async addLibraryRoot(path: string): Promise<number> {
+    return invoke('add_library_root', { path });
+  },
+
+  async removeLibraryRoot(rootId: number): Promise<void> {
+    return invoke('remove_library_root', { rootId });
+  },
+
+  // Call when drives may have been connected; also runs when the library opens
+  async refreshLibraryRoots(): Promise<RootStatus[]> {
+    return invoke('refresh_library_roots');
+  },
+
+  async getFolderTree(): Promise<FolderNode[]> {
+    return invoke('get_folder_tree');
+  },
+
+  async getPhotosInFolder(path: string, recursive: boolean): Promise<DbPhoto[]> {
+    return invoke('get_photos_in_folder', { path, recursive });
+  },
+
+  async previewRename(photoIds: number[], options: RenameOptions): Promise<RenamePreview[]> {
+    return invoke('preview_rename', { photoIds, options });
+  },
+
+  async applyRename(photoIds: number[], options: RenameOptions): Promise<FileOperationResult[]> {
+    return invoke('apply_rename', { photoIds, options });
+  },
+
+  async findSimilar(photoId: number, threshold: number): Promise<SimilarPhoto[]> {
+    return invoke('find_similar', { photoId, threshold });
+  },
+
+  async groupSimilarPhotos(threshold: number): Promise<DbPhoto[][]> {
+    return invoke('group_similar_photos', { threshold });
+  },
+
+  async findByColor(colors: string[], limit: number): Promise<ColorMatch[]> {
+    return invoke('find_by_color', { colors, limit });
+  },
+
+  async findVisuallySimilar(photoId: number, limit: number): Promise<ColorMatch[]> {
+    return invoke('find_visually_similar', { photoId, limit });
+  },
+
+  async extractMotionVideo(photoId: number): Promise<string> {
+    return invoke('extract_motion_video', { photoId });
+  },
+
+  // Stacks
+  async autoStackPhotos(maxGapSeconds?: number): Promise<DbStack[]> {
+    return invoke('auto_stack_photos', { maxGapSeconds });
+  },
+
+  async getAllStacks(): Promise<DbStack[]> {
+    return invoke('get_all_stacks');
+  },
+
+  async getStackPhotos(stackId: number): Promise<DbPhoto[]> {
+    return invoke('get_stack_photos', { stackId });
+  },
+
+  async setStackCollapsed(stackId: number, collapsed: boolean): Promise<void> {
+    return invoke('set_stack_collapsed', { stackId, collapsed });
+  },
+
+  async setStackTop(stackId: number, photoId: number): Promise<void> {
+    return invoke('set_stack_top', { stackId, photoId });
+  },
+
+  async unstack(stackId: number): Promise<void> {
+    return invoke('unstack', { stackId });
+  },
+
   // File system
   async selectFolder(): Promise<string | null> {
     return open({
@@ -93,36 +821,28 @@ export const tauriCommands = {
     });
   },
 
-  async scanImages(folderPath: string): Promise<
-    Array<{
-      path: string;
-      filename: string;
-      file_size: number;
-    }>
-  > {
-    return invoke('scan_images', { folderPath });
+  // Without options the rules stored for the folder's library root apply
+  async scanImages(folderPath: string, options?: ScanOptions): Promise<ScanResult> {
+    return invoke('scan_images', { folderPath, options });
+  },
+
+  async setScanOptions(rootId: number, options: ScanOptions): Promise<void> {
+    return invoke('set_scan_options', { rootId, options });
+  },
+
+  async ingestFromCard(options: IngestOptions): Promise<IngestReport> {
+    return invoke('ingest_from_card', { options });
   },
 
   // EXIF
-  async getExif(path: string): Promise<{
-    camera_make?: string;
-    camera_model?: string;
-    lens_model?: string;
-    focal_length?: number;
-    aperture?: number;
-    shutter_speed?: string;
-    iso?: number;
-    exposure_bias?: number;
-    flash?: string;
-    orientation?: number;
-    capture_date?: string;
-    gps_latitude?: number;
-    gps_longitude?: number;
-    gps_altitude?: number;
-  }> {
+  async getExif(path: string): Promise<ExifData> {
     return invoke('get_exif', { path });
   },
 
+  async getPhotoExif(photoId: number): Promise<ExifData | null> {
+    return invoke('get_photo_exif', { photoId });
+  },
+
   // Image processing
   async getImageDimensions(path: string): Promise<{
     width: number;